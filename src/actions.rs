@@ -0,0 +1,330 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, Error, Result};
+use cursive::Cursive;
+
+use crate::editor::open_organizer;
+use crate::filter::{Filter, FILTER_FIELDS};
+use crate::help;
+use crate::palette;
+use crate::playlist;
+use crate::settings;
+use crate::views::{
+    enqueue, library_selection, open_command_line, rescan, set_filter, SharedState,
+};
+
+/// An action as it's listed in the registry
+pub(crate) struct Command {
+    pub(crate) name: &'static str,
+    /// What goes after the name, for actions that take arguments
+    pub(crate) args: Option<&'static str>,
+    pub(crate) description: &'static str,
+}
+
+impl Command {
+    const fn new(name: &'static str, description: &'static str) -> Self {
+        Self {
+            name,
+            args: None,
+            description,
+        }
+    }
+
+    const fn with_args(name: &'static str, args: &'static str, description: &'static str) -> Self {
+        Self {
+            name,
+            args: Some(args),
+            description,
+        }
+    }
+
+    pub(crate) fn usage(&self) -> String {
+        match self.args {
+            Some(args) => format!("{} {args}", self.name),
+            None => self.name.to_owned(),
+        }
+    }
+}
+
+/// Every action, by the name it's bound to keys with, typed on the command line and picked from the palette
+pub(crate) const COMMANDS: [Command; 16] = [
+    Command::new("play-pause", "Play or pause"),
+    Command::new("next", "Skip to the next track"),
+    Command::new(
+        "previous",
+        "Restart the track, or go back to the previous one",
+    ),
+    Command::with_args(
+        "seek",
+        "<m:ss|+secs|-secs>",
+        "Jump to a position in the track",
+    ),
+    Command::with_args("vol", "<percent|+percent|-percent>", "Set the volume"),
+    Command::new(
+        "toggle-shuffle",
+        "Play the rest of the queue in a random order",
+    ),
+    Command::new("queue", "Queue the marked tracks, or the selected one"),
+    Command::with_args(
+        "filter",
+        "[field:]<text>...",
+        "Only show matching tracks in the library",
+    ),
+    Command::with_args("save-playlist", "<name>", "Save the queue as a playlist"),
+    Command::new("rescan", "Scan the library directories again"),
+    Command::new("organize", "Move and rename files according to their tags"),
+    Command::new("settings", "Choose the output device"),
+    Command::new("command-line", "Type a command"),
+    Command::new("command-palette", "Search for a command"),
+    Command::new("help", "Show the key bindings"),
+    Command::new("quit", "Quit minim"),
+];
+
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum Seek {
+    To(Duration),
+    Forward(Duration),
+    Back(Duration),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum Volume {
+    Set(u8),
+    Up(u8),
+    Down(u8),
+}
+
+/// Something the player can do, from a key binding, the command line or the command palette
+#[derive(Clone, Debug)]
+pub(crate) enum Action {
+    Quit,
+    PlayPause,
+    Next,
+    Previous,
+    ToggleShuffle,
+    Organize,
+    Settings,
+    CommandLine,
+    CommandPalette,
+    Help,
+    /// Queues the marked tracks in the library, or the one under the cursor
+    Queue,
+    Seek(Seek),
+    Volume(Volume),
+    Filter(Filter),
+    SavePlaylist(String),
+    Rescan,
+}
+
+impl Action {
+    /// The name used for the action in the config file and on the command line
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::PlayPause => "play-pause",
+            Action::Next => "next",
+            Action::Previous => "previous",
+            Action::ToggleShuffle => "toggle-shuffle",
+            Action::Organize => "organize",
+            Action::Settings => "settings",
+            Action::CommandLine => "command-line",
+            Action::CommandPalette => "command-palette",
+            Action::Help => "help",
+            Action::Queue => "queue",
+            Action::Seek(_) => "seek",
+            Action::Volume(_) => "vol",
+            Action::Filter(_) => "filter",
+            Action::SavePlaylist(_) => "save-playlist",
+            Action::Rescan => "rescan",
+        }
+    }
+
+    pub(crate) fn run(self, siv: &mut Cursive) {
+        let state = siv
+            .user_data::<SharedState>()
+            .expect("Missing state?")
+            .clone();
+
+        match self {
+            Action::Quit => siv.quit(),
+            Action::PlayPause => state.engine.play_pause(),
+            Action::Next => state.engine.next(),
+            Action::Previous => {
+                if let Err(e) = state.engine.previous() {
+                    state.toasts.error(e.to_string());
+                }
+            }
+            Action::ToggleShuffle => {
+                state.toasts.info(if state.engine.toggle_shuffle() {
+                    "Shuffle on"
+                } else {
+                    "Shuffle off"
+                });
+            }
+            Action::Organize => open_organizer(siv, state),
+            Action::Settings => settings::open(siv, &state),
+            Action::CommandLine => open_command_line(siv, ""),
+            Action::CommandPalette => palette::open(siv),
+            Action::Help => help::open(siv, &state),
+            Action::Queue => {
+                for track in library_selection(siv) {
+                    enqueue(siv, &state, track);
+                }
+            }
+            Action::Seek(seek) => {
+                let position = state.engine.position();
+                let target = match seek {
+                    Seek::To(t) => t,
+                    Seek::Forward(d) => position + d,
+                    Seek::Back(d) => position.saturating_sub(d),
+                };
+                if let Err(e) = state.engine.seek(target) {
+                    state.toasts.error(e.to_string());
+                }
+            }
+            Action::Volume(volume) => {
+                let current = (state.engine.volume() * 100.0).round() as u8;
+                let volume = match volume {
+                    Volume::Set(v) => v,
+                    Volume::Up(v) => current.saturating_add(v).min(100),
+                    Volume::Down(v) => current.saturating_sub(v),
+                };
+                state.engine.set_volume(volume as f32 / 100.0);
+                state.toasts.info(format!("Volume {volume}%"));
+            }
+            Action::Filter(filter) => set_filter(siv, &state, filter),
+            Action::SavePlaylist(name) => {
+                let queue = state.engine.queue();
+                match playlist::save(&name, &queue) {
+                    Ok(path) => state.toasts.info(format!("Saved {}", path.display())),
+                    Err(e) => state.toasts.error(format!("Couldn't save playlist: {e}")),
+                }
+            }
+            Action::Rescan => rescan(&state),
+        }
+    }
+}
+
+/// Parses a position like `90`, `1:30` or `1:02:03`
+fn parse_position(s: &str) -> Option<Duration> {
+    let mut secs = 0;
+    for part in s.split(':') {
+        secs = secs * 60 + part.parse::<u64>().ok()?;
+    }
+    Some(Duration::from_secs(secs))
+}
+
+fn parse_seek(args: &str) -> Option<Seek> {
+    if let Some(secs) = args.strip_prefix('+') {
+        Some(Seek::Forward(parse_position(secs)?))
+    } else if let Some(secs) = args.strip_prefix('-') {
+        Some(Seek::Back(parse_position(secs)?))
+    } else {
+        Some(Seek::To(parse_position(args)?))
+    }
+}
+
+fn parse_volume(args: &str) -> Option<Volume> {
+    let volume = if let Some(v) = args.strip_prefix('+') {
+        Volume::Up(v.parse().ok()?)
+    } else if let Some(v) = args.strip_prefix('-') {
+        Volume::Down(v.parse().ok()?)
+    } else {
+        Volume::Set(args.parse().ok()?)
+    };
+
+    match volume {
+        Volume::Set(v) | Volume::Up(v) | Volume::Down(v) if v > 100 => None,
+        _ => Some(volume),
+    }
+}
+
+impl FromStr for Action {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let (name, args) = s
+            .split_once(char::is_whitespace)
+            .map(|(name, args)| (name, args.trim()))
+            .unwrap_or((s, ""));
+
+        let Some(command) = COMMANDS.iter().find(|c| c.name == name) else {
+            let names: Vec<&str> = COMMANDS.iter().map(|c| c.name).collect();
+            return Err(anyhow!(
+                "Unknown command \"{name}\", expected one of {}",
+                names.join(", ")
+            ));
+        };
+        let usage = || anyhow!("Usage: {}", command.usage());
+        if command.args.is_none() && !args.is_empty() {
+            return Err(usage());
+        }
+
+        Ok(match name {
+            "quit" => Action::Quit,
+            "play-pause" => Action::PlayPause,
+            "next" => Action::Next,
+            "previous" => Action::Previous,
+            "toggle-shuffle" => Action::ToggleShuffle,
+            "organize" => Action::Organize,
+            "settings" => Action::Settings,
+            "command-line" => Action::CommandLine,
+            "command-palette" => Action::CommandPalette,
+            "help" => Action::Help,
+            "queue" => Action::Queue,
+            "seek" => Action::Seek(parse_seek(args).ok_or_else(usage)?),
+            "vol" => Action::Volume(parse_volume(args).ok_or_else(usage)?),
+            // An empty filter shows everything again
+            "filter" => Action::Filter(Filter::parse(args)?),
+            "save-playlist" if args.is_empty() => return Err(usage()),
+            "save-playlist" => Action::SavePlaylist(args.to_owned()),
+            "rescan" => Action::Rescan,
+            _ => unreachable!("Every command is handled"),
+        })
+    }
+}
+
+/// Completes the command or filter field being typed at the end of `line`. Returns the completed line and every
+/// candidate that matched.
+pub(crate) fn complete(line: &str) -> (String, Vec<String>) {
+    let (head, word) = match line.rfind(char::is_whitespace) {
+        Some(i) => line.split_at(i + 1),
+        None => ("", line),
+    };
+
+    let candidates: Vec<String> = if head.is_empty() {
+        COMMANDS
+            .iter()
+            .map(|c| format!("{} ", c.name))
+            .filter(|c| c.starts_with(word))
+            .collect()
+    } else if head.split_whitespace().next() == Some("filter") && !word.contains(':') {
+        FILTER_FIELDS
+            .iter()
+            .map(|(name, _)| format!("{name}:"))
+            .filter(|c| c.starts_with(word))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let Some(first) = candidates.first() else {
+        return (line.to_owned(), candidates);
+    };
+
+    // Extend the word as far as every candidate agrees
+    let mut common = first.clone();
+    for candidate in &candidates[1..] {
+        let len = common
+            .chars()
+            .zip(candidate.chars())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum();
+        common.truncate(len);
+    }
+
+    (format!("{head}{common}"), candidates)
+}
@@ -0,0 +1,468 @@
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use anyhow::{anyhow, Result};
+use base64::prelude::*;
+use cursive::backend::Backend;
+use cursive::event::Event;
+use cursive::theme::{Color, ColorPair, Effect};
+use cursive::Vec2;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, RgbImage};
+use lofty::picture::PictureType;
+use lofty::prelude::*;
+use lofty::probe::Probe;
+
+use crate::files::Track;
+
+/// Longest side of the thumbnails kept in the cache, in pixels
+const THUMBNAIL_SIZE: u32 = 512;
+
+/// Image files that are treated as cover art when they sit next to a track
+const SIDECAR_NAMES: [&str; 6] = [
+    "cover.jpg",
+    "cover.png",
+    "folder.jpg",
+    "folder.png",
+    "front.jpg",
+    "front.png",
+];
+
+/// Typical size of a terminal cell in pixels, for terminals that don't report it
+const DEFAULT_CELL_SIZE: (u32, u32) = (8, 16);
+
+/// Finds cover art for `track`, preferring the front cover embedded in its tags, then any embedded picture,
+/// then an image file in the same directory
+fn load_cover(track: &Track) -> Option<DynamicImage> {
+    if let Ok(tagged_file) = Probe::open(&track.path).and_then(|p| p.read()) {
+        let pictures = tagged_file.tags().iter().flat_map(|t| t.pictures());
+        let picture = pictures
+            .clone()
+            .find(|p| p.pic_type() == PictureType::CoverFront)
+            .or_else(|| pictures.clone().next());
+
+        if let Some(image) = picture.and_then(|p| image::load_from_memory(p.data()).ok()) {
+            return Some(image);
+        }
+    }
+
+    let dir = track.path.parent()?;
+    let entries: Vec<PathBuf> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .collect();
+    SIDECAR_NAMES.iter().find_map(|name| {
+        let path = entries.iter().find(|p| {
+            p.file_name()
+                .is_some_and(|f| f.to_string_lossy().eq_ignore_ascii_case(name))
+        })?;
+        image::open(path).ok()
+    })
+}
+
+fn thumbnail_path(track: &Track) -> Option<PathBuf> {
+    // Include the modification time, so retagged files get a fresh thumbnail
+    let modified = fs::metadata(&track.path).and_then(|m| m.modified()).ok()?;
+    let modified = modified.duration_since(UNIX_EPOCH).ok()?;
+    // The key has to stay the same across builds, so use a fixed hash rather than the standard library's
+    let mut hasher = md5::Context::new();
+    hasher.consume(track.path.as_os_str().as_encoded_bytes());
+    hasher.consume(modified.as_secs().to_le_bytes());
+    hasher.consume(modified.subsec_nanos().to_le_bytes());
+
+    let mut path = dirs::cache_dir()?;
+    path.push("minim");
+    path.push("art");
+    path.push(format!("{:x}.png", hasher.finalize()));
+    Some(path)
+}
+
+/// Loads a downscaled copy of the cover for `track`, decoding the full image only if it isn't cached on disk yet.
+/// This does file I/O and decoding, so it shouldn't be called from the UI thread.
+pub(crate) fn thumbnail(track: &Track) -> Option<Arc<RgbImage>> {
+    let cached = thumbnail_path(track);
+    if let Some(image) = cached.as_ref().and_then(|p| image::open(p).ok()) {
+        return Some(Arc::new(image.to_rgb8()));
+    }
+
+    let image = load_cover(track)?.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+    if let Some(path) = cached {
+        // The cache is only an optimization, so failing to write it is fine
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = image.save_with_format(&path, ImageFormat::Png);
+    }
+
+    Some(Arc::new(image.to_rgb8()))
+}
+
+/// The thumbnail of the cover for `track` as a file, so other programs can show it. Like [`thumbnail`], this
+/// shouldn't be called from the UI thread.
+#[cfg(any(feature = "mpris", feature = "notifications"))]
+pub(crate) fn thumbnail_file(track: &Track) -> Option<PathBuf> {
+    let path = thumbnail_path(track)?;
+    if !path.exists() {
+        thumbnail(track)?;
+    }
+    path.exists().then_some(path)
+}
+
+/// Size of a terminal cell in pixels
+fn cell_size() -> (u32, u32) {
+    use cursive::backends::crossterm::crossterm::terminal;
+
+    match terminal::window_size() {
+        Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => (
+            (size.width / size.columns) as u32,
+            (size.height / size.rows) as u32,
+        ),
+        _ => DEFAULT_CELL_SIZE,
+    }
+}
+
+/// Scales `image` to fit in `cols` x `rows` cells, given how many pixels each cell covers
+pub(crate) fn fit(image: &RgbImage, cols: usize, rows: usize, cell: (u32, u32)) -> RgbImage {
+    let width = (cols as u32 * cell.0).max(1);
+    let height = (rows as u32 * cell.1).max(1);
+    DynamicImage::ImageRgb8(image.clone())
+        .resize(width, height, FilterType::Triangle)
+        .to_rgb8()
+}
+
+/// How many cells an image takes up once it's scaled to fit in `cols` x `rows`, keeping its aspect ratio
+pub(crate) fn fitted_cells(image: &RgbImage, cols: usize, rows: usize) -> (usize, usize) {
+    let (cell_width, cell_height) = cell_size();
+    let width = (cols as u32 * cell_width) as f64;
+    let height = (rows as u32 * cell_height) as f64;
+    let scale = (width / image.width() as f64).min(height / image.height() as f64);
+
+    let cols = ((image.width() as f64 * scale) / cell_width as f64).floor() as usize;
+    let rows = ((image.height() as f64 * scale) / cell_height as f64).floor() as usize;
+    (cols.max(1), rows.max(1))
+}
+
+/// Ways of showing images in a terminal, from best to worst
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Protocol {
+    Kitty,
+    Iterm2,
+    Sixel,
+    /// Upper half block characters, with the foreground and background colors each covering half a cell.
+    /// Works anywhere with true color, and is drawn through cursive like any other text.
+    HalfBlocks,
+}
+
+impl Protocol {
+    /// Guesses the best protocol from the environment. `MINIM_IMAGE_PROTOCOL` can be set to `kitty`, `iterm2`,
+    /// `sixel` or `halfblocks` to override it.
+    pub(crate) fn detect() -> Self {
+        if let Ok(protocol) = env::var("MINIM_IMAGE_PROTOCOL") {
+            match protocol.to_lowercase().as_str() {
+                "kitty" => return Self::Kitty,
+                "iterm2" => return Self::Iterm2,
+                "sixel" => return Self::Sixel,
+                "halfblocks" => return Self::HalfBlocks,
+                _ => (),
+            }
+        }
+
+        // Multiplexers don't pass graphics through reliably
+        if env::var_os("TMUX").is_some() || env::var("TERM").is_ok_and(|t| t.starts_with("screen"))
+        {
+            return Self::HalfBlocks;
+        }
+
+        let term = env::var("TERM").unwrap_or_default();
+        let program = env::var("TERM_PROGRAM").unwrap_or_default();
+        if env::var_os("KITTY_WINDOW_ID").is_some()
+            || term.contains("kitty")
+            || program == "ghostty"
+        {
+            Self::Kitty
+        } else if program == "iTerm.app" || program == "WezTerm" {
+            Self::Iterm2
+        } else if term.contains("sixel") || term.starts_with("foot") || term == "mlterm" {
+            Self::Sixel
+        } else {
+            Self::HalfBlocks
+        }
+    }
+}
+
+/// Where the image should go on screen, in cells
+#[derive(Clone, Debug)]
+pub(crate) struct Placement {
+    pub(crate) x: usize,
+    pub(crate) y: usize,
+    pub(crate) cols: usize,
+    pub(crate) rows: usize,
+    /// Identifies the image, so we only re-send it when it changes
+    pub(crate) id: PathBuf,
+    pub(crate) image: Arc<RgbImage>,
+}
+
+impl PartialEq for Placement {
+    // Comparing pixels every frame would be wasteful, and the id already tells images apart
+    fn eq(&self, other: &Self) -> bool {
+        (self.x, self.y, self.cols, self.rows, &self.id)
+            == (other.x, other.y, other.cols, other.rows, &other.id)
+    }
+}
+
+/// Wraps the terminal backend so a frame can be written out in full. Cursive only sends the cells that changed
+/// since the last frame, which leaves sixel and iTerm2 images behind when they're no longer wanted.
+pub(crate) struct Repainting {
+    inner: Box<dyn Backend>,
+    /// While set, every cell is written out again
+    pub(crate) full: Arc<AtomicBool>,
+}
+
+impl Repainting {
+    pub(crate) fn new(inner: Box<dyn Backend>) -> Self {
+        Self {
+            inner,
+            full: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl Backend for Repainting {
+    fn poll_event(&mut self) -> Option<Event> {
+        self.inner.poll_event()
+    }
+
+    fn set_title(&mut self, title: String) {
+        self.inner.set_title(title)
+    }
+
+    fn refresh(&mut self) {
+        self.inner.refresh()
+    }
+
+    fn has_colors(&self) -> bool {
+        self.inner.has_colors()
+    }
+
+    fn screen_size(&self) -> Vec2 {
+        self.inner.screen_size()
+    }
+
+    fn move_to(&self, pos: Vec2) {
+        self.inner.move_to(pos)
+    }
+
+    fn print(&self, text: &str) {
+        self.inner.print(text)
+    }
+
+    fn clear(&self, color: Color) {
+        self.inner.clear(color)
+    }
+
+    fn set_color(&self, colors: ColorPair) -> ColorPair {
+        self.inner.set_color(colors)
+    }
+
+    fn set_effect(&self, effect: Effect) {
+        self.inner.set_effect(effect)
+    }
+
+    fn unset_effect(&self, effect: Effect) {
+        self.inner.unset_effect(effect)
+    }
+
+    fn is_persistent(&self) -> bool {
+        self.inner.is_persistent() && !self.full.load(Ordering::Relaxed)
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+}
+
+/// Draws images with terminal graphics protocols, outside of cursive. Views request a placement while drawing,
+/// and [`Overlay::flush`] writes it out after cursive is done with the frame.
+pub(crate) struct Overlay {
+    pub(crate) protocol: Protocol,
+    frame: u64,
+    requested: Option<(u64, Placement)>,
+    shown: Option<Placement>,
+}
+
+impl Overlay {
+    pub(crate) fn new(protocol: Protocol) -> Self {
+        Self {
+            protocol,
+            frame: 0,
+            requested: None,
+            shown: None,
+        }
+    }
+
+    /// Called at the start of every frame. Placements not requested again during the frame are removed.
+    pub(crate) fn begin_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    pub(crate) fn request(&mut self, placement: Placement) {
+        self.requested = Some((self.frame, placement));
+    }
+
+    /// Writes out the requested image, or clears the old one if nothing was requested this frame. `covered`
+    /// should be true when a popup is over the screen, since the image would be drawn on top of it. Returns true
+    /// when the old image can only go by repainting the whole screen, in which case nothing was written, and this
+    /// should be called again once the screen has been repainted.
+    pub(crate) fn flush(&mut self, covered: bool) -> Result<bool> {
+        let wanted = match &self.requested {
+            Some((frame, placement)) if *frame == self.frame && !covered => Some(placement.clone()),
+            _ => None,
+        };
+        if wanted == self.shown {
+            return Ok(false);
+        }
+
+        let mut out = String::new();
+        match self.protocol {
+            Protocol::Kitty if self.shown.is_some() => out.push_str("\x1b_Ga=d,d=A,q=2\x1b\\"),
+            // Sixel and iTerm2 images are drawn into the cells, and only go once the cells are written over, which
+            // cursive won't do for cells that haven't changed. See `Repainting`.
+            Protocol::Sixel | Protocol::Iterm2 if self.shown.is_some() => {
+                self.shown = None;
+                return Ok(true);
+            }
+            _ => (),
+        }
+
+        if let Some(p) = &wanted {
+            write!(out, "\x1b7\x1b[{};{}H", p.y + 1, p.x + 1)?;
+            match self.protocol {
+                Protocol::Kitty => out.push_str(&kitty(&p.image, p.cols, p.rows)?),
+                Protocol::Iterm2 => out.push_str(&iterm2(&p.image, p.cols, p.rows)?),
+                Protocol::Sixel => {
+                    let image = fit(&p.image, p.cols, p.rows, cell_size());
+                    out.push_str(&sixel(&image));
+                }
+                Protocol::HalfBlocks => (),
+            }
+            out.push_str("\x1b8");
+        }
+
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(out.as_bytes())?;
+        stdout.flush()?;
+
+        self.shown = wanted;
+        Ok(false)
+    }
+}
+
+fn png_bytes(image: &RgbImage) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?;
+    Ok(bytes)
+}
+
+/// Encodes an image for the kitty graphics protocol, scaled by the terminal to `cols` x `rows` cells
+/// See <https://sw.kovidgoyal.net/kitty/graphics-protocol/>
+fn kitty(image: &RgbImage, cols: usize, rows: usize) -> Result<String> {
+    let data = BASE64_STANDARD.encode(png_bytes(image)?);
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(4096).collect();
+
+    let mut out = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = if i + 1 < chunks.len() { 1 } else { 0 };
+        let chunk = std::str::from_utf8(chunk).map_err(|_| anyhow!("Base64 should be ASCII"))?;
+        if i == 0 {
+            write!(
+                out,
+                "\x1b_Ga=T,f=100,q=2,C=1,c={cols},r={rows},m={more};{chunk}\x1b\\"
+            )?;
+        } else {
+            write!(out, "\x1b_Gm={more};{chunk}\x1b\\")?;
+        }
+    }
+
+    Ok(out)
+}
+
+/// Encodes an image as an iTerm2 inline image, which WezTerm also understands
+/// See <https://iterm2.com/documentation-images.html>
+fn iterm2(image: &RgbImage, cols: usize, rows: usize) -> Result<String> {
+    let bytes = png_bytes(image)?;
+    Ok(format!(
+        "\x1b]1337;File=inline=1;size={};width={cols};height={rows};preserveAspectRatio=1:{}\x07",
+        bytes.len(),
+        BASE64_STANDARD.encode(&bytes)
+    ))
+}
+
+/// Encodes an image as sixels, using a fixed 6x6x6 color cube as the palette
+fn sixel(image: &RgbImage) -> String {
+    let level = |c: u8| (c as u32 * 5 + 127) / 255;
+    let index = |p: &image::Rgb<u8>| (level(p[0]) * 36 + level(p[1]) * 6 + level(p[2])) as usize;
+
+    let (width, height) = image.dimensions();
+    let mut out = format!("\x1bPq\"1;1;{width};{height}");
+    for i in 0..216 {
+        let (r, g, b) = (i / 36, (i / 6) % 6, i % 6);
+        let _ = write!(out, "#{i};2;{};{};{}", r * 20, g * 20, b * 20);
+    }
+
+    for band in (0..height).step_by(6) {
+        let mut used = [false; 216];
+        for y in band..(band + 6).min(height) {
+            for x in 0..width {
+                used[index(image.get_pixel(x, y))] = true;
+            }
+        }
+
+        for (color, _) in used.iter().enumerate().filter(|(_, used)| **used) {
+            let _ = write!(out, "#{color}");
+            let mut run: Option<(char, usize)> = None;
+            for x in 0..width {
+                let mut bits = 0;
+                for dy in 0..6 {
+                    let y = band + dy;
+                    if y < height && index(image.get_pixel(x, y)) == color {
+                        bits |= 1 << dy;
+                    }
+                }
+                let c = char::from(63 + bits);
+
+                run = match run {
+                    Some((prev, n)) if prev == c => Some((prev, n + 1)),
+                    Some(prev) => {
+                        push_sixel_run(&mut out, prev);
+                        Some((c, 1))
+                    }
+                    None => Some((c, 1)),
+                };
+            }
+            if let Some(run) = run {
+                push_sixel_run(&mut out, run);
+            }
+            // Return to the start of the band for the next color
+            out.push('$');
+        }
+        out.push('-');
+    }
+
+    out.push_str("\x1b\\");
+    out
+}
+
+fn push_sixel_run(out: &mut String, (c, n): (char, usize)) {
+    if n > 3 {
+        let _ = write!(out, "!{n}{c}");
+    } else {
+        out.extend(std::iter::repeat_n(c, n));
+    }
+}
@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+
+use crate::files::EditableField;
+
+/// The fields of a single track, as read by [`crate::files::Track::read_editable_fields`]
+pub(crate) type Fields = HashMap<EditableField, String>;
+
+/// Fields that hold free-form text, as opposed to numbers or lyrics
+const TEXT_FIELDS: [EditableField; 5] = [
+    EditableField::Title,
+    EditableField::Artist,
+    EditableField::Album,
+    EditableField::AlbumArtist,
+    EditableField::Genre,
+];
+
+/// An operation applied to every track in a selection
+#[derive(Clone, Debug)]
+pub(crate) enum BatchOp {
+    /// Set `field` to the same value on every track
+    Set { field: EditableField, value: String },
+    /// Number tracks 1, 2, 3, ... in the order they're given
+    AutoNumber,
+    /// Capitalize the first letter of every word in the text fields
+    TitleCase,
+    /// Trim and collapse runs of whitespace in the text fields
+    StripWhitespace,
+    /// Parse tags out of the file path using a pattern like `%artist%/%album%/%track% - %title%`
+    FromPath { pattern: String },
+}
+
+impl BatchOp {
+    /// Computes the changed fields for each track, without touching any files. `tracks` pairs each track's
+    /// path with its current fields. Tracks that wouldn't change get an empty map.
+    pub(crate) fn plan(&self, tracks: &[(&Path, Fields)]) -> Result<Vec<Fields>> {
+        let pattern = match self {
+            BatchOp::FromPath { pattern } => Some(PathPattern::parse(pattern)?),
+            _ => None,
+        };
+
+        tracks
+            .iter()
+            .enumerate()
+            .map(|(i, (path, current))| {
+                let new: Fields = match self {
+                    BatchOp::Set { field, value } => [(*field, value.clone())].into(),
+                    BatchOp::AutoNumber => {
+                        [(EditableField::TrackNumber, (i + 1).to_string())].into()
+                    }
+                    BatchOp::TitleCase => map_text_fields(current, title_case),
+                    BatchOp::StripWhitespace => map_text_fields(current, strip_whitespace),
+                    BatchOp::FromPath { .. } => pattern
+                        .as_ref()
+                        .expect("Pattern is parsed above")
+                        .apply(path)?,
+                };
+
+                Ok(new
+                    .into_iter()
+                    .filter(|(field, value)| {
+                        current.get(field).map_or(!value.is_empty(), |c| c != value)
+                    })
+                    .collect())
+            })
+            .collect()
+    }
+}
+
+fn map_text_fields<F>(fields: &Fields, f: F) -> Fields
+where
+    F: Fn(&str) -> String,
+{
+    TEXT_FIELDS
+        .iter()
+        .filter_map(|field| Some((*field, f(fields.get(field)?))))
+        .collect()
+}
+
+fn title_case(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut start_of_word = true;
+    for c in s.chars() {
+        if start_of_word {
+            out.extend(c.to_uppercase());
+        } else {
+            out.extend(c.to_lowercase());
+        }
+        // Apostrophes don't start a new word, so "don't" doesn't become "Don'T"
+        start_of_word = c.is_whitespace() || (c.is_ascii_punctuation() && c != '\'');
+    }
+
+    out
+}
+
+fn strip_whitespace(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Literal(String),
+    /// `None` for `%ignore%`, which matches text without storing it anywhere
+    Field(Option<EditableField>),
+}
+
+/// A pattern for parsing tags out of a path, matched against the end of the path with the extension removed
+#[derive(Clone, Debug)]
+struct PathPattern {
+    tokens: Vec<Token>,
+}
+
+impl PathPattern {
+    fn parse(pattern: &str) -> Result<Self> {
+        let mut tokens = Vec::new();
+        let mut rest = pattern;
+
+        while let Some(start) = rest.find('%') {
+            if start > 0 {
+                tokens.push(Token::Literal(rest[..start].to_owned()));
+            }
+
+            let after = &rest[start + 1..];
+            let end = after
+                .find('%')
+                .ok_or(anyhow!("Unclosed placeholder in \"{pattern}\""))?;
+            let name = &after[..end];
+            let field = match name.to_lowercase().as_str() {
+                "title" => Some(EditableField::Title),
+                "artist" => Some(EditableField::Artist),
+                "album" => Some(EditableField::Album),
+                "albumartist" => Some(EditableField::AlbumArtist),
+                "year" => Some(EditableField::Year),
+                "genre" => Some(EditableField::Genre),
+                "track" => Some(EditableField::TrackNumber),
+                "disc" => Some(EditableField::DiscNumber),
+                "ignore" => None,
+                _ => return Err(anyhow!("Unknown placeholder %{name}%")),
+            };
+
+            if matches!(tokens.last(), Some(Token::Field(_))) {
+                return Err(anyhow!(
+                    "Placeholders must be separated by some text, e.g. \"%track% - %title%\""
+                ));
+            }
+            tokens.push(Token::Field(field));
+            rest = &after[end + 1..];
+        }
+
+        if !rest.is_empty() {
+            tokens.push(Token::Literal(rest.to_owned()));
+        }
+
+        if !tokens.iter().any(|t| matches!(t, Token::Field(_))) {
+            return Err(anyhow!("Pattern doesn't contain any placeholders"));
+        }
+
+        Ok(Self { tokens })
+    }
+
+    /// The number of path components the pattern spans
+    fn components(&self) -> usize {
+        1 + self
+            .tokens
+            .iter()
+            .map(|t| match t {
+                Token::Literal(l) => l.matches('/').count(),
+                Token::Field(_) => 0,
+            })
+            .sum::<usize>()
+    }
+
+    fn apply(&self, path: &Path) -> Result<Fields> {
+        let stem = path.with_extension("");
+        let components: Vec<_> = stem
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect();
+        let n = self.components();
+        if components.len() < n {
+            return Err(anyhow!("{} is too short for the pattern", path.display()));
+        }
+        let subject = components[components.len() - n..].join("/");
+
+        let mut fields = Fields::new();
+        let mut rest = subject.as_str();
+        let mut tokens = self.tokens.iter().peekable();
+        while let Some(token) = tokens.next() {
+            match token {
+                Token::Literal(l) => {
+                    rest = rest
+                        .strip_prefix(l.as_str())
+                        .ok_or(anyhow!("{} doesn't match the pattern", path.display()))?;
+                }
+                Token::Field(field) => {
+                    // Fields stop at the next literal, and can never span directories
+                    let end = match tokens.peek() {
+                        Some(Token::Literal(l)) => rest
+                            .find(l.as_str())
+                            .ok_or(anyhow!("{} doesn't match the pattern", path.display()))?,
+                        _ => rest.len(),
+                    };
+                    let value = &rest[..end];
+                    if value.is_empty() || value.contains('/') {
+                        return Err(anyhow!("{} doesn't match the pattern", path.display()));
+                    }
+
+                    if let Some(field) = field {
+                        let mut value = value.trim().to_owned();
+                        if field.is_numeric() {
+                            // Normalize "01" to "1", so it compares equal to what's in the tag
+                            let n: u32 = value.parse().map_err(|_| {
+                                anyhow!("{} isn't a number in {}", field.label(), path.display())
+                            })?;
+                            value = n.to_string();
+                        }
+                        fields.insert(*field, value);
+                    }
+                    rest = &rest[end..];
+                }
+            }
+        }
+
+        if !rest.is_empty() {
+            return Err(anyhow!("{} doesn't match the pattern", path.display()));
+        }
+
+        Ok(fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(pattern: &str) -> Vec<Token> {
+        PathPattern::parse(pattern).unwrap().tokens
+    }
+
+    fn apply(pattern: &str, path: &str) -> Result<Fields> {
+        PathPattern::parse(pattern).unwrap().apply(Path::new(path))
+    }
+
+    #[test]
+    fn patterns_split_into_literals_and_fields() {
+        assert_eq!(
+            parse("%artist%/%album%/%track% - %title%"),
+            [
+                Token::Field(Some(EditableField::Artist)),
+                Token::Literal("/".to_owned()),
+                Token::Field(Some(EditableField::Album)),
+                Token::Literal("/".to_owned()),
+                Token::Field(Some(EditableField::TrackNumber)),
+                Token::Literal(" - ".to_owned()),
+                Token::Field(Some(EditableField::Title)),
+            ]
+        );
+        assert_eq!(
+            parse("(%IGNORE%) %Title%!"),
+            [
+                Token::Literal("(".to_owned()),
+                Token::Field(None),
+                Token::Literal(") ".to_owned()),
+                Token::Field(Some(EditableField::Title)),
+                Token::Literal("!".to_owned()),
+            ]
+        );
+        assert_eq!(
+            PathPattern::parse("%artist%/%album%/%title%")
+                .unwrap()
+                .components(),
+            3
+        );
+    }
+
+    #[test]
+    fn bad_patterns_are_refused() {
+        for pattern in [
+            "",
+            "no placeholders",
+            "%title",
+            "%nonsense%",
+            "%track%%title%",
+        ] {
+            assert!(PathPattern::parse(pattern).is_err(), "{pattern:?}");
+        }
+    }
+
+    #[test]
+    fn paths_fill_in_fields() {
+        let fields = apply(
+            "%artist%/%album%/%track% - %title%",
+            "/music/Someone/Something/01 - The Song.flac",
+        )
+        .unwrap();
+        assert_eq!(
+            fields,
+            Fields::from([
+                (EditableField::Artist, "Someone".to_owned()),
+                (EditableField::Album, "Something".to_owned()),
+                (EditableField::TrackNumber, "1".to_owned()),
+                (EditableField::Title, "The Song".to_owned()),
+            ])
+        );
+
+        let fields = apply("%ignore% - %title%", "/music/03 - Other.mp3").unwrap();
+        assert_eq!(
+            fields,
+            Fields::from([(EditableField::Title, "Other".to_owned())])
+        );
+    }
+
+    #[test]
+    fn paths_that_dont_match_are_errors() {
+        let pattern = "%artist%/%track% - %title%";
+        // No separator, too few directories, and a track that isn't a number
+        for path in [
+            "/music/Someone/The Song.mp3",
+            "The Song.mp3",
+            "/music/Someone/One - The Song.mp3",
+        ] {
+            assert!(apply(pattern, path).is_err(), "{path}");
+        }
+        assert!(apply("%title% (live)", "/music/The Song (studio).mp3").is_err());
+        assert!(apply("%track%. %title%", "/music/. The Song.mp3").is_err());
+    }
+
+    #[test]
+    fn title_case_capitalizes_words() {
+        assert_eq!(
+            title_case("the long and winding road"),
+            "The Long And Winding Road"
+        );
+        assert_eq!(title_case("ABBA GOLD"), "Abba Gold");
+        assert_eq!(title_case("don't stop me now"), "Don't Stop Me Now");
+        assert_eq!(title_case("rock-n-roll (live)"), "Rock-N-Roll (Live)");
+        assert_eq!(title_case("  spaced  out "), "  Spaced  Out ");
+        assert_eq!(title_case("élan vital"), "Élan Vital");
+        assert_eq!(title_case(""), "");
+    }
+
+    #[test]
+    fn whitespace_is_collapsed() {
+        assert_eq!(strip_whitespace("  a \t b\n\nc  "), "a b c");
+        assert_eq!(strip_whitespace("   "), "");
+    }
+}
@@ -0,0 +1,88 @@
+use std::iter;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use cursive::{CbSink, Cursive};
+
+use crate::engine::{Event, Listeners, Playback};
+use crate::files::Track;
+use crate::watcher::LibraryChange;
+
+/// Everything besides key presses that the interface reacts to
+#[derive(Clone, Debug)]
+pub(crate) enum Message {
+    /// Something the engine did
+    Player(Event),
+    Library(LibraryEvent),
+    Ui(UiCommand),
+}
+
+/// Something that happened to the library in the background
+#[derive(Clone, Debug)]
+pub(crate) enum LibraryEvent {
+    /// The watcher saw files change
+    Changed(Vec<LibraryChange>),
+    /// A rescan finished and found these tracks
+    Scanned(Vec<Track>),
+    /// Lyrics for a track finished loading
+    LyricsLoaded,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum UiCommand {
+    /// Something on screen changed without a view being told, e.g. a toast expired or cover art finished loading
+    Redraw,
+    /// Time passed while playing, so the position shown should move on
+    Tick,
+}
+
+/// Carries [`Message`]s from wherever they happen to the interface
+pub(crate) type Bus = Listeners<Message>;
+
+/// Passes the engine's events on to `bus`
+pub(crate) fn forward(events: mpsc::Receiver<Event>, bus: Bus) {
+    thread::spawn(move || {
+        for event in events {
+            bus.notify(Message::Player(event));
+        }
+    });
+}
+
+/// Hands what arrives on `bus` to `handle` on the UI thread, which redraws the screen afterwards. Bursts go in one
+/// go, e.g. a whole album being queued. While something's playing, a [`UiCommand::Tick`] is sent every `interval`
+/// as well; otherwise the screen is left alone until something happens.
+pub(crate) fn pump(
+    bus: &Bus,
+    engine: Arc<dyn Playback>,
+    interval: Duration,
+    cb_sink: CbSink,
+    handle: fn(&mut Cursive, Vec<Message>),
+) {
+    let messages = bus.subscribe();
+    thread::spawn(move || loop {
+        let playing = !engine.is_paused() && engine.current().is_some();
+        let first = if playing {
+            match messages.recv_timeout(interval) {
+                Ok(message) => message,
+                Err(RecvTimeoutError::Timeout) => Message::Ui(UiCommand::Tick),
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        } else {
+            match messages.recv() {
+                Ok(message) => message,
+                Err(_) => return,
+            }
+        };
+
+        let burst: Vec<Message> = iter::once(first).chain(messages.try_iter()).collect();
+        if cb_sink
+            .send(Box::new(move |siv| handle(siv, burst)))
+            .is_err()
+        {
+            // The interface has shut down
+            return;
+        }
+    });
+}
@@ -4,6 +4,18 @@ use anyhow::Result;
 
 use crate::files::Track;
 
+/// Path to the library cache, creating its directory if it doesn't exist yet
+pub(crate) fn cache_path() -> Result<PathBuf> {
+    let mut path = dirs::cache_dir().expect("Missing cache dir?");
+    path.push("minim");
+    if !fs::exists(&path)? {
+        fs::create_dir_all(&path)?;
+    }
+    path.push("library.csv");
+
+    Ok(path)
+}
+
 pub(crate) fn read_cache(path: &PathBuf) -> Result<Vec<Track>> {
     let file = fs::File::open(path)?;
     let mut reader = csv::Reader::from_reader(file);
@@ -0,0 +1,253 @@
+use std::cmp::Ordering;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Error, Result};
+use serde::Deserialize;
+
+use crate::files::CachedField;
+use crate::keymap::Keymap;
+use crate::library::LibraryRoot;
+use crate::scrobble::Scrobbling;
+use crate::views::TABS;
+
+/// Columns that can be shown in the library table, by the names used in the config file
+const COLUMNS: [(&str, CachedField); 5] = [
+    ("artist", CachedField::Artist),
+    ("title", CachedField::Title),
+    ("album", CachedField::Album),
+    ("rating", CachedField::Rating),
+    ("length", CachedField::Duration),
+];
+
+fn parse_column(name: &str) -> Result<CachedField> {
+    COLUMNS
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, c)| *c)
+        .ok_or_else(|| {
+            let names: Vec<&str> = COLUMNS.iter().map(|(n, _)| *n).collect();
+            anyhow!(
+                "Unknown column \"{name}\", expected one of {}",
+                names.join(", ")
+            )
+        })
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawColumn {
+    field: String,
+    title: Option<String>,
+    width: Option<usize>,
+    width_percent: Option<usize>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum Width {
+    Cells(usize),
+    Percent(usize),
+}
+
+/// A column of the library table
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "RawColumn")]
+pub(crate) struct Column {
+    pub(crate) field: CachedField,
+    pub(crate) title: String,
+    pub(crate) width: Option<Width>,
+}
+
+impl TryFrom<RawColumn> for Column {
+    type Error = Error;
+
+    fn try_from(raw: RawColumn) -> Result<Self> {
+        let field = parse_column(&raw.field)?;
+        let width = match (raw.width, raw.width_percent) {
+            (Some(_), Some(_)) => {
+                return Err(anyhow!(
+                    "Column \"{}\" can't have both width and width_percent",
+                    raw.field
+                ))
+            }
+            (Some(0), _) | (_, Some(0)) => {
+                return Err(anyhow!("Column \"{}\" can't be 0 wide", raw.field))
+            }
+            (_, Some(p)) if p > 100 => {
+                return Err(anyhow!("Column \"{}\" can't be wider than 100%", raw.field))
+            }
+            (Some(w), None) => Some(Width::Cells(w)),
+            (None, Some(p)) => Some(Width::Percent(p)),
+            (None, None) => None,
+        };
+
+        Ok(Self {
+            field,
+            title: raw
+                .title
+                .unwrap_or_else(|| Self::default_title(field).to_owned()),
+            width,
+        })
+    }
+}
+
+impl Column {
+    fn default_title(field: CachedField) -> &'static str {
+        match field {
+            CachedField::Artist => "Artist",
+            CachedField::Title => "Title",
+            CachedField::Album => "Album",
+            CachedField::Rating => "Rating",
+            CachedField::Duration => "Length",
+            _ => unreachable!("Only columns from COLUMNS are created"),
+        }
+    }
+
+    fn new(field: CachedField, width: Option<Width>) -> Self {
+        Self {
+            field,
+            title: Self::default_title(field).to_owned(),
+            width,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSort {
+    column: String,
+    #[serde(default)]
+    descending: bool,
+}
+
+/// How the library table is sorted at startup
+#[derive(Copy, Clone, Debug, Deserialize)]
+#[serde(try_from = "RawSort")]
+pub(crate) struct Sort {
+    pub(crate) field: CachedField,
+    pub(crate) order: Ordering,
+}
+
+impl TryFrom<RawSort> for Sort {
+    type Error = Error;
+
+    fn try_from(raw: RawSort) -> Result<Self> {
+        Ok(Self {
+            field: parse_column(&raw.column)?,
+            order: if raw.descending {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            },
+        })
+    }
+}
+
+/// Settings from `~/.config/minim/config.toml`. Everything is optional.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    /// Directories to build the library from, instead of the music folder
+    pub(crate) roots: Vec<LibraryRoot>,
+    /// Extra key bindings, on top of the defaults
+    pub(crate) keys: Keymap,
+    pub(crate) columns: Vec<Column>,
+    pub(crate) sort: Option<Sort>,
+    pub(crate) startup_tab: String,
+    /// How often the screen is redrawn while playing, which matters for the progress of the current track. The
+    /// rest of the time it only changes when something happens.
+    pub(crate) fps: u32,
+    /// Where to accept MPD clients, e.g. `127.0.0.1:6600`. Nothing listens unless it's set.
+    pub(crate) mpd_address: Option<SocketAddr>,
+    /// The output device to play on, by the name `minim devices` lists. The default device is used otherwise.
+    pub(crate) device: Option<String>,
+    /// Whether to show a desktop notification as each track starts
+    pub(crate) notifications: bool,
+    /// Services to submit listens to, under `[scrobble.listenbrainz]` and `[scrobble.lastfm]`
+    pub(crate) scrobble: Scrobbling,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            roots: Vec::new(),
+            keys: Keymap::default(),
+            columns: vec![
+                Column::new(CachedField::Artist, None),
+                Column::new(CachedField::Title, None),
+                Column::new(CachedField::Rating, Some(Width::Cells(9))),
+                Column::new(CachedField::Duration, Some(Width::Cells(10))),
+            ],
+            sort: None,
+            startup_tab: TABS[0].to_owned(),
+            fps: 10,
+            mpd_address: None,
+            device: None,
+            notifications: false,
+            scrobble: Scrobbling::default(),
+        }
+    }
+}
+
+impl Config {
+    fn path() -> Result<PathBuf> {
+        let mut path = dirs::config_dir().ok_or(anyhow!("Error getting config dir path"))?;
+        path.push("minim");
+        path.push("config.toml");
+        Ok(path)
+    }
+
+    /// Loads the config file, falling back to the defaults if there isn't one
+    pub(crate) fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if !fs::exists(&path)? {
+            return Ok(Self::default());
+        }
+
+        let text = fs::read_to_string(&path)?;
+        let mut config: Self = toml::from_str(&text)
+            .with_context(|| format!("Invalid config file {}", path.display()))?;
+        config
+            .validate()
+            .with_context(|| format!("Invalid config file {}", path.display()))?;
+        Ok(config)
+    }
+
+    /// Checks settings that depend on each other, which parsing alone can't
+    fn validate(&mut self) -> Result<()> {
+        if self.columns.is_empty() {
+            return Err(anyhow!("columns can't be empty"));
+        }
+        for (i, column) in self.columns.iter().enumerate() {
+            if self.columns[..i].iter().any(|c| c.field == column.field) {
+                return Err(anyhow!("Column \"{}\" is listed twice", column.title));
+            }
+        }
+
+        if let Some(sort) = self.sort {
+            if !self.columns.iter().any(|c| c.field == sort.field) {
+                return Err(anyhow!(
+                    "Can't sort by the {} column since it isn't shown",
+                    Column::default_title(sort.field).to_lowercase()
+                ));
+            }
+        }
+
+        self.startup_tab = TABS
+            .iter()
+            .find(|t| t.eq_ignore_ascii_case(&self.startup_tab))
+            .ok_or(anyhow!(
+                "Unknown startup_tab \"{}\", expected one of {}",
+                self.startup_tab,
+                TABS.join(", ")
+            ))?
+            .to_string();
+
+        if !(1..=60).contains(&self.fps) {
+            return Err(anyhow!("fps must be between 1 and 60, not {}", self.fps));
+        }
+
+        Ok(())
+    }
+}
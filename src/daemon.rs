@@ -0,0 +1,101 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+
+use crate::config::Config;
+use crate::engine::{Core, Engine, Event, Playback};
+use crate::ipc;
+use crate::mpd;
+use crate::player::Args;
+
+/// The ways of controlling a player from outside. They stop when this is dropped.
+pub(crate) struct Servers {
+    pub(crate) ipc: Option<ipc::Server>,
+    _mpd: Option<mpd::Server>,
+}
+
+impl Servers {
+    /// Starts the control socket, the MPD server if it has an address, and MPRIS if it's built in. Servers that
+    /// can't start are passed to `report` and left out.
+    pub(crate) fn start(
+        core: &Core,
+        mpd_address: Option<SocketAddr>,
+        report: impl Fn(String),
+    ) -> Self {
+        let ipc = ipc::serve(core.clone())
+            .map_err(|e| report(format!("{e:#}")))
+            .ok();
+
+        let mpd = mpd_address.and_then(|address| {
+            mpd::start(address, core.clone())
+                .map_err(|e| report(format!("{e:#}")))
+                .ok()
+        });
+
+        // Without a session bus there are no media keys to answer anyway
+        #[cfg(feature = "mpris")]
+        if let Err(e) = crate::mpris::start(core.clone()) {
+            report(format!("Couldn't start MPRIS: {e}"));
+        }
+
+        Self { ipc, _mpd: mpd }
+    }
+}
+
+/// Shows a desktop notification as each track starts, if minim is built with them
+pub(crate) fn notify_track_changes(core: &Core) -> Result<()> {
+    #[cfg(feature = "notifications")]
+    {
+        let notifier = crate::notifications::Notifier::connect()
+            .map_err(|e| anyhow!("Couldn't show notifications: {e}"))?;
+        notifier.follow(core.engine.subscribe());
+        Ok(())
+    }
+    #[cfg(not(feature = "notifications"))]
+    {
+        let _ = core;
+        Err(anyhow!(
+            "Notifications need minim built with `--features notifications`"
+        ))
+    }
+}
+
+/// Plays without a terminal until a client asks it to quit. Running `minim` meanwhile attaches to it.
+pub fn run_daemon(args: &Args) -> Result<()> {
+    let config = Config::load()?;
+    let library = Arc::new(args.library(&config)?);
+    let tracks = args.load_tracks(&library)?;
+    let (engine, samples) = Engine::idle();
+    // Held so the output isn't closed
+    let _output = args.output(&config, samples, |e| eprintln!("Error: {e}"))?;
+    let core = Core::new(engine, library, tracks);
+
+    let servers = Servers::start(&core, config.mpd_address, |e| eprintln!("Error: {e}"));
+    config
+        .scrobble
+        .start(&*core.engine, |e| eprintln!("Error: {e}"))?;
+    if config.notifications {
+        if let Err(e) = notify_track_changes(&core) {
+            eprintln!("Error: {e:#}");
+        }
+    }
+    if servers.ipc.is_none() {
+        return Err(anyhow!(
+            "Can't run in the background without a control socket"
+        ));
+    }
+    eprintln!(
+        "minim is playing in the background. Attach with `minim`, stop with `minim ctl quit`."
+    );
+
+    for event in core.engine.subscribe() {
+        match event {
+            Event::Error(message) => eprintln!("Error: {message}"),
+            Event::Quit => break,
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
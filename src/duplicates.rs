@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::Result;
+use rodio::{Decoder, Source};
+
+use crate::files::{CachedField, Track};
+
+/// How many seconds of audio the fingerprint covers
+const FINGERPRINT_SECS: usize = 60;
+
+/// Fingerprint frames per second
+const FRAMES_PER_SEC: usize = 10;
+
+/// How far apart two fingerprints may be shifted when comparing them, in frames. This absorbs differences in
+/// leading silence between rips.
+const MAX_LAG: usize = 2 * FRAMES_PER_SEC;
+
+/// Minimum correlation for two fingerprints to be considered the same recording
+const SIMILARITY_THRESHOLD: f32 = 0.8;
+
+/// Lowercases and strips punctuation, so "Don't Stop (Remastered)" and "dont stop remastered" match
+fn normalize(s: &str) -> String {
+    s.chars()
+        .filter_map(|c| {
+            if c.is_alphanumeric() {
+                Some(c.to_lowercase().collect::<String>())
+            } else if c.is_whitespace() {
+                Some(" ".to_owned())
+            } else {
+                None
+            }
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Groups tracks with the same normalized artist and title whose durations are within `tolerance` seconds of
+/// each other. Only groups with more than one track are returned.
+pub(crate) fn find_duplicates(tracks: &[Track], tolerance: u64) -> Vec<Vec<Track>> {
+    let mut by_name: HashMap<(String, String), Vec<&Track>> = HashMap::new();
+    for track in tracks {
+        let key = (
+            normalize(&track.cached_field_string(CachedField::Artist)),
+            normalize(&track.cached_field_string(CachedField::Title)),
+        );
+        by_name.entry(key).or_default().push(track);
+    }
+
+    let mut groups = Vec::new();
+    for mut candidates in by_name.into_values() {
+        if candidates.len() < 2 {
+            continue;
+        }
+
+        // Each group spans at most the tolerance from its shortest track, so a run of tracks that are each a
+        // little longer than the last doesn't chain into one group
+        candidates.sort_by_key(|t| t.duration());
+        let mut group: Vec<Track> = Vec::new();
+        for track in candidates {
+            if let Some(first) = group.first() {
+                if track.duration() - first.duration() > tolerance {
+                    if group.len() > 1 {
+                        groups.push(group);
+                    }
+                    group = Vec::new();
+                }
+            }
+            group.push(track.clone());
+        }
+        if group.len() > 1 {
+            groups.push(group);
+        }
+    }
+
+    groups.sort_by_key(|g| {
+        (
+            g[0].cached_field_string(CachedField::Artist).to_lowercase(),
+            g[0].cached_field_string(CachedField::Title).to_lowercase(),
+        )
+    });
+    groups
+}
+
+/// Narrows down `groups` by comparing acoustic fingerprints, splitting each group into tracks that actually
+/// sound alike. Tracks that can't be decoded are dropped.
+pub(crate) fn confirm_with_fingerprints(groups: Vec<Vec<Track>>) -> Vec<Vec<Track>> {
+    let mut confirmed = Vec::new();
+
+    for group in groups {
+        let fingerprinted: Vec<(Track, Vec<f32>)> = group
+            .into_iter()
+            .filter_map(|t| {
+                let fp = fingerprint(&t.path).ok()?;
+                Some((t, fp))
+            })
+            .collect();
+
+        // Greedily cluster around the first unclaimed track
+        let mut claimed = vec![false; fingerprinted.len()];
+        for i in 0..fingerprinted.len() {
+            if claimed[i] {
+                continue;
+            }
+            claimed[i] = true;
+
+            let mut cluster = vec![fingerprinted[i].0.clone()];
+            for j in i + 1..fingerprinted.len() {
+                if !claimed[j]
+                    && similarity(&fingerprinted[i].1, &fingerprinted[j].1) >= SIMILARITY_THRESHOLD
+                {
+                    claimed[j] = true;
+                    cluster.push(fingerprinted[j].0.clone());
+                }
+            }
+
+            if cluster.len() > 1 {
+                confirmed.push(cluster);
+            }
+        }
+    }
+
+    confirmed
+}
+
+/// Computes a coarse fingerprint from the decoded audio: the change in loudness between consecutive
+/// 1/[`FRAMES_PER_SEC`] second frames. This survives re-encoding and format changes, but not remixes.
+fn fingerprint(path: &Path) -> Result<Vec<f32>> {
+    let file = fs::File::open(path)?;
+    let decoder = Decoder::new(BufReader::new(file))?;
+    let frame_len =
+        (decoder.sample_rate() as usize * decoder.channels() as usize / FRAMES_PER_SEC).max(1);
+
+    let mut energies = Vec::with_capacity(FINGERPRINT_SECS * FRAMES_PER_SEC);
+    let mut sum = 0.0;
+    let mut count = 0;
+    for sample in decoder.convert_samples::<f32>() {
+        sum += sample * sample;
+        count += 1;
+        if count == frame_len {
+            energies.push((sum / count as f32).sqrt().ln_1p());
+            sum = 0.0;
+            count = 0;
+            if energies.len() == FINGERPRINT_SECS * FRAMES_PER_SEC {
+                break;
+            }
+        }
+    }
+
+    Ok(energies.windows(2).map(|w| w[1] - w[0]).collect())
+}
+
+/// The best Pearson correlation between two fingerprints over small time shifts, between -1.0 and 1.0
+fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    let correlation = |a: &[f32], b: &[f32]| {
+        let n = a.len().min(b.len());
+        if n < FRAMES_PER_SEC {
+            return 0.0;
+        }
+        let (a, b) = (&a[..n], &b[..n]);
+        let mean_a = a.iter().sum::<f32>() / n as f32;
+        let mean_b = b.iter().sum::<f32>() / n as f32;
+
+        let mut cov = 0.0;
+        let mut var_a = 0.0;
+        let mut var_b = 0.0;
+        for (x, y) in a.iter().zip(b) {
+            cov += (x - mean_a) * (y - mean_b);
+            var_a += (x - mean_a) * (x - mean_a);
+            var_b += (y - mean_b) * (y - mean_b);
+        }
+
+        if var_a == 0.0 || var_b == 0.0 {
+            // Two stretches of silence are the same, but silence isn't the same as anything else
+            return if var_a == var_b { 1.0 } else { 0.0 };
+        }
+        cov / (var_a.sqrt() * var_b.sqrt())
+    };
+
+    (0..=MAX_LAG)
+        .flat_map(|lag| {
+            [
+                correlation(a.get(lag..).unwrap_or_default(), b),
+                correlation(a, b.get(lag..).unwrap_or_default()),
+            ]
+        })
+        .fold(f32::MIN, f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(path: &str, duration: u64) -> Track {
+        serde_json::from_value(serde_json::json!({
+            "path": path,
+            "title": "Song",
+            "artist": "Someone",
+            "album": null,
+            "duration": duration,
+        }))
+        .unwrap()
+    }
+
+    fn paths(groups: &[Vec<Track>]) -> Vec<Vec<&str>> {
+        groups
+            .iter()
+            .map(|g| g.iter().map(|t| t.path.to_str().unwrap()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn groups_dont_chain_past_the_tolerance() {
+        let tracks = [
+            track("a", 200),
+            track("b", 202),
+            track("c", 204),
+            track("d", 206),
+        ];
+        // b is within 2s of both a and c, but a and c are 4s apart
+        assert_eq!(
+            paths(&find_duplicates(&tracks, 2)),
+            [vec!["a", "b"], vec!["c", "d"]]
+        );
+        assert_eq!(
+            paths(&find_duplicates(&tracks, 6)),
+            [vec!["a", "b", "c", "d"]]
+        );
+    }
+}
@@ -0,0 +1,405 @@
+use std::collections::HashMap;
+
+use cursive::{
+    view::{Nameable, Resizable, Scrollable},
+    views::{Dialog, EditView, LinearLayout, ListView, SelectView, TextArea, TextView},
+    Cursive,
+};
+
+use crate::batch::{BatchOp, Fields};
+use crate::files::{CachedField, EditableField, Track};
+use crate::organize::{self, Plan, Template, DEFAULT_TEMPLATE};
+use crate::views::{relocate_track, update_track, SharedState};
+
+fn field_view_name(field: EditableField) -> String {
+    format!("tag_editor_{field:?}")
+}
+
+/// Finds the value shared by every track for each field. Fields that differ between tracks are left out.
+fn common_values(fields: &[HashMap<EditableField, String>]) -> HashMap<EditableField, String> {
+    let mut common = HashMap::new();
+    for field in EditableField::ALL {
+        let mut values = fields
+            .iter()
+            .map(|f| f.get(&field).cloned().unwrap_or_default());
+        let Some(first) = values.next() else {
+            continue;
+        };
+
+        if values.all(|v| v == first) {
+            common.insert(field, first);
+        }
+    }
+
+    common
+}
+
+fn read_field(siv: &mut Cursive, field: EditableField) -> String {
+    let name = field_view_name(field);
+    match field {
+        EditableField::Lyrics => siv
+            .call_on_name(&name, |v: &mut TextArea| v.get_content().to_owned())
+            .unwrap_or_default(),
+        _ => siv
+            .call_on_name(&name, |v: &mut EditView| v.get_content().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+/// Opens a dialog for editing the tags of `tracks`. When editing several tracks at once, fields that differ
+/// between them start out empty and are only written if the user enters something.
+pub(crate) fn open_tag_editor(siv: &mut Cursive, state: SharedState, tracks: Vec<Track>) {
+    let fields: Result<Vec<_>, _> = tracks.iter().map(|t| t.read_editable_fields()).collect();
+    let fields = match fields {
+        Ok(f) => f,
+        Err(e) => {
+            siv.add_layer(Dialog::info(format!("Couldn't read tags: {e}")));
+            return;
+        }
+    };
+    let initial = common_values(&fields);
+
+    let mut list = ListView::new();
+    for field in EditableField::ALL {
+        let value = initial.get(&field).cloned().unwrap_or_default();
+        match field {
+            EditableField::Lyrics => list.add_child(
+                field.label(),
+                TextArea::new()
+                    .content(value)
+                    .with_name(field_view_name(field))
+                    .min_height(6),
+            ),
+            _ => list.add_child(
+                field.label(),
+                EditView::new()
+                    .content(value)
+                    .with_name(field_view_name(field)),
+            ),
+        }
+    }
+
+    let title = match tracks.as_slice() {
+        [track] => format!("Edit {}", track.cached_field_string(CachedField::Title)),
+        _ => format!("Edit {} tracks", tracks.len()),
+    };
+
+    let dialog = Dialog::around(list.scrollable())
+        .title(title)
+        .button("Save", move |siv| {
+            let changes: HashMap<EditableField, String> = EditableField::ALL
+                .into_iter()
+                .map(|field| (field, read_field(siv, field)))
+                .filter(|(field, value)| match initial.get(field) {
+                    Some(initial) => initial != value,
+                    // Leaving a mixed field empty keeps each track's own value
+                    None => !value.is_empty(),
+                })
+                .collect();
+
+            siv.pop_layer();
+            if changes.is_empty() {
+                return;
+            }
+
+            let edits = tracks
+                .iter()
+                .map(|t| (t.clone(), changes.clone()))
+                .collect();
+            save_changes(siv, &state, edits);
+        })
+        .dismiss_button("Cancel")
+        .min_width(60);
+
+    siv.add_layer(dialog);
+}
+
+/// Writes each track's changes to disk and updates the views, reporting any files that couldn't be saved
+fn save_changes(siv: &mut Cursive, state: &SharedState, edits: Vec<(Track, Fields)>) {
+    let mut errors = Vec::new();
+    for (mut track, changes) in edits {
+        if changes.is_empty() {
+            continue;
+        }
+
+        match track.write_tag_fields(&changes) {
+            Ok(()) => {
+                state.lyrics.invalidate(&track.path);
+                update_track(siv, state, &track);
+            }
+            Err(e) => errors.push(format!("{}: {e}", track.path.display())),
+        }
+    }
+
+    if !errors.is_empty() {
+        siv.add_layer(Dialog::info(errors.join("\n")).title("Some tags couldn't be saved"));
+    }
+}
+
+/// Opens a menu of operations to run over `tracks`, which should be in the order they're displayed in
+pub(crate) fn open_batch_menu(siv: &mut Cursive, state: SharedState, tracks: Vec<Track>) {
+    let mut menu = SelectView::new();
+    menu.add_item("Set a field on all tracks...", 0);
+    menu.add_item("Number tracks in current order", 1);
+    menu.add_item("Title case", 2);
+    menu.add_item("Strip whitespace", 3);
+    menu.add_item("Tags from file path...", 4);
+
+    menu.set_on_submit(move |siv, choice: &usize| {
+        siv.pop_layer();
+        let state = state.clone();
+        let tracks = tracks.clone();
+        match choice {
+            0 => open_set_field_dialog(siv, state, tracks),
+            1 => preview_batch(siv, state, tracks, BatchOp::AutoNumber),
+            2 => preview_batch(siv, state, tracks, BatchOp::TitleCase),
+            3 => preview_batch(siv, state, tracks, BatchOp::StripWhitespace),
+            _ => open_path_pattern_dialog(siv, state, tracks),
+        }
+    });
+
+    siv.add_layer(
+        Dialog::around(menu)
+            .title("Batch edit")
+            .dismiss_button("Cancel"),
+    );
+}
+
+fn open_set_field_dialog(siv: &mut Cursive, state: SharedState, tracks: Vec<Track>) {
+    let mut fields = SelectView::new().popup();
+    for field in EditableField::ALL {
+        if field != EditableField::Lyrics {
+            fields.add_item(field.label(), field);
+        }
+    }
+
+    let layout = LinearLayout::vertical()
+        .child(fields.with_name("batch_field"))
+        .child(EditView::new().with_name("batch_value"));
+
+    let dialog = Dialog::around(layout)
+        .title("Set a field")
+        .button("Preview", move |siv| {
+            let field = siv
+                .call_on_name("batch_field", |v: &mut SelectView<EditableField>| {
+                    v.selection()
+                })
+                .flatten()
+                .map(|f| *f)
+                .unwrap_or(EditableField::Title);
+            let value = siv
+                .call_on_name("batch_value", |v: &mut EditView| {
+                    v.get_content().to_string()
+                })
+                .unwrap_or_default();
+
+            siv.pop_layer();
+            preview_batch(
+                siv,
+                state.clone(),
+                tracks.clone(),
+                BatchOp::Set { field, value },
+            );
+        })
+        .dismiss_button("Cancel")
+        .min_width(40);
+
+    siv.add_layer(dialog);
+}
+
+fn open_path_pattern_dialog(siv: &mut Cursive, state: SharedState, tracks: Vec<Track>) {
+    let layout = LinearLayout::vertical()
+        .child(TextView::new(
+            "Placeholders: %title% %artist% %album% %albumartist% %year% %genre% %track% %disc% %ignore%",
+        ))
+        .child(
+            EditView::new()
+                .content("%artist%/%album%/%track% - %title%")
+                .with_name("batch_pattern"),
+        );
+
+    let dialog = Dialog::around(layout)
+        .title("Tags from file path")
+        .button("Preview", move |siv| {
+            let pattern = siv
+                .call_on_name("batch_pattern", |v: &mut EditView| {
+                    v.get_content().to_string()
+                })
+                .unwrap_or_default();
+
+            siv.pop_layer();
+            preview_batch(
+                siv,
+                state.clone(),
+                tracks.clone(),
+                BatchOp::FromPath { pattern },
+            );
+        })
+        .dismiss_button("Cancel")
+        .min_width(60);
+
+    siv.add_layer(dialog);
+}
+
+/// Shows what `op` would change on each track, and applies it if the user confirms
+fn preview_batch(siv: &mut Cursive, state: SharedState, tracks: Vec<Track>, op: BatchOp) {
+    let current: Result<Vec<_>, _> = tracks
+        .iter()
+        .map(|t| t.read_editable_fields().map(|f| (t.path.as_path(), f)))
+        .collect();
+    let plan = current.and_then(|current| {
+        let plan = op.plan(&current)?;
+        Ok(current
+            .into_iter()
+            .map(|(_, f)| f)
+            .zip(plan)
+            .collect::<Vec<_>>())
+    });
+    let plan = match plan {
+        Ok(p) => p,
+        Err(e) => {
+            siv.add_layer(Dialog::info(format!("Couldn't apply batch edit: {e}")));
+            return;
+        }
+    };
+
+    let mut diff = String::new();
+    for (track, (current, changes)) in tracks.iter().zip(&plan) {
+        if changes.is_empty() {
+            continue;
+        }
+
+        diff.push_str(&format!("{}\n", track.path.display()));
+        for field in EditableField::ALL {
+            if let Some(new) = changes.get(&field) {
+                let old = current.get(&field).map(String::as_str).unwrap_or_default();
+                diff.push_str(&format!("  {}: \"{old}\" -> \"{new}\"\n", field.label()));
+            }
+        }
+    }
+
+    if diff.is_empty() {
+        siv.add_layer(Dialog::info("Nothing to change"));
+        return;
+    }
+
+    let edits: Vec<(Track, Fields)> = tracks
+        .into_iter()
+        .zip(plan)
+        .map(|(track, (_, changes))| (track, changes))
+        .collect();
+
+    let dialog = Dialog::around(TextView::new(diff).scrollable())
+        .title("Preview")
+        .button("Apply", move |siv| {
+            siv.pop_layer();
+            save_changes(siv, &state, edits.clone());
+        })
+        .dismiss_button("Cancel")
+        .max_height(30);
+
+    siv.add_layer(dialog);
+}
+
+/// Asks for a template, then previews and performs moving the whole library into place
+pub(crate) fn open_organizer(siv: &mut Cursive, state: SharedState) {
+    let layout = LinearLayout::vertical()
+        .child(TextView::new(
+            "Placeholders: {title} {artist} {album} {albumartist} {year} {genre} {track} {disc} {ext}\n\
+             Add a width to pad numbers, e.g. {track:02}",
+        ))
+        .child(
+            EditView::new()
+                .content(DEFAULT_TEMPLATE)
+                .with_name("organize_template"),
+        );
+
+    let dialog = Dialog::around(layout)
+        .title("Organize library")
+        .button("Preview", move |siv| {
+            let template = siv
+                .call_on_name("organize_template", |v: &mut EditView| {
+                    v.get_content().to_string()
+                })
+                .unwrap_or_default();
+
+            siv.pop_layer();
+            preview_organize(siv, state.clone(), &template);
+        })
+        .dismiss_button("Cancel")
+        .min_width(70);
+
+    siv.add_layer(dialog);
+}
+
+/// Works out the moves in the background, since every track's tags have to be read, then shows them for approval
+fn preview_organize(siv: &mut Cursive, state: SharedState, template: &str) {
+    let template = match Template::parse(template) {
+        Ok(template) => template,
+        Err(e) => {
+            siv.add_layer(Dialog::info(format!("Couldn't organize library: {e}")));
+            return;
+        }
+    };
+    state.toasts.info("Working out where files go...");
+
+    let tracks = state.tracks.lock().unwrap().clone();
+    let cb_sink = siv.cb_sink().clone();
+    std::thread::spawn(move || {
+        let plan = organize::plan(&template, &tracks);
+        let _ = cb_sink.send(Box::new(move |siv| show_organize_plan(siv, state, plan)));
+    });
+}
+
+fn show_organize_plan(siv: &mut Cursive, state: SharedState, plan: Plan) {
+    let skipped: Vec<String> = plan
+        .skipped
+        .iter()
+        .map(|(path, e)| format!("{}\n  can't be moved: {e:#}", path.display()))
+        .collect();
+    let moves = plan.moves;
+
+    if moves.is_empty() {
+        let message = if skipped.is_empty() {
+            "Everything is already in place".to_owned()
+        } else {
+            skipped.join("\n")
+        };
+        siv.add_layer(Dialog::info(message));
+        return;
+    }
+
+    let preview: Vec<String> = moves
+        .iter()
+        .map(|m| {
+            let from = m.from.strip_prefix(&m.root).unwrap_or(&m.from);
+            let to = m.to.strip_prefix(&m.root).unwrap_or(&m.to);
+            format!("{}\n  -> {}", from.display(), to.display())
+        })
+        .chain(skipped)
+        .collect();
+
+    let dialog = Dialog::around(TextView::new(preview.join("\n")).scrollable())
+        .title(format!("Move {} files", moves.len()))
+        .button("Apply", move |siv| {
+            siv.pop_layer();
+
+            let mut errors = Vec::new();
+            for m in &moves {
+                match organize::apply(m) {
+                    Ok(()) => relocate_track(siv, &state, &m.from, &m.to),
+                    Err(e) => errors.push(format!("{}: {e}", m.from.display())),
+                }
+            }
+
+            if !errors.is_empty() {
+                siv.add_layer(
+                    Dialog::info(errors.join("\n")).title("Some files couldn't be moved"),
+                );
+            }
+        })
+        .dismiss_button("Cancel")
+        .max_height(30);
+
+    siv.add_layer(dialog);
+}
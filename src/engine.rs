@@ -0,0 +1,582 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use rodio::decoder::DecoderError;
+use rodio::queue::SourcesQueueOutput;
+use rodio::source::Empty;
+use rodio::{OutputStreamHandle, Sink};
+
+use crate::files::{CachedField, Track, WrappedSource};
+use crate::library::Library;
+
+/// How far into a track going back restarts it instead of playing the previous one
+const PREVIOUS_RESTARTS_AFTER: Duration = Duration::from_secs(3);
+
+/// How often [`Event::PositionChanged`] is sent while playing
+const POSITION_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Why a track couldn't be queued
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum QueueError {
+    /// The file is gone, e.g. it was deleted or its drive isn't mounted
+    Missing,
+    Unreadable(io::Error),
+    Undecodable(DecoderError),
+    /// A player this one is attached to turned it down
+    Refused(String),
+}
+
+impl fmt::Display for QueueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueueError::Missing => write!(f, "the file is missing"),
+            QueueError::Unreadable(e) => write!(f, "{e}"),
+            QueueError::Undecodable(e) => write!(f, "{e}"),
+            QueueError::Refused(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for QueueError {}
+
+/// Something that happened while playing the queue
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Event {
+    /// The track at `index` in the queue began playing
+    TrackStarted { index: usize, track: Track },
+    /// The track at `index` in the queue played to its end or was skipped. `heard` is how long it actually
+    /// played for, without pauses or the parts seeked past.
+    TrackEnded {
+        index: usize,
+        track: Track,
+        heard: Duration,
+    },
+    /// How far into the current track playback is. Sent every second while playing, and after seeking.
+    PositionChanged(Duration),
+    /// Tracks were added to the queue, it was reordered, or queued tracks were edited
+    QueueChanged,
+    /// Playback was paused, resumed or stopped, or the volume or shuffle changed
+    StateChanged,
+    /// A queued track couldn't be played and was skipped
+    Error(String),
+    /// Someone asked the player to quit, e.g. an MPRIS client
+    Quit,
+}
+
+/// Boils `events` down to a signal for every change to what the player reports about itself, rather than time just
+/// passing. It's for servers that send clients a fresh status instead of the events themselves.
+pub(crate) fn status_changes(events: mpsc::Receiver<Event>) -> mpsc::Receiver<()> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for event in events {
+            if !matches!(event, Event::PositionChanged(_)) && tx.send(()).is_err() {
+                return;
+            }
+        }
+    });
+    rx
+}
+
+/// Hands events to subscribers. Sending can happen on the audio thread, so subscribers get a channel to wait on
+/// rather than a callback.
+pub(crate) struct Listeners<T>(Arc<Mutex<Vec<mpsc::Sender<T>>>>);
+
+impl<T> Clone for Listeners<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Default for Listeners<T> {
+    fn default() -> Self {
+        Self(Arc::default())
+    }
+}
+
+impl<T: Clone> Listeners<T> {
+    pub(crate) fn subscribe(&self) -> mpsc::Receiver<T> {
+        let (tx, rx) = mpsc::channel();
+        self.0.lock().unwrap().push(tx);
+        rx
+    }
+
+    pub(crate) fn notify(&self, event: T) {
+        // Subscribers that went away are dropped
+        self.0
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.send(event.clone()).is_ok());
+    }
+}
+
+/// Plays a queue of tracks. [`Engine`] plays them on this machine; the terminal interface drives the same calls
+/// when it's attached to a player running elsewhere.
+pub trait Playback: Send + Sync {
+    fn queue(&self) -> Vec<Track>;
+    /// Where the current track is in the queue. It's past the end once the queue has run out.
+    fn queue_index(&self) -> usize;
+    /// How far into the current track playback is
+    fn position(&self) -> Duration;
+    fn is_paused(&self) -> bool;
+    /// Between 0 and 1
+    fn volume(&self) -> f32;
+    fn is_shuffled(&self) -> bool;
+
+    fn play(&self);
+    fn pause(&self);
+    fn next(&self);
+    /// Goes back to the start of the current track, or to the previous one if it only just started
+    fn previous(&self) -> Result<()>;
+    fn seek(&self, position: Duration) -> Result<()>;
+    /// Between 0 and 1
+    fn set_volume(&self, volume: f32);
+    /// Plays the tracks that haven't been played yet in a random order, or puts them back in the order they were
+    /// queued. Returns whether the queue is shuffled now.
+    fn toggle_shuffle(&self) -> bool;
+    /// Pauses and goes back to the start of the current track
+    fn stop(&self);
+    /// Plays the queue from `index` on
+    fn play_from(&self, index: usize) -> Result<()>;
+    /// Adds `track` to the end of the queue
+    fn enqueue(&self, track: &Track) -> Result<(), QueueError>;
+    /// Replaces the queue's copies of `track` (matched by path), e.g. after its tags were edited
+    fn update(&self, track: &Track);
+    /// Points the queue's copies of the track at `from` to its new location at `to`
+    fn relocate(&self, from: &Path, to: &Path);
+    /// A channel that receives every [`Event`] from now on
+    fn subscribe(&self) -> mpsc::Receiver<Event>;
+
+    /// The track that's playing or paused
+    fn current(&self) -> Option<Track> {
+        self.queue().get(self.queue_index()).cloned()
+    }
+
+    fn play_pause(&self) {
+        if self.is_paused() {
+            self.play();
+        } else {
+            self.pause();
+        }
+    }
+}
+
+/// Plays the queue through rodio
+pub struct Engine {
+    sink: Arc<Sink>,
+    queue: Arc<Mutex<Vec<Track>>>,
+    queue_index: Arc<Mutex<usize>>,
+    /// The queue in the order it was queued in, while it's shuffled
+    unshuffled: Mutex<Option<Vec<Track>>>,
+    /// Held while the sink is being refilled or appended to, so the sink and the queue can't get out of step when
+    /// several clients change them at once. The audio thread never takes it.
+    control: Mutex<()>,
+    /// How long the current track has been heard for, in milliseconds. Kept up to date by the playing source.
+    heard: Arc<AtomicU64>,
+    /// Set by [`Playback::stop`] until playback is started again
+    stopped: AtomicBool,
+    listeners: Listeners<Event>,
+}
+
+impl Engine {
+    /// Plays on `handle`'s output. The `OutputStream` it came from has to be kept alive as long as the engine.
+    pub fn new(handle: &OutputStreamHandle) -> Result<Self> {
+        let sink = Sink::try_new(handle).map_err(|e| anyhow!("Error creating new sink: {e}"))?;
+        Ok(Self::with_sink(sink))
+    }
+
+    /// An engine that isn't connected to any output. Playback advances as samples are pulled from the returned
+    /// source, e.g. by a test or a front-end that does its own output. Skipping, clearing and seeking wait for the
+    /// output to catch up, so it has to be pulled from another thread.
+    pub fn idle() -> (Self, SourcesQueueOutput<f32>) {
+        let (sink, output) = Sink::new_idle();
+        (Self::with_sink(sink), output)
+    }
+
+    fn with_sink(sink: Sink) -> Self {
+        let engine = Self {
+            sink: Arc::new(sink),
+            queue: Arc::default(),
+            queue_index: Arc::default(),
+            unshuffled: Mutex::default(),
+            control: Mutex::default(),
+            heard: Arc::default(),
+            stopped: AtomicBool::default(),
+            listeners: Listeners::default(),
+        };
+        tick(Arc::downgrade(&engine.sink), engine.listeners.clone());
+        engine
+    }
+
+    /// Tells whether the engine has anything to play, e.g. for [`Pace::Unlimited`](crate::output::Pace::Unlimited)
+    pub fn busy(&self) -> Busy {
+        Busy(Arc::downgrade(&self.sink))
+    }
+
+    /// Whether playback was stopped and hasn't been started since. Stopping pauses at the start of the track, so
+    /// the engine is paused as well.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Asks whoever runs the engine to shut down by sending [`Event::Quit`]
+    pub fn request_quit(&self) {
+        self.listeners.notify(Event::Quit);
+    }
+
+    /// Moves the queue on past `index` when the source playing the track there finishes. Sources know where they
+    /// are rather than counting, so a track ending just as the user skips can't move the queue on twice.
+    fn advance(&self, index: usize) -> impl FnMut() + Send + 'static {
+        let queue = self.queue.clone();
+        let queue_index = self.queue_index.clone();
+        let heard = self.heard.clone();
+        let listeners = self.listeners.clone();
+        move || {
+            // Held while announcing it, so the events can't cross with the ones from skipping
+            let mut current = queue_index.lock().unwrap();
+            let from = *current;
+            if from == index {
+                *current = index + 1;
+            } else if from == index + 1 {
+                // Skipped sources never get here, so this one ran out just as `next` moved past it. The skip it
+                // asked the sink for lands on the next source instead.
+                *current = index + 2;
+            } else {
+                return;
+            }
+            let queue = queue.lock().unwrap();
+            moved(&queue, from, *current, &heard, &listeners);
+        }
+    }
+
+    /// Refills the sink with the queue from `from(current index)` on, picking up the first track at `position`.
+    /// The sink can only be appended to, so this is how the queue is replayed or reordered. Tracks that can't be
+    /// opened any more are skipped, with an [`Event::Error`] for each. Callers hold `control`.
+    fn restart(&self, from: impl FnOnce(usize) -> usize, position: Duration) {
+        let paused = self.sink.is_paused();
+        // Tracks can still end while this waits, but once it returns they're gone and the index stays put
+        self.sink.clear();
+
+        let queue = self.queue.lock().unwrap().clone();
+        let index = {
+            let mut current = self.queue_index.lock().unwrap();
+            let index = from(*current);
+            let previous = std::mem::replace(&mut *current, index);
+            // Before refilling, since the first track could end before this is done
+            if position.is_zero() {
+                moved(&queue, previous, index, &self.heard, &self.listeners);
+            }
+            index
+        };
+
+        for (i, track) in queue.iter().enumerate().skip(index) {
+            match open(track) {
+                Ok(decoder) => self.sink.append(WrappedSource::new(
+                    decoder,
+                    self.heard.clone(),
+                    self.advance(i),
+                )),
+                Err(e) => {
+                    let title = track.cached_field_string(CachedField::Title);
+                    self.listeners
+                        .notify(Event::Error(format!("Couldn't play {title}: {e}")));
+                    // Keeps the queue index in step with the sink
+                    self.sink.append(WrappedSource::new(
+                        Empty::<i16>::new(),
+                        self.heard.clone(),
+                        self.advance(i),
+                    ));
+                }
+            }
+        }
+
+        if !position.is_zero() {
+            let _ = self.sink.try_seek(position);
+        }
+        if !paused {
+            self.sink.play();
+        }
+        if !position.is_zero() {
+            self.listeners.notify(Event::PositionChanged(position));
+        }
+    }
+}
+
+/// Whether an engine has anything queued, from [`Engine::busy`]. Paused tracks still count, since they're waiting
+/// to be played.
+#[derive(Clone)]
+pub struct Busy(Weak<Sink>);
+
+impl Busy {
+    /// False before anything's queued, once the queue runs out and once the engine is gone
+    pub fn is_busy(&self) -> bool {
+        self.0.upgrade().is_some_and(|sink| !sink.empty())
+    }
+}
+
+impl fmt::Debug for Busy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Busy").field(&self.is_busy()).finish()
+    }
+}
+
+/// Sends the position every [`POSITION_INTERVAL`] while playing, until the sink is dropped
+fn tick(sink: Weak<Sink>, listeners: Listeners<Event>) {
+    thread::spawn(move || loop {
+        thread::sleep(POSITION_INTERVAL);
+        let Some(sink) = sink.upgrade() else {
+            return;
+        };
+        if !sink.is_paused() && !sink.empty() {
+            listeners.notify(Event::PositionChanged(sink.get_pos()));
+        }
+    });
+}
+
+/// Announces that playback went from the track at `from` to the one at `to`, either of which may be past the end.
+/// The time heard starts again from zero for `to`.
+fn moved(queue: &[Track], from: usize, to: usize, heard: &AtomicU64, listeners: &Listeners<Event>) {
+    let heard = Duration::from_millis(heard.swap(0, Ordering::Relaxed));
+    if let Some(track) = queue.get(from) {
+        listeners.notify(Event::TrackEnded {
+            index: from,
+            track: track.clone(),
+            heard,
+        });
+    }
+    if let Some(track) = queue.get(to) {
+        listeners.notify(Event::TrackStarted {
+            index: to,
+            track: track.clone(),
+        });
+    }
+}
+
+fn open(track: &Track) -> Result<rodio::Decoder<BufReader<fs::File>>, QueueError> {
+    let file = fs::File::open(&track.path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => QueueError::Missing,
+        _ => QueueError::Unreadable(e),
+    })?;
+    rodio::Decoder::new(BufReader::new(file)).map_err(QueueError::Undecodable)
+}
+
+impl Playback for Engine {
+    fn queue(&self) -> Vec<Track> {
+        self.queue.lock().unwrap().clone()
+    }
+
+    fn queue_index(&self) -> usize {
+        *self.queue_index.lock().unwrap()
+    }
+
+    fn position(&self) -> Duration {
+        self.sink.get_pos()
+    }
+
+    fn is_paused(&self) -> bool {
+        self.sink.is_paused()
+    }
+
+    fn volume(&self) -> f32 {
+        self.sink.volume()
+    }
+
+    fn is_shuffled(&self) -> bool {
+        self.unshuffled.lock().unwrap().is_some()
+    }
+
+    fn current(&self) -> Option<Track> {
+        let index = self.queue_index();
+        self.queue.lock().unwrap().get(index).cloned()
+    }
+
+    fn play(&self) {
+        self.stopped.store(false, Ordering::Relaxed);
+        self.sink.play();
+        self.listeners.notify(Event::StateChanged);
+    }
+
+    fn pause(&self) {
+        self.sink.pause();
+        self.listeners.notify(Event::StateChanged);
+    }
+
+    fn next(&self) {
+        let _control = self.control.lock().unwrap();
+        let mut current = self.queue_index.lock().unwrap();
+        let index = *current;
+        let queue = self.queue.lock().unwrap();
+        if index >= queue.len() {
+            return;
+        }
+        *current = index + 1;
+        moved(&queue, index, index + 1, &self.heard, &self.listeners);
+        // Under the lock, so the source can't also move the queue on as it ends. See `advance`.
+        self.sink.skip_one();
+    }
+
+    fn previous(&self) -> Result<()> {
+        let _control = self.control.lock().unwrap();
+        let index = self.queue_index();
+        let len = self.queue.lock().unwrap().len();
+        if len == 0 {
+            return Err(anyhow!("The queue is empty"));
+        }
+
+        if index < len && self.sink.get_pos() > PREVIOUS_RESTARTS_AFTER {
+            let _ = self.sink.try_seek(Duration::ZERO);
+            self.listeners
+                .notify(Event::PositionChanged(Duration::ZERO));
+        } else {
+            self.restart(|index| index.min(len).saturating_sub(1), Duration::ZERO);
+        }
+        Ok(())
+    }
+
+    fn seek(&self, position: Duration) -> Result<()> {
+        if self.sink.empty() {
+            return Err(anyhow!("Nothing is playing"));
+        }
+        self.sink
+            .try_seek(position)
+            .map_err(|e| anyhow!("Couldn't seek: {e}"))?;
+        self.listeners.notify(Event::PositionChanged(position));
+        Ok(())
+    }
+
+    fn set_volume(&self, volume: f32) {
+        self.sink.set_volume(volume.clamp(0.0, 1.0));
+        self.listeners.notify(Event::StateChanged);
+    }
+
+    fn toggle_shuffle(&self) -> bool {
+        let _control = self.control.lock().unwrap();
+        let index = self.queue_index();
+        let (shuffled, playing) = {
+            let mut queue = self.queue.lock().unwrap();
+            let mut unshuffled = self.unshuffled.lock().unwrap();
+            let start = (index + 1).min(queue.len());
+            let shuffled = match unshuffled.take() {
+                Some(order) => {
+                    queue[start..].sort_by_key(|t| order.iter().position(|o| o == t));
+                    false
+                }
+                None => {
+                    *unshuffled = Some(queue.clone());
+                    fastrand::shuffle(&mut queue[start..]);
+                    true
+                }
+            };
+            (shuffled, index < queue.len())
+        };
+
+        if playing {
+            // Carry on with the current track where it was
+            self.restart(|_| index, self.sink.get_pos());
+        }
+        self.listeners.notify(Event::QueueChanged);
+        self.listeners.notify(Event::StateChanged);
+        shuffled
+    }
+
+    fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        self.sink.pause();
+        let _ = self.sink.try_seek(Duration::ZERO);
+        self.listeners.notify(Event::StateChanged);
+    }
+
+    fn play_from(&self, index: usize) -> Result<()> {
+        let _control = self.control.lock().unwrap();
+        let len = self.queue.lock().unwrap().len();
+        if index >= len {
+            return Err(anyhow!("There's no track {index} in the queue"));
+        }
+        self.stopped.store(false, Ordering::Relaxed);
+        self.sink.play();
+        self.restart(|_| index, Duration::ZERO);
+        Ok(())
+    }
+
+    fn enqueue(&self, track: &Track) -> Result<(), QueueError> {
+        let decoder = open(track)?;
+        // It works now even if it didn't before, e.g. a drive was remounted or the file was replaced
+        let mut track = track.clone();
+        track.unavailable = false;
+        track.undecodable = false;
+
+        let _control = self.control.lock().unwrap();
+        let index = {
+            let mut queue = self.queue.lock().unwrap();
+            queue.push(track.clone());
+            queue.len() - 1
+        };
+        if let Some(unshuffled) = self.unshuffled.lock().unwrap().as_mut() {
+            unshuffled.push(track.clone());
+        }
+
+        let current = self.queue_index.lock().unwrap();
+        self.listeners.notify(Event::QueueChanged);
+        if index == *current {
+            // Nothing else was left to play, so it starts straight away
+            self.listeners.notify(Event::TrackStarted { index, track });
+        }
+        // Only once it's been announced, since it could end before this returns
+        self.sink.append(WrappedSource::new(
+            decoder,
+            self.heard.clone(),
+            self.advance(index),
+        ));
+        Ok(())
+    }
+
+    fn update(&self, track: &Track) {
+        for t in self.queue.lock().unwrap().iter_mut() {
+            if t == track {
+                *t = track.clone();
+            }
+        }
+        self.listeners.notify(Event::QueueChanged);
+    }
+
+    fn relocate(&self, from: &Path, to: &Path) {
+        for t in self.queue.lock().unwrap().iter_mut() {
+            if t.path == from {
+                t.path = to.to_path_buf();
+            }
+        }
+        self.listeners.notify(Event::QueueChanged);
+    }
+
+    fn subscribe(&self) -> mpsc::Receiver<Event> {
+        self.listeners.subscribe()
+    }
+}
+
+/// The player without an interface: the library and the engine playing its tracks. A daemon runs only this, and
+/// the control servers work on it whether or not a terminal is attached.
+#[derive(Clone)]
+pub struct Core {
+    pub(crate) engine: Arc<Engine>,
+    pub(crate) library: Arc<Library>,
+    pub(crate) tracks: Arc<Mutex<Vec<Track>>>,
+}
+
+impl Core {
+    /// `tracks` are the library's, found by scanning it or loaded from the cache
+    pub fn new(engine: Engine, library: Arc<Library>, tracks: Vec<Track>) -> Self {
+        Self {
+            engine: Arc::new(engine),
+            library,
+            tracks: Arc::new(Mutex::new(tracks)),
+        }
+    }
+}
@@ -1,23 +1,32 @@
 use std::borrow::Cow;
 use std::cmp;
+use std::collections::HashMap;
+use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
-use anyhow::{anyhow, Error, Result};
+use anyhow::{anyhow, Context, Error, Result};
 use cursive_table_view::TableViewItem;
+use lofty::config::{ParseOptions, WriteOptions};
+use lofty::file::{FileType, TaggedFile};
+use lofty::flac::FlacFile;
+use lofty::id3::v2::{Frame, Id3v2Tag, PopularimeterFrame};
+use lofty::iff::aiff::AiffFile;
+use lofty::iff::wav::WavFile;
+use lofty::mp4::{Ilst, Mp4File};
+use lofty::mpeg::MpegFile;
+use lofty::ogg::{OpusFile, SpeexFile, VorbisComments, VorbisFile};
 use lofty::prelude::*;
 use lofty::probe::Probe;
+use lofty::tag::{MergeTag, SplitTag, Tag, TagType};
+use rodio::source::SeekError;
 use rodio::{Sample, Source};
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, PartialEq, Eq, Hash)]
-pub(crate) enum Field {
-    Cached { field: CachedField },
-    Tag { key: ItemKey },
-}
-
 #[non_exhaustive]
-#[derive(Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub(crate) enum CachedField {
     Title,
     Artist,
@@ -25,6 +34,7 @@ pub(crate) enum CachedField {
     Year,
     Genre,
     Duration,
+    Rating,
 }
 
 impl TryFrom<ItemKey> for CachedField {
@@ -63,16 +73,142 @@ impl TryFrom<CachedField> for ItemKey {
     }
 }
 
+/// An audio file in the library, with the tags it had when it was read and what the user set for it in the player.
+/// Two tracks are equal when they're the same file.
 #[non_exhaustive]
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
-pub(crate) struct Track {
+pub struct Track {
     pub(crate) path: PathBuf,
+    /// The library root the track was found under
+    #[serde(default)]
+    pub(crate) root: PathBuf,
     title: Option<String>,
     artist: Option<String>,
     album: Option<String>,
     duration: u64,
+    // Ratings were added after the cache format was first released, so older caches won't have them
+    #[serde(default)]
+    rating: Option<u8>,
+    #[serde(default)]
+    favourite: bool,
+    /// Hidden tracks stay in the library cache, but aren't shown in the library
+    #[serde(default)]
+    pub(crate) hidden: bool,
+    /// Whether the track is part of the current multi-selection in the library
+    #[serde(skip)]
+    pub(crate) marked: bool,
+    /// Set when the file disappeared while the player was running
+    #[serde(skip)]
+    pub(crate) unavailable: bool,
+    /// Set when the file couldn't be decoded for playback, so it isn't queued again without asking
+    #[serde(default)]
+    pub(crate) undecodable: bool,
+}
+
+/// Fields that can be changed from the tag editor
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum EditableField {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Year,
+    Genre,
+    TrackNumber,
+    DiscNumber,
+    Lyrics,
 }
 
+impl EditableField {
+    pub(crate) const ALL: [EditableField; 9] = [
+        Self::Title,
+        Self::Artist,
+        Self::Album,
+        Self::AlbumArtist,
+        Self::Year,
+        Self::Genre,
+        Self::TrackNumber,
+        Self::DiscNumber,
+        Self::Lyrics,
+    ];
+
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            Self::Title => "Title",
+            Self::Artist => "Artist",
+            Self::Album => "Album",
+            Self::AlbumArtist => "Album artist",
+            Self::Year => "Year",
+            Self::Genre => "Genre",
+            Self::TrackNumber => "Track",
+            Self::DiscNumber => "Disc",
+            Self::Lyrics => "Lyrics",
+        }
+    }
+
+    fn item_key(&self) -> ItemKey {
+        match self {
+            Self::Title => ItemKey::TrackTitle,
+            Self::Artist => ItemKey::TrackArtist,
+            Self::Album => ItemKey::AlbumTitle,
+            Self::AlbumArtist => ItemKey::AlbumArtist,
+            Self::Year => ItemKey::Year,
+            Self::Genre => ItemKey::Genre,
+            Self::TrackNumber => ItemKey::TrackNumber,
+            Self::DiscNumber => ItemKey::DiscNumber,
+            Self::Lyrics => ItemKey::Lyrics,
+        }
+    }
+
+    pub(crate) fn is_numeric(&self) -> bool {
+        matches!(self, Self::Year | Self::TrackNumber | Self::DiscNumber)
+    }
+
+    fn read(&self, tag: &Tag) -> Option<String> {
+        match self {
+            // Formats disagree on where the year lives, so let lofty figure it out
+            Self::Year => tag.year().map(|y| y.to_string()),
+            _ => tag.get_string(&self.item_key()).map(|s| s.to_owned()),
+        }
+    }
+
+    fn write(&self, tag: &mut Tag, value: &str) -> Result<()> {
+        let value = value.trim_end();
+        if value.is_empty() {
+            match self {
+                Self::Year => tag.remove_year(),
+                _ => tag.remove_key(&self.item_key()),
+            }
+            return Ok(());
+        }
+
+        if self.is_numeric() {
+            let n: u32 = value
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("{} must be a number, got \"{value}\"", self.label()))?;
+            match self {
+                Self::Year => tag.set_year(n),
+                Self::TrackNumber => tag.set_track(n),
+                _ => tag.set_disk(n),
+            }
+        } else {
+            tag.insert_text(self.item_key(), value.to_owned());
+        }
+
+        Ok(())
+    }
+}
+
+/// The highest number of stars a track can be rated
+pub const MAX_RATING: u8 = 5;
+
+/// Email identifying our POPM frames. ID3 allows one POPM frame per email, so we only touch our own
+const POPM_EMAIL: &str = "minim";
+
+/// Vorbis comment key used by the FMPS spec, holding a rating between 0.0 and 1.0
+const FMPS_RATING_KEY: &str = "FMPS_RATING";
+
 impl PartialEq for Track {
     fn eq(&self, other: &Self) -> bool {
         self.path.eq(&other.path)
@@ -92,6 +228,36 @@ impl Track {
         tag.as_deref().map(|x| x.to_owned())
     }
 
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    pub fn artist(&self) -> Option<&str> {
+        self.artist.as_deref()
+    }
+
+    pub fn album(&self) -> Option<&str> {
+        self.album.as_deref()
+    }
+
+    /// Length of the track in seconds
+    pub fn duration(&self) -> u64 {
+        self.duration
+    }
+
+    /// Stars out of [`MAX_RATING`], if it's been rated
+    pub fn rating(&self) -> Option<u8> {
+        self.rating
+    }
+
+    pub fn is_favourite(&self) -> bool {
+        self.favourite
+    }
+
     pub(crate) fn cached_field_string(&self, field: CachedField) -> String {
         match field {
             CachedField::Title => {
@@ -106,12 +272,14 @@ impl Track {
                 }
             }
             CachedField::Artist => self.artist.clone().unwrap_or_default(),
+            CachedField::Album => self.album.clone().unwrap_or_default(),
             CachedField::Duration => {
                 let secs = self.duration;
                 let mins = secs / 60;
                 let secs = secs % 60;
                 format!("{mins}:{:0>2}", secs)
             }
+            CachedField::Rating => self.rating_string(),
             _ => {
                 if let Ok(key) = field.try_into() {
                     if let Ok(s) = self.tag_string_from_track(key) {
@@ -126,6 +294,165 @@ impl Track {
         }
     }
 
+    /// Sets the star rating, clamped to [`MAX_RATING`]. A rating of 0 clears it.
+    pub(crate) fn set_rating(&mut self, rating: u8) {
+        self.rating = match rating.min(MAX_RATING) {
+            0 => None,
+            r => Some(r),
+        };
+    }
+
+    pub(crate) fn toggle_favourite(&mut self) {
+        self.favourite = !self.favourite;
+    }
+
+    /// Carries over what the user set in the player from an older copy of this track, e.g., after the file was
+    /// retagged. Ratings only saved in the library cache are kept unless the file now has its own.
+    pub(crate) fn keep_user_state(&mut self, old: &Track) {
+        self.rating = self.rating.or(old.rating);
+        self.favourite = old.favourite;
+        self.hidden = old.hidden;
+        self.marked = old.marked;
+    }
+
+    fn rating_string(&self) -> String {
+        let stars = self.rating.unwrap_or(0);
+        let mut s = String::new();
+        s.push(if self.favourite { '♥' } else { ' ' });
+        s.push(' ');
+        for i in 0..MAX_RATING {
+            s.push(if i < stars { '★' } else { '☆' });
+        }
+        s
+    }
+
+    /// Maps a POPM rating byte to stars, using the same ranges as Windows Media Player
+    /// See <https://en.wikipedia.org/wiki/ID3#ID3v2_star_rating_tag_issue>
+    fn stars_from_popm(rating: u8) -> Option<u8> {
+        match rating {
+            0 => None,
+            1..=31 => Some(1),
+            32..=95 => Some(2),
+            96..=159 => Some(3),
+            160..=223 => Some(4),
+            224..=255 => Some(5),
+        }
+    }
+
+    fn popm_from_stars(stars: u8) -> u8 {
+        match stars {
+            0 => 0,
+            1 => 1,
+            2 => 64,
+            3 => 128,
+            4 => 196,
+            _ => 255,
+        }
+    }
+
+    /// The rating in `tag`. Generic tags leave POPM frames out, so for ID3v2 it's `popm`, the rating found with
+    /// [`Self::rating_from_id3v2`] before the tag was made generic.
+    fn rating_from_tag(tag: &Tag, popm: Option<u8>) -> Option<u8> {
+        match tag.tag_type() {
+            TagType::Id3v2 => popm,
+            TagType::VorbisComments => {
+                let value: f64 = tag
+                    .get_string(&ItemKey::Unknown(FMPS_RATING_KEY.to_owned()))?
+                    .parse()
+                    .ok()?;
+                let stars = (value.clamp(0.0, 1.0) * MAX_RATING as f64).round() as u8;
+                (stars > 0).then_some(stars)
+            }
+            _ => None,
+        }
+    }
+
+    fn rating_from_id3v2(tag: &Id3v2Tag) -> Option<u8> {
+        tag.into_iter().find_map(|frame| match frame {
+            Frame::Popularimeter(popm) if popm.email == POPM_EMAIL => {
+                Self::stars_from_popm(popm.rating)
+            }
+            _ => None,
+        })
+    }
+
+    /// Writes the rating into the file's primary tag, as POPM for ID3v2 or FMPS_RATING for Vorbis comments
+    pub(crate) fn write_rating_to_file(&self) -> Result<()> {
+        let mut tag = PrimaryTag::read(&self.path)?;
+
+        let stars = self.rating.unwrap_or(0);
+        match &mut tag {
+            PrimaryTag::Id3v2(tag) => {
+                // Only ours is replaced, POPM frames written by other players stay
+                if stars > 0 {
+                    let frame = PopularimeterFrame::new(
+                        POPM_EMAIL.to_owned(),
+                        Self::popm_from_stars(stars),
+                        0,
+                    );
+                    tag.insert(Frame::Popularimeter(frame));
+                } else {
+                    tag.retain(|f| !matches!(f, Frame::Popularimeter(p) if p.email == POPM_EMAIL));
+                }
+            }
+            PrimaryTag::VorbisComments(tag) => {
+                if stars > 0 {
+                    let value = stars as f64 / MAX_RATING as f64;
+                    tag.insert(FMPS_RATING_KEY.to_owned(), format!("{value:.1}"));
+                } else {
+                    tag.remove(FMPS_RATING_KEY).for_each(drop);
+                }
+            }
+            _ => return Err(anyhow!("Ratings can't be stored in this tag format")),
+        }
+
+        tag.save(&self.path)
+    }
+
+    /// Reads the current value of every [`EditableField`] from the file
+    pub(crate) fn read_editable_fields(&self) -> Result<HashMap<EditableField, String>> {
+        let tagged_file = Probe::open(&self.path)?.read()?;
+        let Some(tag) = tagged_file
+            .primary_tag()
+            .or_else(|| tagged_file.first_tag())
+        else {
+            return Ok(HashMap::new());
+        };
+
+        Ok(EditableField::ALL
+            .iter()
+            .filter_map(|field| Some((*field, field.read(tag)?)))
+            .collect())
+    }
+
+    /// Writes `changes` into the file's primary tag, creating one if needed, and refreshes the cached
+    /// fields to match. Empty values remove the field from the tag.
+    pub(crate) fn write_tag_fields(
+        &mut self,
+        changes: &HashMap<EditableField, String>,
+    ) -> Result<()> {
+        if fs::metadata(&self.path)?.permissions().readonly() {
+            return Err(anyhow!("{} is read-only", self.path.display()));
+        }
+
+        let mut primary = PrimaryTag::read(&self.path)?;
+        let tag = primary.edit(|tag| {
+            for (field, value) in changes {
+                field.write(tag, value)?;
+            }
+            Ok(())
+        })?;
+        primary
+            .save(&self.path)
+            .with_context(|| format!("Couldn't save tags to {}", self.path.display()))?;
+
+        self.title = Self::tag_to_string(tag.title());
+        self.artist = Self::tag_to_string(tag.artist());
+        self.album = Self::tag_to_string(tag.album());
+
+        Ok(())
+    }
+
     pub(crate) fn tag_string_from_track(&self, key: ItemKey) -> Result<String> {
         let tagged_file = Probe::open(&self.path)?.read()?;
 
@@ -141,6 +468,113 @@ impl Track {
     }
 }
 
+/// A file's primary tag as lofty's own type for that format. Saving a generic [`Tag`] writes a new tag made of only
+/// what it can hold, which would drop e.g. SYLT lyrics, so edits go through this and are merged back in.
+enum PrimaryTag {
+    Id3v2(Id3v2Tag),
+    VorbisComments(VorbisComments),
+    Ilst(Ilst),
+    /// Formats with nothing a generic tag misses, or that minim doesn't know the concrete type of
+    Generic(Tag),
+}
+
+impl PrimaryTag {
+    /// Reads the primary tag of the file at `path`, or an empty one if it has none
+    fn read(path: &Path) -> Result<Self> {
+        let file_type = Probe::open(path)?.guess_file_type()?.file_type();
+        let mut file = fs::File::open(path)?;
+        let options = ParseOptions::new().read_properties(false);
+        Ok(match file_type {
+            Some(FileType::Mpeg) => Self::Id3v2(
+                MpegFile::read_from(&mut file, options)?
+                    .id3v2()
+                    .cloned()
+                    .unwrap_or_default(),
+            ),
+            Some(FileType::Wav) => Self::Id3v2(
+                WavFile::read_from(&mut file, options)?
+                    .id3v2()
+                    .cloned()
+                    .unwrap_or_default(),
+            ),
+            Some(FileType::Aiff) => Self::Id3v2(
+                AiffFile::read_from(&mut file, options)?
+                    .id3v2()
+                    .cloned()
+                    .unwrap_or_default(),
+            ),
+            Some(FileType::Flac) => Self::VorbisComments(
+                FlacFile::read_from(&mut file, options)?
+                    .vorbis_comments()
+                    .cloned()
+                    .unwrap_or_default(),
+            ),
+            Some(FileType::Opus) => Self::VorbisComments(
+                OpusFile::read_from(&mut file, options)?
+                    .vorbis_comments()
+                    .clone(),
+            ),
+            Some(FileType::Vorbis) => Self::VorbisComments(
+                VorbisFile::read_from(&mut file, options)?
+                    .vorbis_comments()
+                    .clone(),
+            ),
+            Some(FileType::Speex) => Self::VorbisComments(
+                SpeexFile::read_from(&mut file, options)?
+                    .vorbis_comments()
+                    .clone(),
+            ),
+            Some(FileType::Mp4) => Self::Ilst(
+                Mp4File::read_from(&mut file, options)?
+                    .ilst()
+                    .cloned()
+                    .unwrap_or_default(),
+            ),
+            _ => {
+                let tagged_file = Probe::open(path)?.read()?;
+                let tag = tagged_file.primary_tag().cloned();
+                Self::Generic(tag.unwrap_or_else(|| Tag::new(tagged_file.primary_tag_type())))
+            }
+        })
+    }
+
+    /// Lets `edit` change the tag as a generic [`Tag`] and merges the changes back in, returning the edited `Tag`
+    fn edit(&mut self, edit: impl FnOnce(&mut Tag) -> Result<()>) -> Result<Tag> {
+        match self {
+            Self::Id3v2(tag) => edit_split(tag, edit),
+            Self::VorbisComments(tag) => edit_split(tag, edit),
+            Self::Ilst(tag) => edit_split(tag, edit),
+            Self::Generic(tag) => {
+                edit(tag)?;
+                Ok(tag.clone())
+            }
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        let options = WriteOptions::default();
+        match self {
+            Self::Id3v2(tag) => tag.save_to_path(path, options)?,
+            Self::VorbisComments(tag) => tag.save_to_path(path, options)?,
+            Self::Ilst(tag) => tag.save_to_path(path, options)?,
+            Self::Generic(tag) => tag.save_to_path(path, options)?,
+        }
+        Ok(())
+    }
+}
+
+/// Splits off the part of `tag` a generic [`Tag`] can hold for `edit` to change, then merges it back in
+fn edit_split<T>(tag: &mut T, edit: impl FnOnce(&mut Tag) -> Result<()>) -> Result<Tag>
+where
+    T: SplitTag + Default,
+    T::Remainder: MergeTag<Merged = T>,
+{
+    let (remainder, mut generic) = std::mem::take(tag).split_tag();
+    let edited = edit(&mut generic);
+    *tag = remainder.merge_tag(generic.clone());
+    edited.map(|()| generic)
+}
+
 // Can't add generic implementation for AsRef<Path> :(
 // https://github.com/rust-lang/rust/issues/50133
 impl TryFrom<&Path> for Track {
@@ -155,7 +589,27 @@ impl TryFrom<PathBuf> for Track {
     type Error = anyhow::Error;
 
     fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
-        let tagged_file = Probe::open(&path)?.read()?;
+        // Formats with ID3v2 tags are read as their own type first, to get at the POPM frames the generic tag drops
+        let probe = Probe::open(&path)?.guess_file_type()?;
+        let options = ParseOptions::new();
+        let (tagged_file, popm): (TaggedFile, _) = match probe.file_type() {
+            Some(FileType::Mpeg) => {
+                let file = MpegFile::read_from(&mut probe.into_inner(), options)?;
+                let popm = file.id3v2().and_then(Self::rating_from_id3v2);
+                (file.into(), popm)
+            }
+            Some(FileType::Wav) => {
+                let file = WavFile::read_from(&mut probe.into_inner(), options)?;
+                let popm = file.id3v2().and_then(Self::rating_from_id3v2);
+                (file.into(), popm)
+            }
+            Some(FileType::Aiff) => {
+                let file = AiffFile::read_from(&mut probe.into_inner(), options)?;
+                let popm = file.id3v2().and_then(Self::rating_from_id3v2);
+                (file.into(), popm)
+            }
+            _ => (probe.read()?, None),
+        };
 
         // Try to get primary tag, then try to find the first tag, otherwise
         // generate an empty tag if none exist
@@ -165,14 +619,22 @@ impl TryFrom<PathBuf> for Track {
             .ok_or(anyhow!("Couldn't find tags from file"))?;
 
         let properties = tagged_file.properties();
+        let rating = Self::rating_from_tag(tag, popm);
 
         Ok({
             Track {
                 path,
+                root: PathBuf::new(),
                 title: Self::tag_to_string(tag.title()),
                 artist: Self::tag_to_string(tag.artist()),
                 album: Self::tag_to_string(tag.album()),
                 duration: properties.duration().as_secs(),
+                rating,
+                favourite: false,
+                hidden: false,
+                marked: false,
+                unavailable: false,
+                undecodable: false,
             }
         })
     }
@@ -180,7 +642,21 @@ impl TryFrom<PathBuf> for Track {
 
 impl TableViewItem<CachedField> for Track {
     fn to_column(&self, column: CachedField) -> String {
-        self.cached_field_string(column)
+        match column {
+            CachedField::Title => {
+                let mut s = self.cached_field_string(column);
+                if self.unavailable {
+                    s = format!("(missing) {s}");
+                } else if self.undecodable {
+                    s = format!("(unplayable) {s}");
+                }
+                if self.marked {
+                    s = format!("+ {s}");
+                }
+                s
+            }
+            _ => self.cached_field_string(column),
+        }
     }
 
     fn cmp(&self, other: &Self, column: CachedField) -> std::cmp::Ordering
@@ -188,30 +664,60 @@ impl TableViewItem<CachedField> for Track {
         Self: Sized,
     {
         match column {
-            CachedField::Title | CachedField::Artist => {
+            CachedField::Title | CachedField::Artist | CachedField::Album => {
                 // TODO: Clean this up? Sort None values to the bottom
                 self.cached_field_string(column)
                     .to_lowercase()
                     .cmp(&other.cached_field_string(column).to_lowercase())
             }
             CachedField::Duration => self.duration.cmp(&other.duration),
+            CachedField::Rating => {
+                (self.rating, self.favourite).cmp(&(other.rating, other.favourite))
+            }
             // Don't bother sorting on anything else, since we don't show those columns
             _ => cmp::Ordering::Equal,
         }
     }
 }
 
+/// How many samples go by between updates of how long a track has been heard
+const HEARD_INTERVAL: u64 = 1024;
+
 // https://stackoverflow.com/questions/77876116/how-to-i-detect-when-a-sink-moves-to-the-next-source
 pub(crate) struct WrappedSource<S, F> {
     source: S,
     on_track_end: F,
+    /// Samples played so far. Paused sources aren't asked for any, and seeking skips past them.
+    played: u64,
+    /// How much of that has been added to `heard`, in milliseconds
+    published: u64,
+    /// Where to add up how long the track has been heard for, in milliseconds. It's added to rather than set, so
+    /// a track carries on counting when its source is replaced, e.g. when the queue is shuffled.
+    heard: Arc<AtomicU64>,
 }
 
 impl<S, F> WrappedSource<S, F> {
-    pub(crate) fn new(source: S, on_track_end: F) -> Self {
+    pub(crate) fn new(source: S, heard: Arc<AtomicU64>, on_track_end: F) -> Self {
         Self {
             source,
             on_track_end,
+            played: 0,
+            published: 0,
+            heard,
+        }
+    }
+}
+
+impl<S: Source, F> WrappedSource<S, F>
+where
+    S::Item: Sample,
+{
+    fn publish_heard(&mut self) {
+        let per_second = self.source.sample_rate() as u64 * self.source.channels() as u64;
+        if let Some(total) = (self.played * 1000).checked_div(per_second) {
+            self.heard
+                .fetch_add(total.saturating_sub(self.published), Ordering::Relaxed);
+            self.published = total;
         }
     }
 }
@@ -226,8 +732,15 @@ where
 
     fn next(&mut self) -> Option<Self::Item> {
         match self.source.next() {
-            Some(s) => Some(s),
+            Some(s) => {
+                self.played += 1;
+                if self.played.is_multiple_of(HEARD_INTERVAL) {
+                    self.publish_heard();
+                }
+                Some(s)
+            }
             None => {
+                self.publish_heard();
                 (self.on_track_end)();
                 None
             }
@@ -260,4 +773,117 @@ where
     fn total_duration(&self) -> Option<Duration> {
         self.source.total_duration()
     }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.source.try_seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use lofty::id3::v2::{
+        BinaryFrame, FrameId, SyncTextContentType, SynchronizedTextFrame, TimestampFormat,
+    };
+    use lofty::TextEncoding;
+
+    use super::*;
+
+    /// An MP3 of silent frames with an ID3v2 tag holding a title, a SYLT frame and another player's rating
+    fn mp3_with_sylt(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("minim-test-{}-{name}.mp3", std::process::id()));
+        // MPEG-1 layer III at 128kbps and 44.1kHz, 417 bytes a frame
+        let mut frame = vec![0xFF, 0xFB, 0x90, 0x00];
+        frame.resize(417, 0);
+        fs::write(&path, frame.repeat(20)).unwrap();
+
+        let sylt = SynchronizedTextFrame::new(
+            TextEncoding::UTF8,
+            *b"eng",
+            TimestampFormat::MS,
+            SyncTextContentType::Lyrics,
+            None,
+            vec![(0, "First".to_owned()), (1000, "Second".to_owned())],
+        );
+        let mut tag = Id3v2Tag::new();
+        tag.set_title("Before".to_owned());
+        tag.insert(Frame::Binary(BinaryFrame::new(
+            FrameId::Valid(Cow::Borrowed("SYLT")),
+            sylt.as_bytes().unwrap(),
+        )));
+        tag.insert(Frame::Popularimeter(PopularimeterFrame::new(
+            "someone@else".to_owned(),
+            64,
+            3,
+        )));
+        tag.save_to_path(&path, WriteOptions::default()).unwrap();
+        path
+    }
+
+    fn read_id3v2(path: &Path) -> Id3v2Tag {
+        let mut file = fs::File::open(path).unwrap();
+        MpegFile::read_from(&mut file, ParseOptions::new())
+            .unwrap()
+            .id3v2()
+            .cloned()
+            .unwrap()
+    }
+
+    fn has_sylt(tag: &Id3v2Tag) -> bool {
+        tag.get(&FrameId::Valid(Cow::Borrowed("SYLT"))).is_some()
+    }
+
+    #[test]
+    fn editing_tags_keeps_frames_the_editor_doesnt_know() {
+        let path = mp3_with_sylt("edit");
+        let mut track = Track::try_from(path.as_path()).unwrap();
+
+        let changes = HashMap::from([(EditableField::Title, "After".to_owned())]);
+        track.write_tag_fields(&changes).unwrap();
+
+        let tag = read_id3v2(&path);
+        assert_eq!(tag.title().as_deref(), Some("After"));
+        assert_eq!(track.title(), Some("After"));
+        assert!(has_sylt(&tag));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rating_keeps_frames_and_other_players_ratings() {
+        let path = mp3_with_sylt("rating");
+        let mut track = Track::try_from(path.as_path()).unwrap();
+        let popms = |tag: &Id3v2Tag| -> Vec<(String, u8)> {
+            let mut popms: Vec<_> = tag
+                .into_iter()
+                .filter_map(|f| match f {
+                    Frame::Popularimeter(p) => Some((p.email.clone(), p.rating)),
+                    _ => None,
+                })
+                .collect();
+            popms.sort();
+            popms
+        };
+
+        track.set_rating(4);
+        track.write_rating_to_file().unwrap();
+        let tag = read_id3v2(&path);
+        assert!(has_sylt(&tag));
+        assert_eq!(
+            popms(&tag),
+            [
+                (POPM_EMAIL.to_owned(), 196),
+                ("someone@else".to_owned(), 64)
+            ]
+        );
+        assert_eq!(Track::try_from(path.as_path()).unwrap().rating(), Some(4));
+
+        track.set_rating(0);
+        track.write_rating_to_file().unwrap();
+        let tag = read_id3v2(&path);
+        assert!(has_sylt(&tag));
+        assert_eq!(popms(&tag), [("someone@else".to_owned(), 64)]);
+        fs::remove_file(path).unwrap();
+    }
 }
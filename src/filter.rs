@@ -0,0 +1,57 @@
+use anyhow::{anyhow, Result};
+
+use crate::files::{CachedField, Track};
+
+/// Fields that can be named in a filter, e.g. `artist:floyd`
+pub(crate) const FILTER_FIELDS: [(&str, CachedField); 3] = [
+    ("artist", CachedField::Artist),
+    ("title", CachedField::Title),
+    ("album", CachedField::Album),
+];
+
+/// Narrows down the library view. Every term has to match, case-insensitively, and terms without a field can match
+/// any of them.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Filter {
+    terms: Vec<(Option<CachedField>, String)>,
+}
+
+impl Filter {
+    pub(crate) fn parse(s: &str) -> Result<Self> {
+        let mut terms = Vec::new();
+        for word in s.split_whitespace() {
+            let term = match word.split_once(':') {
+                Some((name, value)) => {
+                    let field = FILTER_FIELDS
+                        .iter()
+                        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                        .map(|(_, f)| *f)
+                        .ok_or(anyhow!("Can't filter by \"{name}\""))?;
+                    (Some(field), value.to_lowercase())
+                }
+                None => (None, word.to_lowercase()),
+            };
+            terms.push(term);
+        }
+
+        Ok(Self { terms })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub(crate) fn matches(&self, track: &Track) -> bool {
+        let contains = |field, value: &str| {
+            track
+                .cached_field_string(field)
+                .to_lowercase()
+                .contains(value)
+        };
+
+        self.terms.iter().all(|(field, value)| match field {
+            Some(field) => contains(*field, value),
+            None => FILTER_FIELDS.iter().any(|(_, f)| contains(*f, value)),
+        })
+    }
+}
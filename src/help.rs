@@ -0,0 +1,337 @@
+use cursive::{
+    event::{Event, Key},
+    theme::Effect,
+    utils::markup::StyledString,
+    view::{Nameable, Scrollable},
+    views::{Dialog, OnEventView, TextView},
+    Cursive,
+};
+
+use crate::actions::COMMANDS;
+use crate::views::SharedState;
+
+/// Where a view key works
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum KeyScope {
+    /// Tables of tracks, i.e., the library and the queue
+    Tracks,
+    /// Every table
+    Table,
+    Library,
+    Duplicates,
+    Lyrics,
+    CommandLine,
+}
+
+/// What a view key does
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum ViewAction {
+    Rate(u8),
+    Favourite,
+    EditTags,
+    BatchEdit,
+    Down,
+    Up,
+    Top,
+    Bottom,
+    PageDown,
+    PageUp,
+    Queue,
+    Mark,
+    SearchTags,
+    SearchAudio,
+    Hide,
+    Delete,
+    Later,
+    Earlier,
+    Run,
+    Complete,
+    Close,
+}
+
+/// A key, or a few related keys, that a view handles itself rather than through the keymap
+struct ViewKey {
+    scope: KeyScope,
+    /// How the keys are written in the help overlay
+    keys: &'static str,
+    description: &'static str,
+    /// Each key press and what it does
+    presses: &'static [(Event, ViewAction)],
+}
+
+impl ViewKey {
+    const fn new(
+        scope: KeyScope,
+        keys: &'static str,
+        description: &'static str,
+        presses: &'static [(Event, ViewAction)],
+    ) -> Self {
+        Self {
+            scope,
+            keys,
+            description,
+            presses,
+        }
+    }
+}
+
+/// Every key the views handle. The views bind what's here and the help overlay lists it, so the two can't
+/// disagree.
+const VIEW_KEYS: [ViewKey; 17] = [
+    ViewKey::new(
+        KeyScope::Tracks,
+        "0-5",
+        "Rate the marked or selected tracks, 0 clears the rating",
+        &[
+            (Event::Char('0'), ViewAction::Rate(0)),
+            (Event::Char('1'), ViewAction::Rate(1)),
+            (Event::Char('2'), ViewAction::Rate(2)),
+            (Event::Char('3'), ViewAction::Rate(3)),
+            (Event::Char('4'), ViewAction::Rate(4)),
+            (Event::Char('5'), ViewAction::Rate(5)),
+        ],
+    ),
+    ViewKey::new(
+        KeyScope::Tracks,
+        "f",
+        "Toggle favourite",
+        &[(Event::Char('f'), ViewAction::Favourite)],
+    ),
+    ViewKey::new(
+        KeyScope::Tracks,
+        "e",
+        "Edit tags",
+        &[(Event::Char('e'), ViewAction::EditTags)],
+    ),
+    ViewKey::new(
+        KeyScope::Tracks,
+        "b",
+        "Batch edit",
+        &[(Event::Char('b'), ViewAction::BatchEdit)],
+    ),
+    ViewKey::new(
+        KeyScope::Table,
+        "j k",
+        "Move down or up",
+        &[
+            (Event::Char('j'), ViewAction::Down),
+            (Event::Char('k'), ViewAction::Up),
+        ],
+    ),
+    ViewKey::new(
+        KeyScope::Table,
+        "gg G",
+        "Go to the top or bottom",
+        &[
+            (Event::Char('g'), ViewAction::Top),
+            (Event::Char('G'), ViewAction::Bottom),
+        ],
+    ),
+    ViewKey::new(
+        KeyScope::Table,
+        "Ctrl-d Ctrl-u",
+        "Move a page down or up",
+        &[
+            (Event::CtrlChar('d'), ViewAction::PageDown),
+            (Event::CtrlChar('u'), ViewAction::PageUp),
+        ],
+    ),
+    // The table submits on Enter itself
+    ViewKey::new(
+        KeyScope::Library,
+        "Enter",
+        "Queue the track",
+        &[(Event::Key(Key::Enter), ViewAction::Queue)],
+    ),
+    ViewKey::new(
+        KeyScope::Library,
+        "Space",
+        "Mark or unmark the track",
+        &[(Event::Char(' '), ViewAction::Mark)],
+    ),
+    ViewKey::new(
+        KeyScope::Duplicates,
+        "r",
+        "Search for duplicates by tags",
+        &[(Event::Char('r'), ViewAction::SearchTags)],
+    ),
+    ViewKey::new(
+        KeyScope::Duplicates,
+        "F",
+        "Search for duplicates by comparing audio",
+        &[(Event::Char('F'), ViewAction::SearchAudio)],
+    ),
+    ViewKey::new(
+        KeyScope::Duplicates,
+        "h",
+        "Hide or unhide the track in the library",
+        &[(Event::Char('h'), ViewAction::Hide)],
+    ),
+    ViewKey::new(
+        KeyScope::Duplicates,
+        "D",
+        "Delete the file from disk",
+        &[(Event::Char('D'), ViewAction::Delete)],
+    ),
+    ViewKey::new(
+        KeyScope::Lyrics,
+        "+ -",
+        "Show the lyrics a quarter second later or earlier",
+        &[
+            (Event::Char('+'), ViewAction::Later),
+            (Event::Char('='), ViewAction::Later),
+            (Event::Char('-'), ViewAction::Earlier),
+        ],
+    ),
+    // The edit view submits on Enter itself
+    ViewKey::new(
+        KeyScope::CommandLine,
+        "Enter",
+        "Run the command",
+        &[(Event::Key(Key::Enter), ViewAction::Run)],
+    ),
+    ViewKey::new(
+        KeyScope::CommandLine,
+        "Tab",
+        "Complete the command or filter field",
+        &[(Event::Key(Key::Tab), ViewAction::Complete)],
+    ),
+    ViewKey::new(
+        KeyScope::CommandLine,
+        "Esc",
+        "Close the command line",
+        &[(Event::Key(Key::Esc), ViewAction::Close)],
+    ),
+];
+
+/// The key presses views in `scope` handle, and what each does
+pub(crate) fn presses(scope: KeyScope) -> impl Iterator<Item = (Event, ViewAction)> {
+    VIEW_KEYS
+        .iter()
+        .filter(move |k| k.scope == scope)
+        .flat_map(|k| k.presses.iter().cloned())
+}
+
+/// What pressing `event` does in `scope`, if it's one of the keys there
+pub(crate) fn action(scope: KeyScope, event: &Event) -> Option<ViewAction> {
+    presses(scope).find(|(e, _)| e == event).map(|(_, a)| a)
+}
+
+const HELP: &str = "help";
+
+/// How wide the column of keys is
+const KEY_WIDTH: usize = 16;
+
+fn section(text: &mut StyledString, title: &str, keys: Vec<(String, String)>) {
+    if !text.is_empty() {
+        text.append_plain("\n");
+    }
+    text.append_styled(format!("{title}\n"), Effect::Bold);
+    for (key, description) in keys {
+        text.append_plain(format!("  {key:<KEY_WIDTH$}{description}\n"));
+    }
+}
+
+fn local(scopes: &[KeyScope]) -> Vec<(String, String)> {
+    scopes
+        .iter()
+        .flat_map(|scope| VIEW_KEYS.iter().filter(move |k| k.scope == *scope))
+        .map(|k| (k.keys.to_owned(), k.description.to_owned()))
+        .collect()
+}
+
+/// Describes a global binding by its command's description in the registry, along with any arguments it's given
+fn describe(command: &str) -> String {
+    let name = command.split_whitespace().next().unwrap_or_default();
+    let description = COMMANDS
+        .iter()
+        .find(|c| c.name == name)
+        .map_or("", |c| c.description);
+    if command == name {
+        description.to_owned()
+    } else {
+        format!("{description} ({command})")
+    }
+}
+
+/// Shows every key binding grouped by where it works, or closes the list if it's already open. The global
+/// bindings come from the keymap, so ones changed in the config file show up as they are.
+pub(crate) fn open(siv: &mut Cursive, state: &SharedState) {
+    let screen = siv.screen_mut();
+    if let Some(position) = screen.find_layer_from_name(HELP) {
+        screen.remove_layer(position);
+        return;
+    }
+
+    let global = state
+        .keymap
+        .bindings()
+        .map(|(keys, command)| (keys.to_owned(), describe(command)))
+        .collect();
+
+    let mut text = StyledString::new();
+    section(&mut text, "Global", global);
+    section(
+        &mut text,
+        "Library table",
+        local(&[KeyScope::Library, KeyScope::Tracks, KeyScope::Table]),
+    );
+    section(
+        &mut text,
+        "Queue",
+        local(&[KeyScope::Tracks, KeyScope::Table]),
+    );
+    section(&mut text, "Lyrics", local(&[KeyScope::Lyrics]));
+    section(
+        &mut text,
+        "Duplicates",
+        local(&[KeyScope::Duplicates, KeyScope::Table]),
+    );
+    section(&mut text, "Command line", local(&[KeyScope::CommandLine]));
+
+    let dialog = Dialog::around(TextView::new(text).scrollable())
+        .title("Keys")
+        .dismiss_button("Close");
+    siv.add_layer(
+        OnEventView::new(dialog)
+            .on_event(Key::Esc, |siv| {
+                siv.pop_layer();
+            })
+            .with_name(HELP),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::files::MAX_RATING;
+
+    #[test]
+    fn every_rating_has_a_key() {
+        for stars in 0..=MAX_RATING {
+            assert!(
+                presses(KeyScope::Tracks).any(|(_, a)| a == ViewAction::Rate(stars)),
+                "{stars} stars has no key"
+            );
+        }
+    }
+
+    #[test]
+    fn keys_arent_bound_twice_in_one_view() {
+        let views = [
+            vec![KeyScope::Library, KeyScope::Tracks, KeyScope::Table],
+            vec![KeyScope::Duplicates, KeyScope::Table],
+            vec![KeyScope::Lyrics],
+            vec![KeyScope::CommandLine],
+        ];
+        for scopes in views {
+            let events: Vec<Event> = scopes
+                .iter()
+                .flat_map(|s| presses(*s).map(|(e, _)| e))
+                .collect();
+            for (i, event) in events.iter().enumerate() {
+                assert!(!events[i + 1..].contains(event), "{event:?} is bound twice");
+            }
+        }
+    }
+}
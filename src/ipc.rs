@@ -0,0 +1,308 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{status_changes, Core, Playback};
+use crate::files::Track;
+
+/// Where a running player listens for commands
+pub(crate) fn socket_path() -> Result<PathBuf> {
+    let dir = dirs::runtime_dir().ok_or(anyhow!("XDG_RUNTIME_DIR isn't set"))?;
+    Ok(dir.join("minim.sock"))
+}
+
+/// A command sent to a running player, one JSON object per line, e.g. `{"command":"enqueue","path":"a.flac"}`
+#[derive(Subcommand, Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Request {
+    /// Resume playback
+    Play,
+    /// Pause playback
+    Pause,
+    /// Pause if playing, resume if paused
+    PlayPause,
+    /// Pause and go back to the start of the track
+    Stop,
+    /// Skip to the next track
+    Next,
+    /// Go back to the start of the track, or to the previous one if it only just started
+    Previous,
+    /// Jump to a position in the current track
+    Seek {
+        /// Seconds from the start of the track
+        position: f64,
+    },
+    /// Set the volume
+    Volume {
+        /// Between 0 and 100
+        percent: u8,
+    },
+    /// Turn shuffle on or off
+    Shuffle,
+    /// Play the queue from a track on
+    PlayFrom {
+        /// Where the track is in the queue, counting from 0
+        index: usize,
+    },
+    /// Add a file to the end of the queue
+    Enqueue {
+        /// The file to queue
+        path: PathBuf,
+    },
+    /// Print the current track, position and queue length
+    Status,
+    /// Print the tracks in the queue
+    Queue,
+    /// Print the status every time the queue or track changes or playback is paused or resumed
+    Subscribe,
+    /// Stop the player
+    Quit,
+}
+
+/// What the player is doing
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct Status {
+    pub(crate) track: Option<Track>,
+    pub(crate) paused: bool,
+    /// Seconds into the current track
+    pub(crate) position: f64,
+    /// Where the current track is in the queue
+    pub(crate) queue_index: usize,
+    pub(crate) queue_length: usize,
+    /// Percent
+    pub(crate) volume: u8,
+    pub(crate) shuffled: bool,
+}
+
+impl Status {
+    fn of(engine: &dyn Playback) -> Self {
+        let queue = engine.queue();
+        let queue_index = engine.queue_index();
+        Self {
+            track: queue.get(queue_index).cloned(),
+            paused: engine.is_paused(),
+            position: engine.position().as_secs_f64(),
+            queue_index,
+            queue_length: queue.len(),
+            volume: (engine.volume() * 100.0).round() as u8,
+            shuffled: engine.is_shuffled(),
+        }
+    }
+}
+
+/// The answer to a request, also one JSON object per line. Subscribers get a `changed` reply for every change.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "reply", rename_all = "kebab-case")]
+pub(crate) enum Reply {
+    Ok,
+    Error { message: String },
+    Status(Status),
+    Queue { tracks: Vec<Track> },
+    Changed(Status),
+}
+
+/// Accepts connections until it's dropped, then removes the socket
+pub struct Server {
+    path: PathBuf,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Listens on the socket in `$XDG_RUNTIME_DIR` for commands to the player
+pub fn serve(core: Core) -> Result<Server> {
+    let path = socket_path()?;
+    if UnixStream::connect(&path).is_ok() {
+        return Err(anyhow!("Another minim is listening on {}", path.display()));
+    }
+    // Left behind by a player that crashed
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("Couldn't listen on {}", path.display()))?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let core = core.clone();
+            thread::spawn(move || {
+                // The client hanging up isn't the player's problem
+                let _ = handle(stream, &core);
+            });
+        }
+    });
+
+    Ok(Server { path })
+}
+
+fn send(stream: &mut UnixStream, reply: &Reply) -> io::Result<()> {
+    let mut line = serde_json::to_string(reply)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())
+}
+
+fn handle(stream: UnixStream, core: &Core) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let reply = match serde_json::from_str(&line) {
+            Ok(Request::Subscribe) => {
+                // The connection only carries changes from here on
+                let changes = status_changes(core.engine.subscribe());
+                send(&mut writer, &Reply::Ok)?;
+                while changes.recv().is_ok() {
+                    while changes.try_recv().is_ok() {}
+                    send(&mut writer, &Reply::Changed(Status::of(&*core.engine)))?;
+                }
+                return Ok(());
+            }
+            Ok(request) => run(request, core),
+            Err(e) => Reply::Error {
+                message: format!("Couldn't read request: {e}"),
+            },
+        };
+        send(&mut writer, &reply)?;
+    }
+
+    Ok(())
+}
+
+fn run(request: Request, core: &Core) -> Reply {
+    let engine = &core.engine;
+    let result = match request {
+        Request::Play => {
+            engine.play();
+            Ok(())
+        }
+        Request::Pause => {
+            engine.pause();
+            Ok(())
+        }
+        Request::PlayPause => {
+            engine.play_pause();
+            Ok(())
+        }
+        Request::Stop => {
+            engine.stop();
+            Ok(())
+        }
+        Request::Next => {
+            engine.next();
+            Ok(())
+        }
+        Request::Previous => engine.previous(),
+        Request::Seek { position } => Duration::try_from_secs_f64(position)
+            .map_err(|e| anyhow!("Bad position: {e}"))
+            .and_then(|position| engine.seek(position)),
+        Request::Volume { percent } => {
+            engine.set_volume(percent.min(100) as f32 / 100.0);
+            Ok(())
+        }
+        Request::Shuffle => {
+            engine.toggle_shuffle();
+            Ok(())
+        }
+        Request::PlayFrom { index } => engine.play_from(index),
+        Request::Enqueue { path } => find_track(core, &path)
+            .and_then(|track| engine.enqueue(&track).map_err(anyhow::Error::from)),
+        Request::Status => return Reply::Status(Status::of(&**engine)),
+        Request::Queue => {
+            return Reply::Queue {
+                tracks: engine.queue(),
+            }
+        }
+        Request::Subscribe => unreachable!("Subscriptions are handled by the connection"),
+        Request::Quit => {
+            engine.request_quit();
+            Ok(())
+        }
+    };
+
+    match result {
+        Ok(()) => Reply::Ok,
+        Err(e) => Reply::Error {
+            message: format!("{e:#}"),
+        },
+    }
+}
+
+/// The library's copy of the track at `path`, so ratings and tags stay in sync, or the file read fresh if it's
+/// outside the library
+fn find_track(core: &Core, path: &Path) -> Result<Track> {
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("Couldn't find {}", path.display()))?;
+    if let Some(track) = core.tracks.lock().unwrap().iter().find(|t| t.path == path) {
+        return Ok(track.clone());
+    }
+
+    Track::try_from(path.as_path()).with_context(|| format!("Couldn't read {}", path.display()))
+}
+
+/// Connects to the running player
+pub(crate) fn connect() -> Result<UnixStream> {
+    let path = socket_path()?;
+    UnixStream::connect(&path)
+        .with_context(|| format!("Couldn't connect to minim at {}", path.display()))
+}
+
+/// Sends `request` over `stream`, returning the replies as they come
+pub(crate) fn send_request(
+    mut stream: UnixStream,
+    request: &Request,
+) -> Result<impl Iterator<Item = Result<Reply>>> {
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+
+    Ok(BufReader::new(stream)
+        .lines()
+        .map(|line| Ok(serde_json::from_str(&line?)?)))
+}
+
+/// Sends `request` to the running player and waits for its reply. Errors from the player become `Err`s.
+pub(crate) fn request(request: &Request) -> Result<Reply> {
+    match send_request(connect()?, request)?.next() {
+        Some(Ok(Reply::Error { message })) => Err(anyhow!(message)),
+        Some(reply) => reply,
+        None => Err(anyhow!("minim hung up")),
+    }
+}
+
+/// Sends `request` to the running player and prints the replies as JSON lines
+pub(crate) fn control(request: Request) -> Result<()> {
+    // Relative paths are relative to the caller, not the player
+    let request = match request {
+        Request::Enqueue { path } => Request::Enqueue {
+            path: std::path::absolute(path)?,
+        },
+        request => request,
+    };
+    let subscribe = matches!(request, Request::Subscribe);
+
+    for reply in send_request(connect()?, &request)? {
+        match reply? {
+            Reply::Ok if subscribe => continue,
+            Reply::Ok => return Ok(()),
+            Reply::Error { message } => return Err(anyhow!(message)),
+            reply => println!("{}", serde_json::to_string(&reply)?),
+        }
+        if !subscribe {
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Error, Result};
+use cursive::event::{Event, EventResult, EventTrigger, Key};
+use cursive::Cursive;
+use serde::Deserialize;
+
+use crate::actions::Action;
+
+/// Binding a key to this in the config file removes the default binding
+const UNBIND: &str = "none";
+
+/// Named keys, as written in the config file
+const KEYS: [(&str, Key); 26] = [
+    ("enter", Key::Enter),
+    ("tab", Key::Tab),
+    ("backspace", Key::Backspace),
+    ("esc", Key::Esc),
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("up", Key::Up),
+    ("down", Key::Down),
+    ("insert", Key::Ins),
+    ("delete", Key::Del),
+    ("home", Key::Home),
+    ("end", Key::End),
+    ("pageup", Key::PageUp),
+    ("pagedown", Key::PageDown),
+    ("f1", Key::F1),
+    ("f2", Key::F2),
+    ("f3", Key::F3),
+    ("f4", Key::F4),
+    ("f5", Key::F5),
+    ("f6", Key::F6),
+    ("f7", Key::F7),
+    ("f8", Key::F8),
+    ("f9", Key::F9),
+    ("f10", Key::F10),
+    ("f11", Key::F11),
+    ("f12", Key::F12),
+];
+
+/// Parses a single key with optional modifiers, e.g. `q`, `Space`, `Ctrl-p`, `Alt-Enter` or `Ctrl-Shift-Up`
+fn parse_key(s: &str) -> Result<Event> {
+    let mut ctrl = false;
+    let mut alt = false;
+    let mut shift = false;
+
+    let mut rest = s;
+    // A lone "-" is a key in its own right
+    while let Some((modifier, after)) = rest.split_once('-').filter(|(_, a)| !a.is_empty()) {
+        match modifier.to_lowercase().as_str() {
+            "ctrl" => ctrl = true,
+            "alt" => alt = true,
+            "shift" => shift = true,
+            _ => return Err(anyhow!("Unknown modifier \"{modifier}\" in \"{s}\"")),
+        }
+        rest = after;
+    }
+
+    if rest.eq_ignore_ascii_case("space") {
+        rest = " ";
+    }
+
+    let mut chars = rest.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return match (ctrl, alt, shift) {
+            (false, false, false) => Ok(Event::Char(c)),
+            // Terminals send shifted letters as uppercase
+            (false, false, true) if c.is_alphabetic() => Ok(Event::Char(c.to_ascii_uppercase())),
+            (true, false, false) => Ok(Event::CtrlChar(c.to_ascii_lowercase())),
+            (false, true, false) => Ok(Event::AltChar(c)),
+            _ => Err(anyhow!(
+                "Terminals can't tell \"{s}\" apart from other keys"
+            )),
+        };
+    }
+
+    let name = rest.to_lowercase();
+    let key = KEYS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, k)| *k)
+        .ok_or(anyhow!("Unknown key \"{rest}\" in \"{s}\""))?;
+
+    Ok(match (ctrl, alt, shift) {
+        (false, false, false) => Event::Key(key),
+        (true, false, false) => Event::Ctrl(key),
+        (false, true, false) => Event::Alt(key),
+        (false, false, true) => Event::Shift(key),
+        (true, true, false) => Event::CtrlAlt(key),
+        (true, false, true) => Event::CtrlShift(key),
+        (false, true, true) => Event::AltShift(key),
+        (true, true, true) => return Err(anyhow!("Too many modifiers in \"{s}\"")),
+    })
+}
+
+/// Parses a sequence of keys separated by spaces, e.g. `g g` or `Ctrl-x Ctrl-c`
+fn parse_keys(s: &str) -> Result<Vec<Event>> {
+    let keys = s
+        .split_whitespace()
+        .map(parse_key)
+        .collect::<Result<Vec<_>>>()?;
+    if keys.is_empty() {
+        return Err(anyhow!("Empty key binding"));
+    }
+    Ok(keys)
+}
+
+#[derive(Clone, Debug)]
+struct Binding {
+    keys: Vec<Event>,
+    /// The keys as written, for error messages and the help overlay
+    text: String,
+    /// The action as written, including any arguments
+    command: String,
+    action: Action,
+}
+
+/// Global key bindings. A binding can be a chord of several keys pressed one after another.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "HashMap<String, String>")]
+pub(crate) struct Keymap {
+    bindings: Vec<Binding>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = [
+            ("q", Action::Quit),
+            ("p", Action::PlayPause),
+            ("n", Action::Next),
+            ("O", Action::Organize),
+            (":", Action::CommandLine),
+            ("Ctrl-p", Action::CommandPalette),
+            ("?", Action::Help),
+        ]
+        .into_iter()
+        .map(|(text, action)| Binding {
+            keys: parse_keys(text).expect("Default bindings should be valid"),
+            text: text.to_owned(),
+            command: action.name().to_owned(),
+            action,
+        })
+        .collect();
+
+        Self { bindings }
+    }
+}
+
+impl TryFrom<HashMap<String, String>> for Keymap {
+    type Error = Error;
+
+    /// Applies the bindings from the config file on top of the defaults
+    fn try_from(config: HashMap<String, String>) -> Result<Self> {
+        let mut keymap = Self::default();
+
+        // Sort so errors are reported in a stable order
+        let mut config: Vec<_> = config.into_iter().collect();
+        config.sort();
+
+        for (text, command) in config {
+            let keys = parse_keys(&text)?;
+            keymap.bindings.retain(|b| b.keys != keys);
+            if command != UNBIND {
+                keymap.bindings.push(Binding {
+                    keys,
+                    text,
+                    action: command.parse()?,
+                    command,
+                });
+            }
+        }
+
+        // Otherwise the longer chord could never be finished
+        for a in &keymap.bindings {
+            for b in &keymap.bindings {
+                if a.keys.len() < b.keys.len() && b.keys.starts_with(&a.keys) {
+                    return Err(anyhow!(
+                        "\"{}\" can't be bound to {} since it's the start of \"{}\"",
+                        a.text,
+                        a.action.name(),
+                        b.text
+                    ));
+                }
+            }
+        }
+
+        Ok(keymap)
+    }
+}
+
+impl Keymap {
+    /// Each binding's keys and the command they run, as written in the config file
+    pub(crate) fn bindings(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.bindings
+            .iter()
+            .map(|b| (b.text.as_str(), b.command.as_str()))
+    }
+
+    /// Handles key presses that no view used. Keys that start a chord are held until the chord is finished or
+    /// broken off.
+    pub(crate) fn install(&self, siv: &mut Cursive) {
+        let bindings = self.bindings.clone();
+        let pending: Arc<Mutex<Vec<Event>>> = Arc::default();
+
+        let is_key = EventTrigger::from_fn(|e| {
+            matches!(
+                e,
+                Event::Char(_)
+                    | Event::CtrlChar(_)
+                    | Event::AltChar(_)
+                    | Event::Key(_)
+                    | Event::Shift(_)
+                    | Event::Alt(_)
+                    | Event::AltShift(_)
+                    | Event::Ctrl(_)
+                    | Event::CtrlShift(_)
+                    | Event::CtrlAlt(_)
+            )
+        });
+
+        siv.set_on_event_inner(is_key, move |event| {
+            let mut pending = pending.lock().unwrap();
+            pending.push(event.clone());
+            let broke_chord = pending.len() > 1;
+
+            Self::step(&bindings, &mut pending).or_else(|| {
+                // The key broke off a chord, but it might start a new one or be bound on its own
+                if broke_chord {
+                    pending.push(event.clone());
+                    Self::step(&bindings, &mut pending)
+                } else {
+                    None
+                }
+            })
+        });
+    }
+
+    /// Runs the action bound to the keys in `pending`, or waits for more keys if they start a chord
+    fn step(bindings: &[Binding], pending: &mut Vec<Event>) -> Option<EventResult> {
+        if let Some(binding) = bindings.iter().find(|b| b.keys == *pending) {
+            pending.clear();
+            let action = binding.action.clone();
+            return Some(EventResult::with_cb_once(move |siv| action.run(siv)));
+        }
+
+        if bindings.iter().any(|b| b.keys.starts_with(pending)) {
+            return Some(EventResult::Consumed(None));
+        }
+
+        pending.clear();
+        None
+    }
+}
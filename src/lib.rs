@@ -1,9 +1,69 @@
+//! The core of the minim music player, for building other front-ends or testing against.
+//!
+//! A [`Library`] finds [`Track`]s under its roots, and an [`Engine`] plays a queue of them. Engines are driven through
+//! the [`Playback`] trait and report what happens as [`Event`]s:
+//!
+//! ```no_run
+//! use minim::{Engine, Event, Library, LibraryRoot, Playback};
+//!
+//! let library = Library::new(vec![LibraryRoot::new("/music".into())]);
+//! let (_stream, handle) = rodio::OutputStream::try_default()?;
+//! let engine = Engine::new(&handle)?;
+//! let events = engine.subscribe();
+//! for track in library.scan() {
+//!     engine.enqueue(&track)?;
+//! }
+//!
+//! for event in events {
+//!     if let Event::TrackStarted { track, .. } = event {
+//!         println!("Now playing {}", track.path().display());
+//!     }
+//! }
+//! # anyhow::Ok(())
+//! ```
+//!
+//! [`scrobble::Scrobbler`] follows those events to submit listens to ListenBrainz or Last.fm.
+
 #![forbid(unsafe_code)]
 
+mod actions;
+mod art;
+mod batch;
+mod bus;
 mod cache;
+mod config;
+mod daemon;
+mod duplicates;
+mod editor;
+mod engine;
 mod files;
+mod filter;
+mod help;
+pub mod ipc;
+mod keymap;
+mod library;
+mod lyrics;
+pub mod mpd;
+#[cfg(feature = "mpris")]
+pub mod mpris;
+#[cfg(feature = "notifications")]
+pub mod notifications;
+mod organize;
+pub mod output;
+mod palette;
 mod player;
+mod playlist;
+mod remote;
+pub mod scrobble;
+mod settings;
+mod toast;
 mod views;
+mod watcher;
 
+pub use daemon::run_daemon;
+pub use engine::{Busy, Core, Engine, Event, Playback, QueueError};
+pub use files::{Track, MAX_RATING};
+pub use library::{Library, LibraryRoot};
 pub use player::Args;
+pub use player::Command;
 pub use player::Player;
@@ -0,0 +1,199 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Error, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+use walkdir::WalkDir;
+
+use crate::files::Track;
+
+/// A root as written in the config file, before its patterns are compiled
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RootConfig {
+    path: String,
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default)]
+    extensions: Vec<String>,
+    #[serde(default)]
+    follow_symlinks: bool,
+    max_depth: Option<usize>,
+}
+
+/// A directory the library is built from, along with rules for which files under it count
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "RootConfig")]
+pub struct LibraryRoot {
+    pub(crate) path: PathBuf,
+    /// Matched against paths relative to the root
+    exclude: GlobSet,
+    /// Lowercase extensions to allow, or empty to allow anything lofty can read
+    extensions: Vec<String>,
+    follow_symlinks: bool,
+    /// How many directories deep to look, where 1 means only files directly in the root
+    max_depth: Option<usize>,
+}
+
+impl TryFrom<RootConfig> for LibraryRoot {
+    type Error = Error;
+
+    fn try_from(config: RootConfig) -> Result<Self> {
+        let path = match config.path.strip_prefix("~/") {
+            Some(rest) => dirs::home_dir()
+                .ok_or(anyhow!("Couldn't find home directory"))?
+                .join(rest),
+            None => PathBuf::from(&config.path),
+        };
+
+        let mut exclude = GlobSetBuilder::new();
+        for pattern in &config.exclude {
+            // The error already names the pattern
+            exclude.add(Glob::new(pattern)?);
+        }
+
+        if config.max_depth == Some(0) {
+            return Err(anyhow!(
+                "max_depth for {} must be at least 1",
+                path.display()
+            ));
+        }
+
+        Ok(Self {
+            path,
+            exclude: exclude.build()?,
+            extensions: config
+                .extensions
+                .iter()
+                .map(|e| e.trim_start_matches('.').to_lowercase())
+                .collect(),
+            follow_symlinks: config.follow_symlinks,
+            max_depth: config.max_depth,
+        })
+    }
+}
+
+impl LibraryRoot {
+    /// A root that includes everything under `path`
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            exclude: GlobSet::empty(),
+            extensions: Vec::new(),
+            follow_symlinks: false,
+            max_depth: None,
+        }
+    }
+
+    /// Whether `path` is a file this root would pick up, going by its path alone
+    pub(crate) fn includes(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.path) else {
+            return false;
+        };
+
+        if self
+            .max_depth
+            .is_some_and(|max| relative.components().count() > max)
+        {
+            return false;
+        }
+
+        if !self.extensions.is_empty() {
+            let ext = path
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            if !self.extensions.contains(&ext) {
+                return false;
+            }
+        }
+
+        !self.exclude.is_match(relative)
+    }
+
+    /// Files under `start`, which must be inside the root, that the root includes
+    fn walk(&self, start: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+        let mut walker = WalkDir::new(start).follow_links(self.follow_symlinks);
+        if let Some(max) = self.max_depth {
+            let depth = start
+                .strip_prefix(&self.path)
+                .map(|r| r.components().count())
+                .unwrap_or(0);
+            walker = walker.max_depth(max.saturating_sub(depth));
+        }
+
+        walker
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|f| f.file_type().is_file())
+            .map(|f| f.into_path())
+            .filter(|p| self.includes(p))
+    }
+}
+
+/// Every root the library is built from
+#[derive(Clone, Debug)]
+pub struct Library {
+    pub(crate) roots: Vec<LibraryRoot>,
+}
+
+impl Library {
+    pub fn new(roots: Vec<LibraryRoot>) -> Self {
+        Self { roots }
+    }
+
+    /// The innermost root containing `path`, whether or not its rules include it
+    fn containing(&self, path: &Path) -> Option<&LibraryRoot> {
+        self.roots
+            .iter()
+            .filter(|r| path.starts_with(&r.path))
+            .max_by_key(|r| r.path.components().count())
+    }
+
+    /// The root `path` belongs to. When roots are nested, the innermost one's rules decide.
+    pub(crate) fn root_for(&self, path: &Path) -> Option<&LibraryRoot> {
+        self.containing(path).filter(|r| r.includes(path))
+    }
+
+    /// Reads every track in every root
+    pub fn scan(&self) -> Vec<Track> {
+        let mut seen = HashSet::new();
+        self.roots
+            .iter()
+            .flat_map(|r| r.walk(&r.path))
+            // Nested roots would otherwise find the same files twice
+            .filter(|p| seen.insert(p.clone()))
+            .filter_map(|p| self.read_track(&p))
+            .collect()
+    }
+
+    /// Reads the tracks under `dir`, e.g., after a directory was moved into the library
+    pub(crate) fn scan_dir(&self, dir: &Path) -> Vec<Track> {
+        let Some(root) = self.containing(dir) else {
+            return Vec::new();
+        };
+
+        root.walk(dir).filter_map(|p| self.read_track(&p)).collect()
+    }
+
+    /// Reads the track at `path` if it belongs to the library
+    pub fn read_track(&self, path: &Path) -> Option<Track> {
+        let root = self.root_for(path)?;
+        let mut track = Track::try_from(path).ok()?;
+        track.root = root.path.clone();
+        Some(track)
+    }
+
+    /// Drops cached tracks that no longer belong to the library, e.g., after a root was removed from the config, and
+    /// records the root of the rest
+    pub(crate) fn adopt(&self, tracks: Vec<Track>) -> Vec<Track> {
+        tracks
+            .into_iter()
+            .filter_map(|mut t| {
+                t.root = self.root_for(&t.path)?.path.clone();
+                Some(t)
+            })
+            .collect()
+    }
+}
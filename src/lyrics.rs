@@ -0,0 +1,238 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use lofty::config::ParseOptions;
+use lofty::file::FileType;
+use lofty::id3::v2::{Frame, FrameFlags, FrameId, SynchronizedTextFrame, TimestampFormat};
+use lofty::mpeg::MpegFile;
+use lofty::prelude::*;
+use lofty::probe::Probe;
+
+use crate::bus::{Bus, LibraryEvent, Message};
+use crate::files::Track;
+
+/// A line of synced lyrics, shown starting at `time`
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct LyricLine {
+    pub(crate) time: Duration,
+    pub(crate) text: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Lyrics {
+    Plain(String),
+    /// Lines sorted by time
+    Synced(Vec<LyricLine>),
+}
+
+impl Lyrics {
+    /// Loads lyrics for `track`, preferring synced lyrics. Looks at ID3v2 SYLT frames, then the lyrics tag if it's
+    /// in LRC format, then a `.lrc` file next to the track, and finally falls back to the lyrics tag as plain text.
+    pub(crate) fn load(track: &Track) -> Option<Self> {
+        if let Some(lines) = Self::read_sylt(track) {
+            return Some(Self::Synced(lines));
+        }
+
+        let tag = Self::read_tag(track);
+        if let Some(lines) = tag.as_deref().and_then(parse_lrc) {
+            return Some(Self::Synced(lines));
+        }
+
+        let sidecar = fs::read_to_string(track.path.with_extension("lrc")).ok();
+        if let Some(lines) = sidecar.as_deref().and_then(parse_lrc) {
+            return Some(Self::Synced(lines));
+        }
+
+        tag.filter(|t| !t.trim().is_empty()).map(Self::Plain)
+    }
+
+    fn read_tag(track: &Track) -> Option<String> {
+        let tagged_file = Probe::open(&track.path).ok()?.read().ok()?;
+        let tag = tagged_file
+            .primary_tag()
+            .or_else(|| tagged_file.first_tag())?;
+        tag.get_string(&ItemKey::Lyrics).map(|s| s.to_owned())
+    }
+
+    /// SYLT frames aren't part of lofty's generic tags, so we have to dig them out of the ID3v2 tag ourselves
+    fn read_sylt(track: &Track) -> Option<Vec<LyricLine>> {
+        let probe = Probe::open(&track.path).ok()?.guess_file_type().ok()?;
+        if probe.file_type() != Some(FileType::Mpeg) {
+            return None;
+        }
+
+        let mut file = fs::File::open(&track.path).ok()?;
+        let mpeg = MpegFile::read_from(&mut file, ParseOptions::new()).ok()?;
+        let id = FrameId::Valid(Cow::Borrowed("SYLT"));
+        let Frame::Binary(binary) = mpeg.id3v2()?.get(&id)? else {
+            return None;
+        };
+
+        let sylt = SynchronizedTextFrame::parse(&binary.data, FrameFlags::default()).ok()?;
+        // Timestamps in MPEG frames depend on the frame size, which isn't worth the trouble
+        if sylt.timestamp_format != TimestampFormat::MS {
+            return None;
+        }
+
+        let mut lines: Vec<LyricLine> = sylt
+            .content
+            .into_iter()
+            .map(|(ms, text)| LyricLine {
+                time: Duration::from_millis(ms as u64),
+                // SYLT entries often carry their own line breaks
+                text: text.trim_matches(['\r', '\n']).to_owned(),
+            })
+            .collect();
+        lines.sort_by_key(|l| l.time);
+
+        (!lines.is_empty()).then_some(lines)
+    }
+
+    /// Index of the line that should be highlighted at `position`, if any line has started yet
+    pub(crate) fn current_line(&self, position: Duration) -> Option<usize> {
+        match self {
+            Self::Plain(_) => None,
+            Self::Synced(lines) => lines.partition_point(|l| l.time <= position).checked_sub(1),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum CacheEntry {
+    /// Being loaded by a background thread. The number tells loads apart, so a load that was invalidated halfway
+    /// through doesn't overwrite the newer one.
+    Loading(u64),
+    Loaded(Option<Arc<Lyrics>>),
+}
+
+/// Lyrics for queued tracks, loaded in the background once per track instead of reading the file every time
+#[derive(Clone)]
+pub(crate) struct LyricsCache {
+    entries: Arc<Mutex<HashMap<PathBuf, CacheEntry>>>,
+    next_load: Arc<Mutex<u64>>,
+    /// Told when a load finishes
+    bus: Bus,
+}
+
+impl LyricsCache {
+    pub(crate) fn new(bus: Bus) -> Self {
+        Self {
+            entries: Arc::default(),
+            next_load: Arc::default(),
+            bus,
+        }
+    }
+
+    /// Returns the lyrics for `track` if they're loaded, where the inner `None` means the track has no lyrics.
+    /// Returns `None` while they're still loading, and starts loading them if nobody has asked for them yet.
+    pub(crate) fn get(&self, track: &Track) -> Option<Option<Arc<Lyrics>>> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(&track.path) {
+            Some(CacheEntry::Loaded(lyrics)) => return Some(lyrics.clone()),
+            Some(CacheEntry::Loading(_)) => return None,
+            None => (),
+        }
+
+        let id = {
+            let mut next = self.next_load.lock().unwrap();
+            *next += 1;
+            *next
+        };
+        entries.insert(track.path.clone(), CacheEntry::Loading(id));
+
+        let shared = self.entries.clone();
+        let bus = self.bus.clone();
+        let track = track.clone();
+        std::thread::spawn(move || {
+            let lyrics = Lyrics::load(&track).map(Arc::new);
+            let mut entries = shared.lock().unwrap();
+            if let Some(entry) = entries.get_mut(&track.path) {
+                if matches!(entry, CacheEntry::Loading(loading) if *loading == id) {
+                    *entry = CacheEntry::Loaded(lyrics);
+                    bus.notify(Message::Library(LibraryEvent::LyricsLoaded));
+                }
+            }
+        });
+
+        None
+    }
+
+    /// Starts loading lyrics for `track` ahead of time, e.g., when it's queued
+    pub(crate) fn prefetch(&self, track: &Track) {
+        let _ = self.get(track);
+    }
+
+    /// Forgets the lyrics for `path`, so they're read again the next time they're needed. Call this whenever the
+    /// file or its `.lrc` file changes.
+    pub(crate) fn invalidate(&self, path: &Path) {
+        self.entries.lock().unwrap().remove(path);
+    }
+}
+
+/// Parses a timestamp like `mm:ss`, `mm:ss.xx` or `mm:ss.xxx`
+fn parse_timestamp(s: &str) -> Option<Duration> {
+    let (mins, secs) = s.split_once(':')?;
+    let mins: u64 = mins.trim().parse().ok()?;
+    let secs: f64 = secs.trim().parse().ok()?;
+    if !(0.0..60.0).contains(&secs) {
+        return None;
+    }
+
+    Some(Duration::from_secs(mins * 60) + Duration::from_secs_f64(secs))
+}
+
+/// Parses LRC lyrics, returning `None` if there aren't any timestamped lines.
+/// See <https://en.wikipedia.org/wiki/LRC_(file_format)>
+pub(crate) fn parse_lrc(text: &str) -> Option<Vec<LyricLine>> {
+    // Adjustment from an [offset:...] tag, in milliseconds
+    let mut offset: i64 = 0;
+    let mut lines = Vec::new();
+
+    for line in text.lines() {
+        let mut rest = line.trim();
+        let mut times = Vec::new();
+
+        // A line can have several timestamps when it's repeated, e.g. "[00:12.00][01:30.00]Chorus"
+        while let Some(tag) = rest.strip_prefix('[') {
+            let Some((tag, after)) = tag.split_once(']') else {
+                break;
+            };
+
+            if let Some(time) = parse_timestamp(tag) {
+                times.push(time);
+            } else if let Some(value) = tag.strip_prefix("offset:") {
+                offset = value.trim().parse().unwrap_or(0);
+            }
+            // Other tags like [ar:Artist] are metadata we don't need
+            rest = after;
+        }
+
+        for time in times {
+            lines.push((time, rest.trim().to_owned()));
+        }
+    }
+
+    if lines.is_empty() {
+        return None;
+    }
+
+    // A positive offset means the lyrics should show up sooner
+    let mut lines: Vec<LyricLine> = lines
+        .into_iter()
+        .map(|(time, text)| LyricLine {
+            time: if offset >= 0 {
+                time.saturating_sub(Duration::from_millis(offset as u64))
+            } else {
+                time + Duration::from_millis(offset.unsigned_abs())
+            },
+            text,
+        })
+        .collect();
+    lines.sort_by_key(|l| l.time);
+
+    Some(lines)
+}
@@ -3,10 +3,17 @@
 use anyhow::Result;
 use clap::Parser;
 
-use minim::{Args, Player};
+use minim::{run_daemon, Args, Player};
 
 fn main() -> Result<()> {
-    let args = Args::parse();
+    let mut args = Args::parse();
+    if let Some(command) = args.command.take() {
+        return command.run(&args);
+    }
+    if args.daemon {
+        return run_daemon(&args);
+    }
+
     let mut player = Player::new(args)?;
 
     player.run()
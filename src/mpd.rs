@@ -0,0 +1,894 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+use crate::engine::{status_changes, Core, Playback};
+use crate::files::{CachedField, Track};
+
+/// The protocol version announced to clients. It's old enough that they stick to the commands here, e.g. `search`
+/// with tag and value pairs rather than filter expressions.
+const VERSION: &str = "0.19.0";
+
+// Error codes, as MPD numbers them
+const ACK_ARG: u32 = 2;
+const ACK_UNKNOWN: u32 = 5;
+const ACK_NO_EXIST: u32 = 50;
+const ACK_SYSTEM: u32 = 52;
+
+/// Every command that's understood, for clients that ask
+const COMMANDS: [&str; 34] = [
+    "add",
+    "clearerror",
+    "close",
+    "command_list_begin",
+    "command_list_end",
+    "command_list_ok_begin",
+    "commands",
+    "currentsong",
+    "decoders",
+    "find",
+    "idle",
+    "list",
+    "listplaylists",
+    "lsinfo",
+    "next",
+    "noidle",
+    "notcommands",
+    "outputs",
+    "pause",
+    "ping",
+    "play",
+    "playid",
+    "playlistid",
+    "playlistinfo",
+    "plchanges",
+    "previous",
+    "random",
+    "search",
+    "seek",
+    "seekcur",
+    "seekid",
+    "setvol",
+    "stats",
+    "status",
+];
+
+/// The tags songs can be searched and listed by, besides `file` and `any`
+const TAGS: [&str; 3] = ["Artist", "Album", "Title"];
+
+/// A song in the library or the queue
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Song {
+    /// Where the song is, relative to the library. Slashes separate the directories `lsinfo` browses.
+    pub file: String,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub duration: Duration,
+}
+
+impl Song {
+    fn tag(&self, name: &str) -> Option<&str> {
+        match name.to_lowercase().as_str() {
+            "file" => Some(&self.file),
+            "artist" => Some(&self.artist),
+            "album" => Some(&self.album),
+            "title" => Some(&self.title),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum State {
+    #[default]
+    Stop,
+    Play,
+    Pause,
+}
+
+/// What the player is doing
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Status {
+    pub state: State,
+    /// Where the current song is in the queue
+    pub song: Option<usize>,
+    pub elapsed: Duration,
+    /// Percent
+    pub volume: u8,
+    pub shuffle: bool,
+}
+
+/// Something a client asked the player to do
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    /// Play the song at this position in the queue, or carry on with the current one
+    Play(Option<usize>),
+    /// Pause or resume, or toggle if it isn't given
+    Pause(Option<bool>),
+    Stop,
+    Next,
+    Previous,
+    /// Queue these library songs, by file
+    Add(Vec<String>),
+    SetVolume(u8),
+    Shuffle(bool),
+    /// Jump to a position in the current song
+    Seek(Duration),
+}
+
+/// The player, as far as MPD clients are concerned
+pub trait Backend: Send + Sync + 'static {
+    fn status(&self) -> Status;
+    fn queue(&self) -> Vec<Song>;
+    fn library(&self) -> Vec<Song>;
+    /// Carries out a request, or explains why it couldn't
+    fn request(&self, request: Request) -> Result<(), String>;
+    /// Gets a message whenever the status or the queue might have changed
+    fn subscribe(&self) -> mpsc::Receiver<()>;
+}
+
+/// An error reply, which ends a command list
+struct Ack {
+    code: u32,
+    command: String,
+    message: String,
+}
+
+impl Ack {
+    fn new(code: u32, command: &str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            command: command.to_owned(),
+            message: message.into(),
+        }
+    }
+
+    fn line(&self, index: usize) -> String {
+        format!(
+            "ACK [{}@{index}] {{{}}} {}\n",
+            self.code, self.command, self.message
+        )
+    }
+}
+
+/// Splits a command into its name and arguments. Arguments with spaces are quoted, with backslashes escaping quotes
+/// and backslashes.
+fn tokenize(line: &str) -> Result<Vec<String>, &'static str> {
+    let mut args = Vec::new();
+    let mut chars = line.trim().chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let mut arg = String::new();
+        if c == '"' {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => arg.push(chars.next().ok_or("Missing closing '\"'")?),
+                    Some(c) => arg.push(c),
+                    None => return Err("Missing closing '\"'"),
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek().filter(|c| !c.is_whitespace()) {
+                arg.push(c);
+                chars.next();
+            }
+        }
+        args.push(arg);
+    }
+
+    Ok(args)
+}
+
+fn write_song(out: &mut String, song: &Song, position: Option<usize>) {
+    let _ = writeln!(out, "file: {}", song.file);
+    for (name, value) in [
+        ("Title", &song.title),
+        ("Artist", &song.artist),
+        ("Album", &song.album),
+    ] {
+        if !value.is_empty() {
+            let _ = writeln!(out, "{name}: {value}");
+        }
+    }
+    let _ = writeln!(out, "Time: {}", song.duration.as_secs());
+    let _ = writeln!(out, "duration: {:.3}", song.duration.as_secs_f64());
+    if let Some(position) = position {
+        // Songs aren't moved around in the queue much, so their position doubles as their id
+        let _ = writeln!(out, "Pos: {position}\nId: {position}");
+    }
+}
+
+/// Identifies the queue's contents, so clients can tell when to fetch it again
+fn playlist_version(queue: &[Song]) -> u32 {
+    let mut hasher = DefaultHasher::new();
+    for song in queue {
+        song.file.hash(&mut hasher);
+    }
+    hasher.finish() as u32
+}
+
+/// What `idle` can tell apart
+#[derive(PartialEq)]
+struct Snapshot {
+    status: Status,
+    files: Vec<String>,
+}
+
+impl Snapshot {
+    fn of(backend: &dyn Backend) -> Self {
+        Self {
+            // Otherwise every change would look like a seek
+            status: Status {
+                elapsed: Duration::ZERO,
+                ..backend.status()
+            },
+            files: backend.queue().into_iter().map(|s| s.file).collect(),
+        }
+    }
+
+    /// The subsystems that changed since `self`
+    fn changes(&self, now: &Self) -> Vec<&'static str> {
+        let mut changes = Vec::new();
+        if self.files != now.files {
+            changes.push("playlist");
+        }
+        if (self.status.state, self.status.song) != (now.status.state, now.status.song) {
+            changes.push("player");
+        }
+        if self.status.volume != now.status.volume {
+            changes.push("mixer");
+        }
+        if self.status.shuffle != now.status.shuffle {
+            changes.push("options");
+        }
+        changes
+    }
+}
+
+fn parse<T: std::str::FromStr>(command: &str, arg: &str) -> Result<T, Ack> {
+    arg.parse()
+        .map_err(|_| Ack::new(ACK_ARG, command, format!("Invalid argument \"{arg}\"")))
+}
+
+fn parse_seconds(command: &str, arg: &str) -> Result<Duration, Ack> {
+    Duration::try_from_secs_f64(parse(command, arg)?)
+        .map_err(|_| Ack::new(ACK_ARG, command, format!("Invalid time \"{arg}\"")))
+}
+
+/// Parses a position in the queue, or a range like `2:5` or `2:`
+fn parse_range(command: &str, arg: &str, len: usize) -> Result<std::ops::Range<usize>, Ack> {
+    let range = match arg.split_once(':') {
+        Some((start, "")) => parse(command, start)?..len,
+        Some((start, end)) => parse(command, start)?..parse(command, end)?,
+        None => {
+            let position: usize = parse(command, arg)?;
+            let end = position
+                .checked_add(1)
+                .ok_or_else(|| Ack::new(ACK_ARG, command, "Bad song index"))?;
+            position..end
+        }
+    };
+    if range.start >= len || range.end > len {
+        return Err(Ack::new(ACK_ARG, command, "Bad song index"));
+    }
+    Ok(range)
+}
+
+/// Whether `song` matches every tag and value pair, exactly or as a case-insensitive substring
+fn matches(command: &str, song: &Song, filters: &[String], exact: bool) -> Result<bool, Ack> {
+    for pair in filters.chunks(2) {
+        let [tag, value] = pair else {
+            return Err(Ack::new(
+                ACK_ARG,
+                command,
+                "Incorrect number of filter arguments",
+            ));
+        };
+        let is_match = |field: &str| {
+            if exact {
+                field == value
+            } else {
+                field.to_lowercase().contains(&value.to_lowercase())
+            }
+        };
+
+        let matched = if tag.eq_ignore_ascii_case("any") {
+            ["file", "artist", "album", "title"]
+                .iter()
+                .any(|t| song.tag(t).is_some_and(is_match))
+        } else {
+            let field = song
+                .tag(tag)
+                .ok_or_else(|| Ack::new(ACK_ARG, command, format!("Unknown tag type \"{tag}\"")))?;
+            is_match(field)
+        };
+        if !matched {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// One client
+struct Session {
+    backend: Arc<dyn Backend>,
+    seen: Snapshot,
+    /// Subsystems that changed since the client last heard about them
+    pending: BTreeSet<&'static str>,
+}
+
+impl Session {
+    fn notice_changes(&mut self) {
+        let now = Snapshot::of(&*self.backend);
+        self.pending.extend(self.seen.changes(&now));
+        self.seen = now;
+    }
+
+    /// Reports the pending changes among `subsystems`, or every one if it's empty
+    fn take_changes(&mut self, subsystems: &[String]) -> Option<String> {
+        let wanted: Vec<&'static str> = self
+            .pending
+            .iter()
+            .copied()
+            .filter(|s| subsystems.is_empty() || subsystems.iter().any(|w| w == s))
+            .collect();
+        if wanted.is_empty() {
+            return None;
+        }
+
+        let mut out = String::new();
+        for subsystem in wanted {
+            self.pending.remove(subsystem);
+            let _ = writeln!(out, "changed: {subsystem}");
+        }
+        Some(out)
+    }
+
+    fn request(&self, command: &str, request: Request) -> Result<(), Ack> {
+        self.backend
+            .request(request)
+            .map_err(|message| Ack::new(ACK_SYSTEM, command, message))
+    }
+
+    fn execute(&mut self, line: &str) -> Result<String, Ack> {
+        let args = tokenize(line).map_err(|e| Ack::new(ACK_ARG, "", e))?;
+        let Some((command, args)) = args.split_first() else {
+            return Err(Ack::new(ACK_UNKNOWN, "", "No command given"));
+        };
+        let command = command.as_str();
+        let arg = |i: usize| {
+            args.get(i)
+                .map(String::as_str)
+                .ok_or_else(|| Ack::new(ACK_ARG, command, "Missing argument"))
+        };
+        let mut out = String::new();
+
+        match command {
+            "ping" | "clearerror" => {}
+            "status" => self.status(&mut out),
+            "stats" => self.stats(&mut out),
+            "currentsong" => {
+                let queue = self.backend.queue();
+                if let Some(i) = self.backend.status().song {
+                    if let Some(song) = queue.get(i) {
+                        write_song(&mut out, song, Some(i));
+                    }
+                }
+            }
+            "playlistinfo" | "playlistid" | "plchanges" => {
+                let queue = self.backend.queue();
+                let range = match args.first() {
+                    // Every song counts as changed, which is always correct if not the least work
+                    Some(arg) if command != "plchanges" => parse_range(command, arg, queue.len())?,
+                    _ => 0..queue.len(),
+                };
+                for i in range {
+                    write_song(&mut out, &queue[i], Some(i));
+                }
+            }
+            "play" | "playid" => {
+                let position = args
+                    .first()
+                    .map(|arg| parse_range(command, arg, self.backend.queue().len()))
+                    .transpose()?
+                    .map(|r| r.start);
+                self.request(command, Request::Play(position))?;
+            }
+            "pause" => {
+                let pause = args
+                    .first()
+                    .map(|arg| parse::<u8>(command, arg))
+                    .transpose()?
+                    .map(|p| p != 0);
+                self.request(command, Request::Pause(pause))?;
+            }
+            "stop" => self.request(command, Request::Stop)?,
+            "next" => self.request(command, Request::Next)?,
+            "previous" => self.request(command, Request::Previous)?,
+            "setvol" => {
+                let volume: u8 = parse(command, arg(0)?)?;
+                if volume > 100 {
+                    return Err(Ack::new(ACK_ARG, command, "Invalid volume value"));
+                }
+                self.request(command, Request::SetVolume(volume))?;
+            }
+            "random" => {
+                let on = parse::<u8>(command, arg(0)?)? != 0;
+                self.request(command, Request::Shuffle(on))?;
+            }
+            "seekcur" => {
+                let time = arg(0)?;
+                let elapsed = self.backend.status().elapsed;
+                let target = if let Some(by) = time.strip_prefix('+') {
+                    elapsed + parse_seconds(command, by)?
+                } else if let Some(by) = time.strip_prefix('-') {
+                    elapsed.saturating_sub(parse_seconds(command, by)?)
+                } else {
+                    parse_seconds(command, time)?
+                };
+                self.request(command, Request::Seek(target))?;
+            }
+            "seek" | "seekid" => {
+                let position: usize = parse(command, arg(0)?)?;
+                if self.backend.status().song != Some(position) {
+                    return Err(Ack::new(
+                        ACK_ARG,
+                        command,
+                        "Only the current song can be seeked",
+                    ));
+                }
+                self.request(command, Request::Seek(parse_seconds(command, arg(1)?)?))?;
+            }
+            "add" => {
+                let uri = arg(0)?.trim_end_matches('/');
+                let files: Vec<String> = self
+                    .backend
+                    .library()
+                    .into_iter()
+                    .map(|s| s.file)
+                    .filter(|f| {
+                        uri.is_empty()
+                            || f == uri
+                            || f.strip_prefix(uri).is_some_and(|r| r.starts_with('/'))
+                    })
+                    .collect();
+                if files.is_empty() {
+                    return Err(Ack::new(ACK_NO_EXIST, command, "No such song"));
+                }
+                self.request(command, Request::Add(files))?;
+            }
+            "lsinfo" => self.lsinfo(args.first().map_or("", String::as_str), &mut out)?,
+            "search" | "find" => {
+                for song in self.backend.library() {
+                    if matches(command, &song, args, command == "find")? {
+                        write_song(&mut out, &song, None);
+                    }
+                }
+            }
+            "list" => {
+                let tag = arg(0)?;
+                let name = TAGS
+                    .iter()
+                    .find(|t| t.eq_ignore_ascii_case(tag))
+                    .ok_or_else(|| {
+                        Ack::new(ACK_ARG, command, format!("Unknown tag type \"{tag}\""))
+                    })?;
+                let mut values = BTreeSet::new();
+                for song in self.backend.library() {
+                    if matches(command, &song, &args[1..], true)? {
+                        values.insert(song.tag(name).unwrap_or_default().to_owned());
+                    }
+                }
+                for value in values {
+                    let _ = writeln!(out, "{name}: {value}");
+                }
+            }
+            "commands" => {
+                for name in COMMANDS {
+                    let _ = writeln!(out, "command: {name}");
+                }
+            }
+            "tagtypes" => {
+                for name in TAGS {
+                    let _ = writeln!(out, "tagtype: {name}");
+                }
+            }
+            "outputs" => out.push_str("outputid: 0\noutputname: minim\noutputenabled: 1\n"),
+            "notcommands" | "urlhandlers" | "decoders" | "listplaylists" => {}
+            _ => {
+                return Err(Ack::new(
+                    ACK_UNKNOWN,
+                    command,
+                    format!("unknown command \"{command}\""),
+                ))
+            }
+        }
+
+        Ok(out)
+    }
+
+    fn status(&self, out: &mut String) {
+        let status = self.backend.status();
+        let queue = self.backend.queue();
+        let state = match status.state {
+            State::Stop => "stop",
+            State::Play => "play",
+            State::Pause => "pause",
+        };
+
+        let _ = writeln!(out, "volume: {}", status.volume);
+        let _ = writeln!(out, "repeat: 0\nsingle: 0\nconsume: 0");
+        let _ = writeln!(out, "random: {}", u8::from(status.shuffle));
+        let _ = writeln!(out, "playlist: {}", playlist_version(&queue));
+        let _ = writeln!(out, "playlistlength: {}", queue.len());
+        let _ = writeln!(out, "state: {state}");
+
+        let Some((i, song)) = status.song.and_then(|i| Some((i, queue.get(i)?))) else {
+            return;
+        };
+        let _ = writeln!(out, "song: {i}\nsongid: {i}");
+        let _ = writeln!(
+            out,
+            "time: {}:{}",
+            status.elapsed.as_secs(),
+            song.duration.as_secs()
+        );
+        let _ = writeln!(out, "elapsed: {:.3}", status.elapsed.as_secs_f64());
+        let _ = writeln!(out, "duration: {:.3}", song.duration.as_secs_f64());
+        if i + 1 < queue.len() {
+            let _ = writeln!(out, "nextsong: {}\nnextsongid: {}", i + 1, i + 1);
+        }
+    }
+
+    fn stats(&self, out: &mut String) {
+        let library = self.backend.library();
+        let count = |tag: &str| {
+            library
+                .iter()
+                .filter_map(|s| s.tag(tag).filter(|t| !t.is_empty()))
+                .collect::<BTreeSet<_>>()
+                .len()
+        };
+        let playtime: u64 = library.iter().map(|s| s.duration.as_secs()).sum();
+
+        let _ = writeln!(out, "artists: {}", count("artist"));
+        let _ = writeln!(out, "albums: {}", count("album"));
+        let _ = writeln!(out, "songs: {}", library.len());
+        let _ = writeln!(out, "uptime: 0\nplaytime: 0\ndb_update: 0");
+        let _ = writeln!(out, "db_playtime: {playtime}");
+    }
+
+    /// Lists the directories and songs directly under `uri`, or the song it names
+    fn lsinfo(&self, uri: &str, out: &mut String) -> Result<(), Ack> {
+        let uri = uri.trim_matches('/');
+        let library = self.backend.library();
+        if let Some(song) = library.iter().find(|s| !uri.is_empty() && s.file == uri) {
+            write_song(out, song, None);
+            return Ok(());
+        }
+
+        let prefix = if uri.is_empty() {
+            String::new()
+        } else {
+            format!("{uri}/")
+        };
+        let mut directories = BTreeSet::new();
+        let mut songs = Vec::new();
+        for song in &library {
+            let Some(rest) = song.file.strip_prefix(&prefix) else {
+                continue;
+            };
+            match rest.split_once('/') {
+                Some((directory, _)) => {
+                    directories.insert(format!("{prefix}{directory}"));
+                }
+                None => songs.push(song),
+            }
+        }
+        if !uri.is_empty() && directories.is_empty() && songs.is_empty() {
+            return Err(Ack::new(ACK_NO_EXIST, "lsinfo", "No such directory"));
+        }
+
+        songs.sort_by(|a, b| a.file.cmp(&b.file));
+        for directory in directories {
+            let _ = writeln!(out, "directory: {directory}");
+        }
+        for song in songs {
+            write_song(out, song, None);
+        }
+        Ok(())
+    }
+}
+
+enum Input {
+    Line(String),
+    Changed,
+    Closed,
+}
+
+fn handle(stream: TcpStream, backend: Arc<dyn Backend>) -> io::Result<()> {
+    // Lines and changes arrive on the same channel, since either can end an idle
+    let (tx, inputs) = mpsc::channel();
+    let reader = stream.try_clone()?;
+    let lines = tx.clone();
+    thread::spawn(move || {
+        for line in BufReader::new(reader).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if lines.send(Input::Line(line)).is_err() {
+                return;
+            }
+        }
+        let _ = lines.send(Input::Closed);
+    });
+    let changes = backend.subscribe();
+    thread::spawn(move || {
+        while changes.recv().is_ok() {
+            if tx.send(Input::Changed).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut writer = stream;
+    let mut session = Session {
+        seen: Snapshot::of(&*backend),
+        backend,
+        pending: BTreeSet::new(),
+    };
+    // The subsystems an idling client is waiting for
+    let mut idle: Option<Vec<String>> = None;
+    // Commands collected between `command_list_begin` and `command_list_end`, and whether each gets a `list_OK`
+    let mut list: Option<(bool, Vec<String>)> = None;
+
+    writer.write_all(format!("OK MPD {VERSION}\n").as_bytes())?;
+    for input in inputs {
+        let line = match input {
+            Input::Closed => break,
+            Input::Changed => {
+                session.notice_changes();
+                if let Some(changes) = idle.as_deref().and_then(|s| session.take_changes(s)) {
+                    writer.write_all(format!("{changes}OK\n").as_bytes())?;
+                    idle = None;
+                }
+                continue;
+            }
+            Input::Line(line) => line,
+        };
+
+        if let Some(subsystems) = idle.take() {
+            if line.trim() != "noidle" {
+                // Only noidle is allowed while idling, and MPD hangs up on anything else
+                break;
+            }
+            let changes = session.take_changes(&subsystems).unwrap_or_default();
+            writer.write_all(format!("{changes}OK\n").as_bytes())?;
+            continue;
+        }
+
+        if let Some((list_ok, commands)) = &mut list {
+            if line.trim() != "command_list_end" {
+                commands.push(line);
+                continue;
+            }
+
+            let mut reply = String::new();
+            let mut failed = false;
+            for (i, command) in commands.iter().enumerate() {
+                match session.execute(command) {
+                    Ok(out) => {
+                        reply.push_str(&out);
+                        if *list_ok {
+                            reply.push_str("list_OK\n");
+                        }
+                    }
+                    Err(ack) => {
+                        reply.push_str(&ack.line(i));
+                        failed = true;
+                        break;
+                    }
+                }
+            }
+            if !failed {
+                reply.push_str("OK\n");
+            }
+            writer.write_all(reply.as_bytes())?;
+            list = None;
+            continue;
+        }
+
+        let args = tokenize(&line).unwrap_or_default();
+        match args.first().map(String::as_str) {
+            Some("close") => break,
+            Some("command_list_begin") => list = Some((false, Vec::new())),
+            Some("command_list_ok_begin") => list = Some((true, Vec::new())),
+            Some("idle") => {
+                session.notice_changes();
+                let subsystems = args[1..].to_vec();
+                match session.take_changes(&subsystems) {
+                    Some(changes) => writer.write_all(format!("{changes}OK\n").as_bytes())?,
+                    None => idle = Some(subsystems),
+                }
+            }
+            // A stray noidle is answered like an idle that ended straight away
+            Some("noidle") => writer.write_all(b"OK\n")?,
+            _ => {
+                let reply = match session.execute(&line) {
+                    Ok(out) => format!("{out}OK\n"),
+                    Err(ack) => ack.line(0),
+                };
+                writer.write_all(reply.as_bytes())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// An MPD server. It accepts clients until the program exits.
+pub struct Server {
+    address: SocketAddr,
+}
+
+impl Server {
+    pub fn bind(address: impl ToSocketAddrs, backend: impl Backend) -> Result<Self> {
+        let listener = TcpListener::bind(address).context("Couldn't listen for MPD clients")?;
+        let address = listener.local_addr()?;
+        let backend: Arc<dyn Backend> = Arc::new(backend);
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let backend = backend.clone();
+                // A client going away mid-reply isn't the player's problem
+                thread::spawn(move || handle(stream, backend));
+            }
+        });
+
+        Ok(Self { address })
+    }
+
+    /// Where clients can connect, e.g. to find the port when bound to port 0
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+/// Lets MPD clients control the player
+struct PlayerBackend {
+    core: Core,
+}
+
+impl PlayerBackend {
+    /// Paths relative to the library root. With several roots, they're told apart by their directory's name.
+    fn uri(&self, track: &Track) -> String {
+        let relative = track.path.strip_prefix(&track.root).unwrap_or(&track.path);
+        let root_name = track.root.file_name().map(Path::new);
+        match root_name {
+            Some(name) if self.core.library.roots.len() > 1 => name.join(relative),
+            _ => relative.to_path_buf(),
+        }
+        .to_string_lossy()
+        .into_owned()
+    }
+
+    fn song(&self, track: &Track) -> Song {
+        Song {
+            file: self.uri(track),
+            title: track.cached_field_string(CachedField::Title),
+            artist: track.cached_field_string(CachedField::Artist),
+            album: track.cached_field_string(CachedField::Album),
+            duration: Duration::from_secs(track.duration()),
+        }
+    }
+}
+
+impl Backend for PlayerBackend {
+    fn status(&self) -> Status {
+        let engine = &self.core.engine;
+        let index = engine.queue_index();
+        let playing = engine.current().is_some();
+
+        Status {
+            state: match (playing, engine.is_paused()) {
+                (false, _) => State::Stop,
+                (true, true) if engine.is_stopped() => State::Stop,
+                (true, true) => State::Pause,
+                (true, false) => State::Play,
+            },
+            song: playing.then_some(index),
+            elapsed: engine.position(),
+            volume: (engine.volume() * 100.0).round() as u8,
+            shuffle: engine.is_shuffled(),
+        }
+    }
+
+    fn queue(&self) -> Vec<Song> {
+        let queue = self.core.engine.queue();
+        queue.iter().map(|t| self.song(t)).collect()
+    }
+
+    fn library(&self) -> Vec<Song> {
+        let tracks = self.core.tracks.lock().unwrap();
+        tracks
+            .iter()
+            .filter(|t| !t.hidden)
+            .map(|t| self.song(t))
+            .collect()
+    }
+
+    fn request(&self, request: Request) -> Result<(), String> {
+        let engine = &self.core.engine;
+        match request {
+            Request::Play(position) => {
+                let index = engine.queue_index();
+                let len = engine.queue().len();
+                // Once the queue has run out, playing starts it over
+                match position.or((index >= len && len > 0).then_some(0)) {
+                    Some(start) => engine.play_from(start).map_err(|e| e.to_string())?,
+                    None => engine.play(),
+                }
+            }
+            Request::Pause(pause) => {
+                if pause.unwrap_or(!engine.is_paused()) {
+                    engine.pause();
+                } else {
+                    engine.play();
+                }
+            }
+            Request::Stop => engine.stop(),
+            Request::Next => engine.next(),
+            Request::Previous => engine.previous().map_err(|e| e.to_string())?,
+            Request::Add(files) => {
+                let tracks: Vec<Track> = {
+                    let library = self.core.tracks.lock().unwrap();
+                    let by_uri: HashMap<String, &Track> =
+                        library.iter().map(|t| (self.uri(t), t)).collect();
+                    files
+                        .iter()
+                        .filter_map(|f| by_uri.get(f).copied().cloned())
+                        .collect()
+                };
+                for track in tracks {
+                    engine.enqueue(&track).map_err(|e| {
+                        let title = track.cached_field_string(CachedField::Title);
+                        format!("Couldn't queue {title}: {e}")
+                    })?;
+                }
+            }
+            Request::SetVolume(volume) => engine.set_volume(volume.min(100) as f32 / 100.0),
+            Request::Shuffle(on) => {
+                if on != engine.is_shuffled() {
+                    engine.toggle_shuffle();
+                }
+            }
+            Request::Seek(position) => engine.seek(position).map_err(|e| e.to_string())?,
+        }
+        Ok(())
+    }
+
+    fn subscribe(&self) -> mpsc::Receiver<()> {
+        status_changes(self.core.engine.subscribe())
+    }
+}
+
+/// Starts accepting MPD clients for the player
+pub(crate) fn start(address: SocketAddr, core: Core) -> Result<Server> {
+    Server::bind(address, PlayerBackend { core })
+}
@@ -0,0 +1,447 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+use zbus::blocking::{connection, Connection};
+use zbus::fdo;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+
+use crate::art;
+use crate::engine::{status_changes, Core, Playback};
+use crate::files::CachedField;
+
+/// The bus name MPRIS clients look for. A second instance gets a unique suffix.
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.minim";
+
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+
+/// Prefix for the ids of queued tracks, which are object paths
+const TRACK_PATH: &str = "/org/minim/queue";
+
+/// Something an MPRIS client asked the player to do
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    PlayPause,
+    Play,
+    Pause,
+    Stop,
+    Next,
+    Previous,
+    /// Move by this many microseconds, backwards if it's negative
+    Seek(i64),
+    SetPosition(Duration),
+    /// Between 0 and 1
+    SetVolume(f64),
+    Quit,
+}
+
+/// The current track, as MPRIS clients see it
+#[derive(Clone, Debug, Default)]
+pub struct TrackInfo {
+    /// Where the track is in the queue
+    pub index: usize,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub length: Duration,
+    /// An image file with the cover
+    pub art: Option<PathBuf>,
+}
+
+/// What the player is doing
+#[derive(Clone, Debug, Default)]
+pub struct Status {
+    /// Whether there's a current track, even if it's paused
+    pub loaded: bool,
+    pub paused: bool,
+    pub position: Duration,
+    /// Between 0 and 1
+    pub volume: f64,
+}
+
+/// The player, as far as the MPRIS server is concerned. Requests should be handled without blocking, since D-Bus
+/// calls wait for them.
+pub trait Controls: Send + Sync + 'static {
+    /// Asked for by nearly every property, so it should be cheap
+    fn status(&self) -> Status;
+    /// Only asked for when a client needs the track's details, since finding its cover can take a while
+    fn track(&self) -> Option<TrackInfo>;
+    fn request(&self, request: Request);
+}
+
+fn owned<'a>(value: impl Into<Value<'a>>) -> OwnedValue {
+    value
+        .into()
+        .try_into()
+        .expect("Values without file descriptors can always be owned")
+}
+
+fn track_id(index: usize) -> ObjectPath<'static> {
+    ObjectPath::try_from(format!("{TRACK_PATH}/{index}")).expect("Track ids are valid paths")
+}
+
+fn micros(duration: Duration) -> i64 {
+    duration.as_micros().try_into().unwrap_or(i64::MAX)
+}
+
+/// The `org.mpris.MediaPlayer2` interface, which describes the player itself
+struct Root {
+    controls: Arc<dyn Controls>,
+}
+
+#[zbus::interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    fn raise(&self) {}
+
+    fn quit(&self) {
+        self.controls.request(Request::Quit);
+    }
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn identity(&self) -> &str {
+        "minim"
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// The `org.mpris.MediaPlayer2.Player` interface, which controls playback
+struct Player {
+    controls: Arc<dyn Controls>,
+}
+
+#[zbus::interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    fn play_pause(&self) {
+        self.controls.request(Request::PlayPause);
+    }
+
+    fn play(&self) {
+        self.controls.request(Request::Play);
+    }
+
+    fn pause(&self) {
+        self.controls.request(Request::Pause);
+    }
+
+    fn stop(&self) {
+        self.controls.request(Request::Stop);
+    }
+
+    fn next(&self) {
+        self.controls.request(Request::Next);
+    }
+
+    fn previous(&self) {
+        self.controls.request(Request::Previous);
+    }
+
+    async fn seek(&self, offset: i64, #[zbus(signal_emitter)] emitter: SignalEmitter<'_>) {
+        let position = micros(self.controls.status().position).saturating_add(offset);
+        self.controls.request(Request::Seek(offset));
+        let _ = Self::seeked(&emitter, position.max(0)).await;
+    }
+
+    async fn set_position(
+        &self,
+        track_id: ObjectPath<'_>,
+        position: i64,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) {
+        // Requests for a track that's no longer playing are ignored, as the spec says
+        let Some(track) = self.controls.track() else {
+            return;
+        };
+        if track_id != track_id_of(&track) || position < 0 || position > micros(track.length) {
+            return;
+        }
+
+        let position = Duration::from_micros(position as u64);
+        self.controls.request(Request::SetPosition(position));
+        let _ = Self::seeked(&emitter, micros(position)).await;
+    }
+
+    fn open_uri(&self, _uri: &str) -> fdo::Result<()> {
+        Err(fdo::Error::NotSupported("minim can't open URIs".to_owned()))
+    }
+
+    #[zbus(signal)]
+    async fn seeked(emitter: &SignalEmitter<'_>, position: i64) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> &str {
+        let status = self.controls.status();
+        match (status.loaded, status.paused) {
+            (false, _) => "Stopped",
+            (true, true) => "Paused",
+            (true, false) => "Playing",
+        }
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        let Some(track) = self.controls.track() else {
+            return HashMap::new();
+        };
+
+        let mut metadata = HashMap::from([
+            ("mpris:trackid".to_owned(), owned(track_id_of(&track))),
+            ("mpris:length".to_owned(), owned(micros(track.length))),
+            ("xesam:title".to_owned(), owned(track.title)),
+            ("xesam:album".to_owned(), owned(track.album)),
+        ]);
+        if !track.artist.is_empty() {
+            metadata.insert("xesam:artist".to_owned(), owned(vec![track.artist]));
+        }
+        if let Some(art) = track.art {
+            metadata.insert(
+                "mpris:artUrl".to_owned(),
+                owned(format!("file://{}", art.display())),
+            );
+        }
+        metadata
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        self.controls.status().volume
+    }
+
+    #[zbus(property)]
+    fn set_volume(&self, volume: f64) {
+        self.controls
+            .request(Request::SetVolume(volume.clamp(0.0, 1.0)));
+    }
+
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        micros(self.controls.status().position)
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        self.controls.status().loaded
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        self.controls.status().loaded
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        self.controls.status().loaded
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        self.controls.status().loaded
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        self.controls.status().loaded
+    }
+
+    #[zbus(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+fn track_id_of(track: &TrackInfo) -> ObjectPath<'static> {
+    track_id(track.index)
+}
+
+/// An MPRIS server on D-Bus. It keeps serving until it's dropped.
+pub struct Server {
+    connection: Connection,
+}
+
+impl Server {
+    /// Serves on the session bus
+    pub fn start(controls: impl Controls) -> Result<Self> {
+        Self::serve(connection::Builder::session()?, controls)
+    }
+
+    /// Serves on the bus at `address`, e.g. a private one started for tests
+    pub fn start_at(address: &str, controls: impl Controls) -> Result<Self> {
+        Self::serve(connection::Builder::address(address)?, controls)
+    }
+
+    fn serve(builder: connection::Builder, controls: impl Controls) -> Result<Self> {
+        let controls: Arc<dyn Controls> = Arc::new(controls);
+        let connection = builder
+            .serve_at(
+                OBJECT_PATH,
+                Root {
+                    controls: controls.clone(),
+                },
+            )?
+            .serve_at(OBJECT_PATH, Player { controls })?
+            .build()?;
+
+        if connection.request_name(BUS_NAME).is_err() {
+            // Another instance has the name already
+            connection.request_name(format!("{BUS_NAME}.instance{}", process::id()))?;
+        }
+
+        Ok(Self { connection })
+    }
+
+    /// Tells clients the track, whether it's playing or the volume changed
+    pub fn changed(&self) -> Result<()> {
+        let player = self
+            .connection
+            .object_server()
+            .interface::<_, Player>(OBJECT_PATH)?;
+        let emitter = player.signal_emitter();
+        let player = player.get();
+        zbus::block_on(async {
+            player.metadata_changed(emitter).await?;
+            player.playback_status_changed(emitter).await?;
+            player.volume_changed(emitter).await?;
+            player.can_go_next_changed(emitter).await?;
+            player.can_go_previous_changed(emitter).await?;
+            player.can_play_changed(emitter).await?;
+            player.can_pause_changed(emitter).await?;
+            player.can_seek_changed(emitter).await
+        })?;
+        Ok(())
+    }
+}
+
+/// Lets MPRIS clients control the player
+struct PlayerControls {
+    core: Core,
+    /// The cover of the last track asked for, by queue index and path, so it's only looked for once per track
+    art: Mutex<Option<(usize, PathBuf, Option<PathBuf>)>>,
+}
+
+impl Controls for PlayerControls {
+    fn status(&self) -> Status {
+        let engine = &self.core.engine;
+        Status {
+            loaded: engine.current().is_some(),
+            paused: engine.is_paused(),
+            position: engine.position(),
+            volume: engine.volume() as f64,
+        }
+    }
+
+    fn track(&self) -> Option<TrackInfo> {
+        let engine = &self.core.engine;
+        let index = engine.queue_index();
+        let track = engine.current()?;
+
+        let mut cached = self.art.lock().unwrap();
+        let art = match &*cached {
+            Some((i, path, art)) if *i == index && *path == track.path => art.clone(),
+            _ => {
+                let art = art::thumbnail_file(&track);
+                *cached = Some((index, track.path.clone(), art.clone()));
+                art
+            }
+        };
+
+        Some(TrackInfo {
+            index,
+            title: track.cached_field_string(CachedField::Title),
+            artist: track.cached_field_string(CachedField::Artist),
+            album: track.cached_field_string(CachedField::Album),
+            length: Duration::from_secs(track.duration()),
+            art,
+        })
+    }
+
+    fn request(&self, request: Request) {
+        let engine = &self.core.engine;
+        // There's nowhere to show errors, and MPRIS clients don't expect any
+        match request {
+            Request::PlayPause => engine.play_pause(),
+            Request::Play => engine.play(),
+            Request::Pause => engine.pause(),
+            Request::Stop => engine.stop(),
+            Request::Next => engine.next(),
+            Request::Previous => {
+                let _ = engine.previous();
+            }
+            Request::Seek(offset) => {
+                let by = Duration::from_micros(offset.unsigned_abs());
+                let position = engine.position();
+                let _ = engine.seek(if offset < 0 {
+                    position.saturating_sub(by)
+                } else {
+                    position + by
+                });
+            }
+            Request::SetPosition(position) => {
+                let _ = engine.seek(position);
+            }
+            Request::SetVolume(volume) => engine.set_volume(volume as f32),
+            Request::Quit => engine.request_quit(),
+        }
+    }
+}
+
+/// Starts the MPRIS server for the player, keeping clients up to date as tracks change
+pub(crate) fn start(core: Core) -> Result<()> {
+    let changes = status_changes(core.engine.subscribe());
+    let server = Server::start(PlayerControls {
+        core,
+        art: Mutex::new(None),
+    })?;
+
+    thread::spawn(move || {
+        while changes.recv().is_ok() {
+            // Coalesce bursts, e.g. skipping through several tracks
+            while changes.try_recv().is_ok() {}
+            let _ = server.changed();
+        }
+    });
+
+    Ok(())
+}
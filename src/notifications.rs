@@ -0,0 +1,141 @@
+//! Tells the desktop about each track as it starts, through whatever notification daemon is running. Each one
+//! replaces the last, so they don't pile up.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::mpsc::Receiver;
+use std::thread::{self, JoinHandle};
+
+use anyhow::Result;
+use zbus::blocking::{connection, Connection};
+use zbus::zvariant::Value;
+
+use crate::art;
+use crate::engine::Event;
+use crate::files::{CachedField, Track};
+
+/// The `org.freedesktop.Notifications` interface, as far as minim uses it
+#[zbus::proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    fn get_capabilities(&self) -> zbus::Result<Vec<String>>;
+
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+}
+
+/// What's said about a track
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Notification {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    /// An image file with the cover
+    pub art: Option<PathBuf>,
+}
+
+impl Notification {
+    fn body(&self) -> String {
+        [self.artist.as_str(), self.album.as_str()]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(" — ")
+    }
+}
+
+/// Shows notifications through the daemon on a bus, replacing its own previous one
+pub struct Notifier {
+    proxy: NotificationsProxyBlocking<'static>,
+    /// Whether the daemon reads the body as markup, in which case it has to be escaped
+    markup: bool,
+    /// The id of the notification shown last, or 0 before there's been one
+    shown: u32,
+}
+
+impl Notifier {
+    /// Connects to the notification daemon on the session bus
+    pub fn connect() -> Result<Self> {
+        Self::on(connection::Builder::session()?.build()?)
+    }
+
+    /// Connects to the notification daemon on the bus at `address`, e.g. a private one started for tests
+    pub fn connect_at(address: &str) -> Result<Self> {
+        Self::on(connection::Builder::address(address)?.build()?)
+    }
+
+    fn on(connection: Connection) -> Result<Self> {
+        let proxy = NotificationsProxyBlocking::new(&connection)?;
+        let markup = proxy.get_capabilities()?.iter().any(|c| c == "body-markup");
+        Ok(Self {
+            proxy,
+            markup,
+            shown: 0,
+        })
+    }
+
+    /// Shows `notification` in place of the last one
+    pub fn show(&mut self, notification: &Notification) -> Result<()> {
+        let mut body = notification.body();
+        if self.markup {
+            body = body
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;");
+        }
+        let mut hints = HashMap::new();
+        if let Some(art) = &notification.art {
+            hints.insert(
+                "image-path",
+                Value::from(art.to_string_lossy().into_owned()),
+            );
+        }
+
+        // If the last one was closed, the daemon hands out a new id
+        self.shown = self.proxy.notify(
+            "minim",
+            self.shown,
+            "",
+            &notification.title,
+            &body,
+            &[],
+            hints,
+            -1,
+        )?;
+        Ok(())
+    }
+
+    /// Shows a notification for each track that starts in `events`, on a thread of its own
+    pub fn follow(mut self, events: Receiver<Event>) -> JoinHandle<()> {
+        thread::spawn(move || {
+            for event in events {
+                if let Event::TrackStarted { track, .. } = event {
+                    // A daemon that's gone away shouldn't bother the player, and may come back
+                    let _ = self.show(&describe(&track));
+                }
+            }
+        })
+    }
+}
+
+fn describe(track: &Track) -> Notification {
+    Notification {
+        title: track.cached_field_string(CachedField::Title),
+        artist: track.cached_field_string(CachedField::Artist),
+        album: track.cached_field_string(CachedField::Album),
+        art: art::thumbnail_file(track),
+    }
+}
@@ -0,0 +1,349 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::files::{EditableField, Track};
+
+pub(crate) const DEFAULT_TEMPLATE: &str =
+    "{albumartist}/{year} - {album}/{disc}{track:02} {title}.{ext}";
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Literal(String),
+    Placeholder { name: String, width: usize },
+}
+
+/// A template for where a track should live under the library root, e.g. [`DEFAULT_TEMPLATE`]
+#[derive(Clone, Debug)]
+pub(crate) struct Template {
+    tokens: Vec<Token>,
+}
+
+impl Template {
+    const PLACEHOLDERS: [&'static str; 9] = [
+        "title",
+        "artist",
+        "album",
+        "albumartist",
+        "year",
+        "genre",
+        "track",
+        "disc",
+        "ext",
+    ];
+
+    pub(crate) fn parse(template: &str) -> Result<Self> {
+        let mut tokens = Vec::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                tokens.push(Token::Literal(rest[..start].to_owned()));
+            }
+
+            let after = &rest[start + 1..];
+            let end = after
+                .find('}')
+                .ok_or(anyhow!("Unclosed placeholder in \"{template}\""))?;
+            let (name, width) = match after[..end].split_once(':') {
+                Some((name, width)) => {
+                    let width = width
+                        .parse()
+                        .map_err(|_| anyhow!("Invalid width in {{{}}}", &after[..end]))?;
+                    (name, width)
+                }
+                None => (&after[..end], 0),
+            };
+
+            if !Self::PLACEHOLDERS.contains(&name) {
+                return Err(anyhow!("Unknown placeholder {{{name}}}"));
+            }
+            tokens.push(Token::Placeholder {
+                name: name.to_owned(),
+                width,
+            });
+            rest = &after[end + 1..];
+        }
+
+        if !rest.is_empty() {
+            tokens.push(Token::Literal(rest.to_owned()));
+        }
+
+        Ok(Self { tokens })
+    }
+
+    fn value(track: &Track, fields: &crate::batch::Fields, name: &str) -> String {
+        let field = |f: EditableField| fields.get(&f).cloned().unwrap_or_default();
+        let or = |s: String, default: &str| {
+            if s.is_empty() {
+                default.to_owned()
+            } else {
+                s
+            }
+        };
+
+        match name {
+            "title" => or(field(EditableField::Title), "Unknown Title"),
+            "artist" => or(field(EditableField::Artist), "Unknown Artist"),
+            "album" => or(field(EditableField::Album), "Unknown Album"),
+            "albumartist" => or(
+                or(
+                    field(EditableField::AlbumArtist),
+                    &field(EditableField::Artist),
+                ),
+                "Unknown Artist",
+            ),
+            "year" => field(EditableField::Year),
+            "genre" => field(EditableField::Genre),
+            "track" => field(EditableField::TrackNumber),
+            "disc" => field(EditableField::DiscNumber),
+            "ext" => track
+                .path
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_default(),
+            _ => unreachable!("Placeholders are checked when parsing"),
+        }
+    }
+
+    /// The path of `track` relative to the library root
+    fn render(&self, track: &Track) -> Result<PathBuf> {
+        let fields = track.read_editable_fields()?;
+        let rendered = self.fill(|name| Self::value(track, &fields, name));
+        Ok(rendered.split('/').map(sanitize).collect())
+    }
+
+    /// Puts the values in place of the placeholders. A missing value takes the text around it along, so
+    /// "{year} - {album}" doesn't leave "- Album" behind, but text between two values that are there is kept once,
+    /// so "{artist} - {year} - {album}" gives "Artist - Album".
+    fn fill(&self, value: impl Fn(&str) -> String) -> String {
+        let mut pieces = Vec::new();
+        for token in &self.tokens {
+            match token {
+                Token::Literal(l) => {
+                    for (i, part) in l.split('/').enumerate() {
+                        if i > 0 {
+                            pieces.push(Piece::Separator);
+                        }
+                        if !part.is_empty() {
+                            pieces.push(Piece::Text(part.to_owned()));
+                        }
+                    }
+                }
+                Token::Placeholder { name, width } => {
+                    let value = value(name);
+                    // Pad numbers like "{track:02}", but leave missing values empty
+                    let value = if value.is_empty() {
+                        value
+                    } else {
+                        format!("{value:0>width$}")
+                    };
+                    // Values can't introduce new directories
+                    pieces.push(Piece::Value(value.replace(['/', '\\'], "_")));
+                }
+            }
+        }
+
+        let text = |i: usize| matches!(pieces.get(i), Some(Piece::Text(_)));
+        let filled = |i: Option<usize>| matches!(i.and_then(|i| pieces.get(i)), Some(Piece::Value(v)) if !v.is_empty());
+        let mut dropped = vec![false; pieces.len()];
+        for (i, piece) in pieces.iter().enumerate() {
+            if !matches!(piece, Piece::Value(v) if v.is_empty()) {
+                continue;
+            }
+            let before = i.checked_sub(1).filter(|&j| text(j));
+            let after = Some(i + 1).filter(|&j| text(j));
+            if let Some(j) = before {
+                dropped[j] = true;
+            }
+            if let Some(j) = after {
+                let between =
+                    before.is_some_and(|b| filled(b.checked_sub(1))) && filled(Some(j + 1));
+                dropped[j] |= !between;
+            }
+        }
+
+        let mut rendered = String::new();
+        for (piece, dropped) in pieces.iter().zip(dropped) {
+            match piece {
+                _ if dropped => (),
+                Piece::Text(s) | Piece::Value(s) => rendered.push_str(s),
+                Piece::Separator => rendered.push('/'),
+            }
+        }
+        rendered
+    }
+}
+
+/// Part of a template once the values are known
+enum Piece {
+    Text(String),
+    Value(String),
+    Separator,
+}
+
+/// Replaces characters that aren't allowed in file names on common filesystems
+fn sanitize(component: &str) -> String {
+    let s: String = component
+        .chars()
+        .map(|c| match c {
+            '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    // Windows doesn't allow trailing dots or spaces, and leading ones make hidden or odd-looking files
+    let s = s.trim_matches(|c: char| c == ' ' || c == '.');
+    if s.is_empty() {
+        "_".to_owned()
+    } else {
+        s.to_owned()
+    }
+}
+
+/// A planned rename of a single file
+#[derive(Clone, Debug)]
+pub(crate) struct Move {
+    /// The library root the track stays under
+    pub(crate) root: PathBuf,
+    pub(crate) from: PathBuf,
+    pub(crate) to: PathBuf,
+}
+
+/// What organizing the library would do
+#[derive(Debug, Default)]
+pub(crate) struct Plan {
+    pub(crate) moves: Vec<Move>,
+    /// Tracks whose tags couldn't be read, e.g. because the file has gone missing. They stay where they are.
+    pub(crate) skipped: Vec<(PathBuf, anyhow::Error)>,
+}
+
+/// Works out where each track should be moved to, relative to its own library root. Tracks that are already in
+/// place are left out, and targets that would collide with an existing file or another move get a numbered suffix.
+pub(crate) fn plan(template: &Template, tracks: &[Track]) -> Plan {
+    let mut plan = Plan::default();
+    let mut taken = HashSet::new();
+
+    for track in tracks {
+        let target = match template.render(track) {
+            Ok(relative) => track.root.join(relative),
+            Err(e) => {
+                plan.skipped.push((track.path.clone(), e));
+                continue;
+            }
+        };
+
+        // Other tracks still sit at their old paths when this one moves, so any existing file is a collision
+        let mut candidate = target.clone();
+        let mut n = 2;
+        while candidate != track.path && (taken.contains(&candidate) || candidate.exists()) {
+            let stem = target
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let name = match target.extension() {
+                Some(ext) => format!("{stem} ({n}).{}", ext.to_string_lossy()),
+                None => format!("{stem} ({n})"),
+            };
+            candidate = target.with_file_name(name);
+            n += 1;
+        }
+
+        taken.insert(candidate.clone());
+        if candidate != track.path {
+            plan.moves.push(Move {
+                root: track.root.clone(),
+                from: track.path.clone(),
+                to: candidate,
+            });
+        }
+    }
+
+    plan
+}
+
+/// Performs a single move, creating directories as needed and cleaning up ones left empty under its root
+pub(crate) fn apply(m: &Move) -> Result<()> {
+    if m.to.exists() {
+        return Err(anyhow!("{} already exists", m.to.display()));
+    }
+
+    if let Some(parent) = m.to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let couldnt_move = || format!("Couldn't move {} to {}", m.from.display(), m.to.display());
+    match fs::rename(&m.from, &m.to) {
+        Ok(()) => {}
+        // Renaming can't cross filesystems, so the file is copied instead
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            // Either way it goes wrong, the copy is removed rather than left half written or as a duplicate
+            if let Err(e) = fs::copy(&m.from, &m.to).and_then(|_| fs::remove_file(&m.from)) {
+                let _ = fs::remove_file(&m.to);
+                return Err(e).with_context(couldnt_move);
+            }
+        }
+        Err(e) => return Err(e).with_context(couldnt_move),
+    }
+
+    let mut dir = m.from.parent();
+    while let Some(d) = dir {
+        if d == m.root || !d.starts_with(&m.root) || fs::remove_dir(d).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(template: &str, values: &[(&str, &str)]) -> String {
+        let values: std::collections::HashMap<_, _> = values.iter().copied().collect();
+        Template::parse(template)
+            .unwrap()
+            .fill(|name| values.get(name).copied().unwrap_or_default().to_owned())
+    }
+
+    #[test]
+    fn missing_values_take_their_separators_along() {
+        let all = [
+            ("albumartist", "Band"),
+            ("year", "1999"),
+            ("album", "Album"),
+        ];
+        assert_eq!(
+            fill("{albumartist}/{year} - {album}", &all),
+            "Band/1999 - Album"
+        );
+        assert_eq!(
+            fill(
+                "{albumartist}/{year} - {album}",
+                &[("albumartist", "Band"), ("album", "Album")]
+            ),
+            "Band/Album"
+        );
+        assert_eq!(fill("{album} ({year})", &[("album", "Album")]), "Album");
+        assert_eq!(
+            fill(
+                "{artist} - {year} - {album}",
+                &[("artist", "Band"), ("album", "Album")]
+            ),
+            "Band - Album"
+        );
+        assert_eq!(
+            fill(
+                "{disc}{track:02} {title}.{ext}",
+                &[("track", "3"), ("title", "Song"), ("ext", "mp3")]
+            ),
+            "03 Song.mp3"
+        );
+    }
+}
@@ -0,0 +1,739 @@
+//! Where an [`Engine::idle`](crate::Engine::idle)'s samples go: a sound card, a WAV file or nowhere. Each backend
+//! takes them on a thread of its own until it's dropped.
+//!
+//! ```no_run
+//! use minim::output::{Null, Pace};
+//! use minim::Engine;
+//!
+//! let (engine, samples) = Engine::idle();
+//! // Plays along in silence, e.g. on a machine without a sound card
+//! let _output = Null::start(samples, Pace::RealTime);
+//!
+//! // Or as fast as the tracks can be decoded, waiting while there's nothing to play
+//! let (engine, samples) = Engine::idle();
+//! let _output = Null::start(samples, Pace::Unlimited(engine.busy()));
+//! ```
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+use rodio::cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use rodio::cpal::{self, FromSample, Sample as _, SampleFormat, SizedSample, Stream, StreamConfig};
+use rodio::queue::SourcesQueueOutput;
+use rodio::source::UniformSourceIterator;
+use rodio::Source;
+
+use crate::Busy;
+
+/// How often to look for the chosen device again while it's missing, where there's no telling when devices change
+const RECHECK_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Where ALSA keeps a node for each sound card. Devices are only looked for again when it changes, since listing
+/// them makes ALSA complain on stderr, which would end up over the interface.
+const SOUND_CARDS: &str = "/dev/snd";
+
+/// How long to wait for a card's nodes to settle after it's plugged in
+const CARDS_SETTLE: Duration = Duration::from_secs(1);
+
+/// How much playing time a [`Null`] or [`WavFile`] takes at once
+const CHUNK: Duration = Duration::from_millis(10);
+
+/// The most sample bytes a WAV file can hold, since its sizes are 32-bit
+const WAV_LIMIT: u64 = (u32::MAX - 36) as u64;
+
+/// How many frames [`Spans`] reads ahead at most
+const SPAN_FRAMES: usize = 256;
+
+/// What the engine plays into, shared by whichever stream is playing it
+type Samples = Arc<Mutex<Spans<SourcesQueueOutput<f32>>>>;
+
+/// Somewhere an engine's samples go
+pub trait Backend: Send + Sync {
+    /// Where the samples are going, to tell the user
+    fn describe(&self) -> String;
+
+    /// The sound card playing the samples, which can be changed while playing
+    fn device(&self) -> Option<&Device> {
+        None
+    }
+}
+
+/// How fast a [`Null`] or [`WavFile`] takes samples
+#[derive(Clone, Debug)]
+pub enum Pace {
+    /// As fast as they'd be heard, so playback goes on as it would on a sound card
+    RealTime,
+    /// As fast as they can be decoded, e.g. for tests. Nothing is taken while the engine has nothing queued, so
+    /// it doesn't spin on silence.
+    Unlimited(Busy),
+}
+
+impl Pace {
+    /// Whether samples should be taken now
+    fn taking(&self) -> bool {
+        match self {
+            Pace::RealTime => true,
+            Pace::Unlimited(busy) => busy.is_busy(),
+        }
+    }
+}
+
+/// The names of the output devices there are now
+pub fn devices() -> Result<Vec<String>> {
+    let mut names: Vec<String> = cpal::default_host()
+        .output_devices()?
+        .filter_map(|device| device.name().ok())
+        .collect();
+    // Some hosts leave the default device out of the list
+    if let Some(default) = default_device().filter(|d| !names.contains(d)) {
+        names.insert(0, default);
+    }
+    Ok(names)
+}
+
+/// Finds the output device called `name`
+fn find(host: &cpal::Host, name: &str) -> Result<cpal::Device> {
+    let default = host
+        .default_output_device()
+        .filter(|d| d.name().is_ok_and(|n| n == name));
+    if let Some(device) = default {
+        return Ok(device);
+    }
+    if let Some(device) = host
+        .output_devices()?
+        .find(|d| d.name().is_ok_and(|n| n == name))
+    {
+        return Ok(device);
+    }
+
+    let names = devices().unwrap_or_default();
+    if names.is_empty() {
+        Err(anyhow!("There's no output device called \"{name}\""))
+    } else {
+        Err(anyhow!(
+            "There's no output device called \"{name}\", expected one of {}",
+            names.join(", ")
+        ))
+    }
+}
+
+/// The name of the device used when none is chosen
+pub fn default_device() -> Option<String> {
+    cpal::default_host()
+        .default_output_device()
+        .and_then(|device| device.name().ok())
+}
+
+enum Command {
+    /// Play on the named device, or the default one. With `fall_back`, a named device that can't be opened is
+    /// still chosen, and playback goes to the default one until it turns up.
+    Use {
+        device: Option<String>,
+        fall_back: bool,
+        reply: mpsc::Sender<Result<String>>,
+    },
+    /// The stream opened as this one stopped working
+    Failed(u64, String),
+    /// A sound card was plugged in or out
+    CardsChanged,
+    Stop,
+}
+
+/// Plays samples on a sound card. It can be changed while playing, and if it goes away, e.g. a USB DAC being
+/// unplugged, playback moves to the default one until it's back.
+pub struct Device {
+    commands: mpsc::Sender<Command>,
+    /// The device that was chosen, or `None` for the default one
+    chosen: Arc<Mutex<Option<String>>>,
+}
+
+impl Device {
+    /// Plays `samples` on the device called `name`, or the default device. If the named one is missing, it plays
+    /// on the default device until it turns up. That, and problems that come up later like the device going away,
+    /// are passed to `report`.
+    pub fn start(
+        samples: SourcesQueueOutput<f32>,
+        name: Option<String>,
+        report: impl Fn(String) + Send + 'static,
+    ) -> Result<Self> {
+        let (commands, received) = mpsc::channel();
+        let device = Self {
+            commands: commands.clone(),
+            chosen: Arc::default(),
+        };
+
+        let samples = Arc::new(Mutex::new(Spans::new(samples, Pace::RealTime)));
+        thread::spawn(move || {
+            let mut worker = Worker {
+                samples,
+                cards: watch_cards(commands.clone()),
+                commands,
+                stream: None,
+                opened: 0,
+                checked: Instant::now(),
+                report: Box::new(report),
+            };
+            worker.run(received);
+        });
+
+        device.request(name, true)?;
+        Ok(device)
+    }
+
+    /// The device that was chosen, or `None` for the default one. While it's missing, playback is elsewhere.
+    pub fn chosen(&self) -> Option<String> {
+        self.chosen.lock().unwrap().clone()
+    }
+
+    /// Moves playback to `device`, or the default device, and returns the name of the device it's on now. The
+    /// queue and the position in it carry on where they were.
+    pub fn switch(&self, device: Option<String>) -> Result<String> {
+        self.request(device, false)
+    }
+
+    fn request(&self, device: Option<String>, fall_back: bool) -> Result<String> {
+        let (reply, result) = mpsc::channel();
+        let command = Command::Use {
+            device: device.clone(),
+            fall_back,
+            reply,
+        };
+        self.commands
+            .send(command)
+            .map_err(|_| anyhow!("The output has stopped"))?;
+        let name = result
+            .recv()
+            .map_err(|_| anyhow!("The output has stopped"))??;
+        *self.chosen.lock().unwrap() = device;
+        Ok(name)
+    }
+}
+
+impl Backend for Device {
+    fn describe(&self) -> String {
+        self.chosen()
+            .unwrap_or_else(|| "the default output device".to_owned())
+    }
+
+    fn device(&self) -> Option<&Device> {
+        Some(self)
+    }
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Stop);
+    }
+}
+
+/// The device's thread
+struct Worker {
+    samples: Samples,
+    /// For streams to say when they stop working
+    commands: mpsc::Sender<Command>,
+    /// The stream playing now and the device it's on. There's none if no device could be opened.
+    stream: Option<(Stream, String)>,
+    /// How many streams have been opened, to tell which one failed
+    opened: u64,
+    /// When devices were last looked for while there wasn't one
+    checked: Instant,
+    /// Watches for sound cards coming and going, where that's possible. Without it, devices are looked for every
+    /// [`RECHECK_INTERVAL`].
+    cards: Option<Debouncer<RecommendedWatcher>>,
+    report: Box<dyn Fn(String) + Send>,
+}
+
+impl Worker {
+    fn run(&mut self, commands: mpsc::Receiver<Command>) {
+        let mut chosen: Option<String> = None;
+        loop {
+            // Only the default device is ever missing on purpose, and the watcher says when another might be back
+            let settled = self.stream.as_ref().is_some_and(|(_, name)| {
+                self.cards.is_some() || chosen.is_none() || chosen.as_ref() == Some(name)
+            });
+            let command = if settled {
+                commands.recv().map_err(|_| RecvTimeoutError::Disconnected)
+            } else {
+                commands.recv_timeout(self.wait())
+            };
+
+            match command {
+                Ok(Command::Use {
+                    device,
+                    fall_back,
+                    reply,
+                }) => {
+                    let mut result = self.open(device.as_deref());
+                    if let (Err(e), Some(name), true) = (&result, &device, fall_back) {
+                        // `recheck` moves back once it's there
+                        if let Ok(default) = self.open(None) {
+                            (self.report)(format!(
+                                "{e:#}. Playing on {default} until {name} is back"
+                            ));
+                            result = Ok(default);
+                        }
+                    }
+                    if result.is_ok() {
+                        chosen = device;
+                    }
+                    let _ = reply.send(result);
+                }
+                Ok(Command::Failed(stream, e)) if stream == self.opened => {
+                    let Some((_, lost)) = self.stream.take() else {
+                        continue;
+                    };
+                    let message = match self.open(chosen.as_deref()).or_else(|_| self.open(None)) {
+                        Ok(name) if name == lost => continue,
+                        Ok(name) => format!("Lost {lost} ({e}), playing on {name} instead"),
+                        Err(_) => format!("Lost {lost} ({e}), and there's no other output device"),
+                    };
+                    (self.report)(message);
+                }
+                Ok(Command::Failed(..)) => (),
+                Ok(Command::CardsChanged) => self.recheck(chosen.as_deref()),
+                Err(RecvTimeoutError::Timeout) => {
+                    if self.stream.is_none() {
+                        self.throw_away();
+                    }
+                    if self.cards.is_none() && self.checked.elapsed() >= RECHECK_INTERVAL {
+                        self.recheck(chosen.as_deref());
+                    }
+                }
+                Ok(Command::Stop) | Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    }
+
+    /// Opens `device`, or the default device, and moves playback there
+    fn open(&mut self, device: Option<&str>) -> Result<String> {
+        let host = cpal::default_host();
+        let device = match device {
+            Some(name) => find(&host, name)?,
+            None => host
+                .default_output_device()
+                .ok_or(anyhow!("There's no output device"))?,
+        };
+        let name = device.name()?;
+
+        // Only one stream pulls samples at a time
+        self.stream = None;
+        self.opened += 1;
+        let config = device.default_output_config()?;
+        let samples = UniformSourceIterator::new(
+            Shared(self.samples.clone()),
+            config.channels(),
+            config.sample_rate().0,
+        );
+        let opened = self.opened;
+        let commands = self.commands.clone();
+        let on_error = move |e: cpal::StreamError| {
+            let _ = commands.send(Command::Failed(opened, e.to_string()));
+        };
+        let stream = match config.sample_format() {
+            SampleFormat::F32 => build::<f32>(&device, &config.into(), samples, on_error),
+            SampleFormat::I16 => build::<i16>(&device, &config.into(), samples, on_error),
+            SampleFormat::U16 => build::<u16>(&device, &config.into(), samples, on_error),
+            SampleFormat::I32 => build::<i32>(&device, &config.into(), samples, on_error),
+            format => Err(anyhow!(
+                "{name} wants {format} samples, which aren't supported"
+            )),
+        }?;
+        stream.play()?;
+
+        self.stream = Some((stream, name.clone()));
+        Ok(name)
+    }
+
+    /// How long to wait for a command before checking on the devices again
+    fn wait(&self) -> Duration {
+        if self.stream.is_some() {
+            RECHECK_INTERVAL
+        } else {
+            // Samples have to keep moving, or the engine would wait on them whenever it skips
+            Duration::from_millis(100)
+        }
+    }
+
+    /// Goes back to the chosen device if it's back, or to the default one if there's no device at all
+    fn recheck(&mut self, chosen: Option<&str>) {
+        self.checked = Instant::now();
+
+        let back = match chosen {
+            Some(name) => devices().is_ok_and(|names| names.iter().any(|n| n == name)),
+            None => true,
+        };
+        if back && self.open(chosen).is_ok() {
+            return;
+        }
+        if self.stream.is_none() {
+            let _ = self.open(None);
+        }
+    }
+
+    /// Pulls as many samples as would have played by now, so playback carries on in silence
+    fn throw_away(&self) {
+        let mut samples = self.samples.lock().unwrap();
+        let per_second = samples.sample_rate() as f64 * samples.channels() as f64;
+        let count = (self.wait().as_secs_f64() * per_second) as usize;
+        samples.by_ref().take(count).for_each(drop);
+    }
+}
+
+/// Sends [`Command::CardsChanged`] whenever a sound card comes or goes, or returns `None` if that can't be watched
+fn watch_cards(commands: mpsc::Sender<Command>) -> Option<Debouncer<RecommendedWatcher>> {
+    let mut debouncer = new_debouncer(CARDS_SETTLE, move |events: DebounceEventResult| {
+        if events.is_ok() {
+            let _ = commands.send(Command::CardsChanged);
+        }
+    })
+    .ok()?;
+    debouncer
+        .watcher()
+        .watch(Path::new(SOUND_CARDS), RecursiveMode::NonRecursive)
+        .ok()?;
+    Some(debouncer)
+}
+
+fn build<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    mut samples: impl Iterator<Item = f32> + Send + 'static,
+    on_error: impl FnMut(cpal::StreamError) + Send + 'static,
+) -> Result<Stream>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            for sample in data {
+                *sample = samples.next().map(T::from_sample).unwrap_or(T::EQUILIBRIUM);
+            }
+        },
+        on_error,
+        None,
+    )?;
+    Ok(stream)
+}
+
+/// Reads from the engine's samples without taking them, so they can move to another stream
+struct Shared(Samples);
+
+impl Iterator for Shared {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        self.0.lock().unwrap().next()
+    }
+}
+
+impl Source for Shared {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.0.lock().unwrap().current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.0.lock().unwrap().channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.0.lock().unwrap().sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Passes samples on in spans of one format. The engine's queue says how long its spans are before it knows what
+/// plays next, so a track starting after silence would have its start read at the silence's rate.
+struct Spans<S> {
+    samples: S,
+    /// Whether to read more, so a backend with nothing to play stops at the end of what it has
+    pace: Pace,
+    span: VecDeque<f32>,
+    /// The first sample of the next span, with its channels and rate, if reading ahead ran into it
+    next: Option<(f32, u16, u32)>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl<S: Source<Item = f32>> Spans<S> {
+    fn new(samples: S, pace: Pace) -> Self {
+        let mut spans = Self {
+            pace,
+            channels: samples.channels(),
+            sample_rate: samples.sample_rate(),
+            samples,
+            span: VecDeque::new(),
+            next: None,
+        };
+        spans.read_ahead();
+        spans
+    }
+
+    /// Reads the next span, which is only left empty once the samples run out or there's nothing to play
+    fn read_ahead(&mut self) {
+        if self.next.is_none() && !self.pace.taking() {
+            return;
+        }
+        let Some((sample, channels, sample_rate)) = self.next.take().or_else(|| self.pull()) else {
+            return;
+        };
+        self.channels = channels;
+        self.sample_rate = sample_rate;
+        self.span.push_back(sample);
+        while self.span.len() < SPAN_FRAMES * channels as usize {
+            match self.pull() {
+                Some((sample, c, r)) if (c, r) == (channels, sample_rate) => {
+                    self.span.push_back(sample)
+                }
+                next => {
+                    self.next = next;
+                    break;
+                }
+            }
+        }
+    }
+
+    /// The next sample with the format of the source it came from
+    fn pull(&mut self) -> Option<(f32, u16, u32)> {
+        let sample = self.samples.next()?;
+        Some((sample, self.samples.channels(), self.samples.sample_rate()))
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Spans<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.span.is_empty() {
+            // Something may have been queued since it last ran out
+            self.read_ahead();
+        }
+        let sample = self.span.pop_front();
+        if self.span.is_empty() {
+            self.read_ahead();
+        }
+        sample
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Spans<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.span.len())
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Keeps a backend that takes samples in chunks from getting ahead of its [`Pace`]
+struct Clock {
+    pace: Pace,
+    started: Instant,
+    taken: Duration,
+}
+
+impl Clock {
+    fn new(pace: Pace) -> Self {
+        Self {
+            pace,
+            started: Instant::now(),
+            taken: Duration::ZERO,
+        }
+    }
+
+    /// Takes a chunk's worth of `samples`, passing each to `take`, then waits until they'd have been heard. The
+    /// rate and channels are looked up as it goes, since they change between tracks. If there's nothing to take,
+    /// it waits a chunk's time for something to be queued.
+    fn take(&mut self, samples: &mut impl Source<Item = f32>, mut take: impl FnMut(f32)) {
+        let mut taken = Duration::ZERO;
+        while taken < CHUNK {
+            let per_second = samples.sample_rate() as u64 * samples.channels() as u64;
+            let Some(sample) = samples.next() else {
+                break;
+            };
+            take(sample);
+            taken += Duration::from_nanos(1_000_000_000 / per_second.max(1));
+        }
+
+        self.taken += taken;
+        if taken.is_zero() {
+            thread::sleep(CHUNK);
+        } else if matches!(self.pace, Pace::RealTime) {
+            if let Some(ahead) = self.taken.checked_sub(self.started.elapsed()) {
+                thread::sleep(ahead);
+            }
+        }
+    }
+}
+
+/// Takes samples and throws them away, e.g. to play without a sound card
+pub struct Null {
+    stop: Arc<AtomicBool>,
+}
+
+impl Null {
+    pub fn start(samples: SourcesQueueOutput<f32>, pace: Pace) -> Self {
+        let mut samples = Spans::new(samples, pace.clone());
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        thread::spawn(move || {
+            let mut clock = Clock::new(pace);
+            while !stopped.load(Ordering::Relaxed) {
+                clock.take(&mut samples, drop);
+            }
+        });
+        Self { stop }
+    }
+}
+
+impl Backend for Null {
+    fn describe(&self) -> String {
+        "nowhere".to_owned()
+    }
+}
+
+impl Drop for Null {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Writes samples to a WAV file as 16-bit PCM
+pub struct WavFile {
+    path: PathBuf,
+    stop: Arc<AtomicBool>,
+    /// Gone once the file is finished
+    writer: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl WavFile {
+    /// Writes `samples` to a new file at `path`, converted to `channels` channels at `sample_rate`
+    pub fn create(
+        samples: SourcesQueueOutput<f32>,
+        path: impl AsRef<Path>,
+        channels: u16,
+        sample_rate: u32,
+        pace: Pace,
+    ) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file =
+            File::create(&path).with_context(|| format!("Couldn't create {}", path.display()))?;
+        let mut file = BufWriter::new(file);
+        write_wav_header(&mut file, channels, sample_rate, 0)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let mut samples =
+            UniformSourceIterator::new(Spans::new(samples, pace.clone()), channels, sample_rate);
+        let block_align = channels as u64 * 2;
+        let limit = WAV_LIMIT / block_align * block_align;
+        let writer = thread::spawn(move || {
+            let mut clock = Clock::new(pace);
+            let mut written: u64 = 0;
+            let mut result = Ok(());
+            let mut full = false;
+            while !stopped.load(Ordering::Relaxed) {
+                clock.take(&mut samples, |sample| {
+                    // Once writing fails or the file is full, samples are still taken so playback carries on
+                    if result.is_ok() {
+                        if written < limit {
+                            result = file.write_all(&i16::from_sample(sample).to_le_bytes());
+                            written += 2;
+                        } else {
+                            full = true;
+                        }
+                    }
+                });
+            }
+
+            result?;
+            file.seek(SeekFrom::Start(0))?;
+            let length = u32::try_from(written).expect("Writing stops at the limit");
+            write_wav_header(&mut file, channels, sample_rate, length)?;
+            file.flush()?;
+            if full {
+                return Err(io::Error::other(
+                    "WAV files can't hold more than 4 GiB, so the rest was left out",
+                ));
+            }
+            Ok(())
+        });
+
+        Ok(Self {
+            path,
+            stop,
+            writer: Some(writer),
+        })
+    }
+
+    /// Stops writing and fills in the header, so the file can be read
+    pub fn finish(mut self) -> Result<()> {
+        self.close()
+            .with_context(|| format!("Couldn't write {}", self.path.display()))
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        match self.writer.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(io::Error::other("The writer panicked")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Backend for WavFile {
+    fn describe(&self) -> String {
+        self.path.display().to_string()
+    }
+}
+
+impl Drop for WavFile {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+/// The 44 bytes before the samples in a PCM WAV file with `length` bytes of 16-bit samples
+fn write_wav_header(
+    file: &mut impl Write,
+    channels: u16,
+    sample_rate: u32,
+    length: u32,
+) -> io::Result<()> {
+    let block_align = channels * 2;
+    file.write_all(b"RIFF")?;
+    file.write_all(&length.saturating_add(36).to_le_bytes())?;
+    file.write_all(b"WAVEfmt ")?;
+    file.write_all(&16u32.to_le_bytes())?;
+    file.write_all(&1u16.to_le_bytes())?;
+    file.write_all(&channels.to_le_bytes())?;
+    file.write_all(&sample_rate.to_le_bytes())?;
+    file.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+    file.write_all(&block_align.to_le_bytes())?;
+    file.write_all(&16u16.to_le_bytes())?;
+    file.write_all(b"data")?;
+    file.write_all(&length.to_le_bytes())
+}
@@ -0,0 +1,124 @@
+use std::cmp::Reverse;
+
+use cursive::{
+    event::Key,
+    view::{Nameable, Resizable, Scrollable},
+    views::{Dialog, EditView, LinearLayout, OnEventView, SelectView},
+    Cursive,
+};
+
+use crate::actions::{Action, Command, COMMANDS};
+use crate::views::open_command_line;
+
+const RESULTS: &str = "palette_results";
+
+/// How many commands are listed at once
+const HEIGHT: usize = 12;
+
+type Results = SelectView<&'static Command>;
+
+/// Scores how well `query` matches `text` as a subsequence, or `None` if it doesn't. Runs of consecutive characters
+/// and matches at the start of a word score higher.
+fn fuzzy_score(query: &str, text: &str) -> Option<usize> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut start = 0;
+    let mut last = None;
+
+    for q in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let i = start + text[start..].iter().position(|&c| c == q)?;
+        score += 1;
+        if i > 0 && last == Some(i - 1) {
+            score += 2;
+        }
+        if i == 0 || !text[i - 1].is_alphanumeric() {
+            score += 3;
+        }
+        last = Some(i);
+        start = i + 1;
+    }
+
+    Some(score)
+}
+
+/// The commands matching `query`, best first. Matching names come before matching descriptions.
+fn matches(query: &str) -> Vec<&'static Command> {
+    let mut scored: Vec<(usize, &Command)> = COMMANDS
+        .iter()
+        .filter_map(|c| {
+            // Any query is a subsequence of most descriptions, so those have to contain it outright
+            let score = fuzzy_score(query, c.name).map(|s| s + 1).or_else(|| {
+                let description = c.description.to_lowercase();
+                description
+                    .contains(&query.trim().to_lowercase())
+                    .then_some(0)
+            })?;
+            Some((score, c))
+        })
+        .collect();
+    // Stable, so ties keep the registry's order
+    scored.sort_by_key(|&(score, _)| Reverse(score));
+    scored.into_iter().map(|(_, c)| c).collect()
+}
+
+fn show_matches(results: &mut Results, query: &str) {
+    results.clear();
+    for command in matches(query) {
+        results.add_item(
+            format!("{:<16}{}", command.name, command.description),
+            command,
+        );
+    }
+}
+
+fn run(siv: &mut Cursive, command: &Command) {
+    siv.pop_layer();
+    if command.args.is_some() {
+        // Let the arguments be typed on the command line
+        open_command_line(siv, &format!("{} ", command.name));
+    } else {
+        let action: Action = command
+            .name
+            .parse()
+            .expect("Commands without arguments always parse");
+        action.run(siv);
+    }
+}
+
+/// Opens a popup to search the registered commands by name or description and run one
+pub(crate) fn open(siv: &mut Cursive) {
+    let query = EditView::new()
+        .on_edit(|siv, query, _| {
+            siv.call_on_name(RESULTS, |v: &mut Results| show_matches(v, query));
+        })
+        .on_submit(|siv, _| {
+            let selection = siv
+                .call_on_name(RESULTS, |v: &mut Results| v.selection())
+                .flatten();
+            if let Some(command) = selection {
+                run(siv, &command);
+            }
+        });
+
+    // The focus stays in the query, so the arrow keys move through the results from there
+    let query = OnEventView::new(query)
+        .on_pre_event(Key::Up, |siv| {
+            siv.call_on_name(RESULTS, |v: &mut Results| v.select_up(1));
+        })
+        .on_pre_event(Key::Down, |siv| {
+            siv.call_on_name(RESULTS, |v: &mut Results| v.select_down(1));
+        });
+
+    let mut results = Results::new().on_submit(|siv, command: &&Command| run(siv, command));
+    show_matches(&mut results, "");
+
+    let layout = LinearLayout::vertical()
+        .child(query)
+        .child(results.with_name(RESULTS).scrollable().fixed_height(HEIGHT));
+
+    let dialog = OnEventView::new(Dialog::around(layout).title("Commands").fixed_width(72))
+        .on_event(Key::Esc, |siv| {
+            siv.pop_layer();
+        });
+    siv.add_layer(dialog);
+}
@@ -1,17 +1,33 @@
-use std::fs;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{anyhow, Context, Result};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use cursive::traits::*;
-use cursive::CursiveRunnable;
-use rodio::OutputStream;
-use walkdir::WalkDir;
-
+use cursive::{Cursive, CursiveRunnable};
+use rodio::queue::SourcesQueueOutput;
+
+use crate::art::Repainting;
+use crate::bus;
+use crate::config::Config;
+use crate::daemon::{notify_track_changes, Servers};
+use crate::duplicates;
+use crate::engine::{Core, Engine, Playback};
 use crate::files::Track;
-use crate::views::{PlayerView, SharedState, TrackTable, TRACKS_TABLE_VIEW_SELECTOR};
+use crate::ipc;
+use crate::library::{Library, LibraryRoot};
+use crate::organize::{self, Template, DEFAULT_TEMPLATE};
+use crate::output::{self, Backend, Device, Null, Pace, WavFile};
+use crate::remote::Remote;
+use crate::scrobble::{self, Scrobbling};
+use crate::views::{
+    handle_messages, PlayerView, SharedState, TrackTable, TRACKS_TABLE_VIEW_SELECTOR,
+};
+use crate::watcher;
 
 #[derive(Parser, Debug)]
 #[command(version, about)]
@@ -22,6 +38,189 @@ pub struct Args {
     /// Reset library cache
     #[arg(short = 'c', long = "clean")]
     disable_cache: bool,
+
+    /// Also save ratings into the files' tags (POPM for ID3, FMPS_RATING for Vorbis comments)
+    #[arg(long = "write-ratings")]
+    write_ratings: bool,
+
+    /// Play without a terminal, controlled with `minim ctl`, MPD clients or MPRIS. Running `minim` while it's
+    /// up attaches to it.
+    #[arg(long)]
+    pub daemon: bool,
+
+    /// Play on this output device instead of the default one. `minim devices` lists them.
+    #[arg(long)]
+    device: Option<String>,
+
+    /// Write what's played to this WAV file instead of a device
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["device", "null"])]
+    wav: Option<PathBuf>,
+
+    /// Play without making a sound, e.g. where there's no sound card
+    #[arg(long, conflicts_with = "device")]
+    null: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Args {
+    /// The directory given on the command line, then the roots in the config file, then the music folder
+    pub(crate) fn library(&self, config: &Config) -> Result<Library> {
+        let roots = if let Some(ref dir) = self.dir {
+            vec![LibraryRoot::new(
+                PathBuf::from_str(dir).expect("Shouldn't fail"),
+            )]
+        } else if !config.roots.is_empty() {
+            config.roots.clone()
+        } else {
+            vec![LibraryRoot::new(
+                dirs::audio_dir().ok_or(anyhow!("Couldn't find music folder"))?,
+            )]
+        };
+
+        Ok(Library { roots })
+    }
+
+    /// Starts taking `samples` to the WAV file or nowhere if asked to on the command line, otherwise to the device
+    /// given there or in the config file
+    pub(crate) fn output(
+        &self,
+        config: &Config,
+        samples: SourcesQueueOutput<f32>,
+        report: impl Fn(String) + Send + 'static,
+    ) -> Result<Arc<dyn Backend>> {
+        Ok(if let Some(path) = &self.wav {
+            Arc::new(WavFile::create(samples, path, 2, 44100, Pace::RealTime)?)
+        } else if self.null {
+            Arc::new(Null::start(samples, Pace::RealTime))
+        } else {
+            let device = self.device.clone().or_else(|| config.device.clone());
+            Arc::new(Device::start(samples, device, report)?)
+        })
+    }
+
+    /// Loads the library from the cache, or scans the library roots if there's no cache or it's disabled
+    pub(crate) fn load_tracks(&self, library: &Library) -> Result<Vec<Track>> {
+        let path = crate::cache::cache_path()?;
+
+        if !self.disable_cache {
+            if let Ok(tracks) = crate::cache::read_cache(&path) {
+                return Ok(library.adopt(tracks));
+            }
+        }
+
+        Ok(library.scan())
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Move and rename files under the library root according to their tags
+    Organize {
+        /// Where files should go, relative to the library root
+        #[arg(short, long, default_value = DEFAULT_TEMPLATE)]
+        template: String,
+
+        /// Print the planned moves without touching any files
+        #[arg(short = 'n', long = "dry-run")]
+        dry_run: bool,
+    },
+
+    /// Print groups of duplicate tracks as JSON
+    Duplicates {
+        /// How many seconds apart two copies' lengths may be
+        #[arg(short, long, default_value_t = 2)]
+        tolerance: u64,
+
+        /// Confirm matches by comparing the decoded audio. This is much slower.
+        #[arg(short, long)]
+        fingerprint: bool,
+    },
+
+    /// List the output devices that can be played on
+    Devices,
+
+    /// Control the running player
+    Ctl {
+        #[command(subcommand)]
+        request: ipc::Request,
+    },
+
+    /// Let minim scrobble to a Last.fm account, using the api_key and api_secret from the config file
+    LastfmLogin,
+}
+
+impl Command {
+    /// Runs the command without starting the player
+    pub fn run(self, args: &Args) -> Result<()> {
+        match self {
+            Command::Organize { template, dry_run } => {
+                let library = args.library(&Config::load()?)?;
+                let mut tracks = args.load_tracks(&library)?;
+                let template = Template::parse(&template)?;
+                let plan = organize::plan(&template, &tracks);
+
+                for (path, e) in &plan.skipped {
+                    eprintln!("Skipping {}: {e:#}", path.display());
+                }
+                for m in &plan.moves {
+                    println!("{} -> {}", m.from.display(), m.to.display());
+                    if dry_run {
+                        continue;
+                    }
+
+                    match organize::apply(m) {
+                        Ok(()) => {
+                            if let Some(track) = tracks.iter_mut().find(|t| t.path == m.from) {
+                                track.path = m.to.clone();
+                            }
+                        }
+                        Err(e) => eprintln!("Error: {e:#}"),
+                    }
+                }
+
+                if !dry_run {
+                    crate::cache::write_cache(&crate::cache::cache_path()?, tracks)?;
+                }
+
+                Ok(())
+            }
+            Command::Duplicates {
+                tolerance,
+                fingerprint,
+            } => {
+                let library = args.library(&Config::load()?)?;
+                let tracks = args.load_tracks(&library)?;
+                let mut groups = duplicates::find_duplicates(&tracks, tolerance);
+                if fingerprint {
+                    groups = duplicates::confirm_with_fingerprints(groups);
+                }
+
+                println!("{}", serde_json::to_string_pretty(&groups)?);
+                Ok(())
+            }
+            Command::Devices => {
+                let default = output::default_device();
+                for name in output::devices()? {
+                    if Some(&name) == default.as_ref() {
+                        println!("{name} (default)");
+                    } else {
+                        println!("{name}");
+                    }
+                }
+                Ok(())
+            }
+            Command::Ctl { request } => ipc::control(request),
+            Command::LastfmLogin => {
+                let config = Config::load()?;
+                let lastfm = config.scrobble.lastfm.ok_or(anyhow!(
+                    "Add a [scrobble.lastfm] section with an api_key and api_secret to the config file first"
+                ))?;
+                scrobble::lastfm_login(&lastfm)
+            }
+        }
+    }
 }
 
 struct Interface {
@@ -29,62 +228,80 @@ struct Interface {
 }
 
 pub struct Player {
-    // We need to hold the stream to prevent it from being dropped, even if we don't access it otherwise
-    // See https://github.com/RustAudio/rodio/issues/525
-    _stream: OutputStream,
     args: Args,
-    library_root: PathBuf,
+    library: Arc<Library>,
+    /// The engine playing here, if it isn't attached to another minim
+    core: Option<Core>,
+    mpd_address: Option<SocketAddr>,
+    scrobble: Scrobbling,
+    notifications: bool,
+    /// How often the screen is redrawn while playing
+    fps: u32,
     ui: Interface,
 }
 
 impl Player {
     pub fn new(args: Args) -> Result<Self> {
-        let (stream, handle) =
-            rodio::OutputStream::try_default().context("Error opening rodio output stream")?;
-        let sink = rodio::Sink::try_new(&handle).context("Error creating new sink")?;
-        let shared_sink = Arc::new(sink);
-        let shared_state = SharedState::new(shared_sink.clone());
-
+        let config = Config::load()?;
+        let library = Arc::new(args.library(&config)?);
+        let tracks = Arc::default();
         let mut siv = cursive::default();
-        siv.set_user_data(shared_state.clone());
-        siv.set_fps(10);
-
-        siv.add_global_callback('q', |s| s.quit());
 
-        {
-            let sink = shared_sink.clone();
-            siv.add_global_callback('p', move |_| {
-                if sink.is_paused() {
-                    sink.play();
-                } else {
-                    sink.pause();
+        let cb_sink = siv.cb_sink().clone();
+        let remote = Remote::attach(move || {
+            let _ = cb_sink.send(Box::new(|siv| {
+                if let Some(state) = siv.user_data::<SharedState>() {
+                    state
+                        .toasts
+                        .error("The minim this was attached to has stopped");
                 }
-            });
-        }
+            }));
+        })?;
+        // There's no output when attached to another minim, which does the playing
+        let (output, core, engine): (_, _, Arc<dyn Playback>) = match remote {
+            Some(remote) => (None, None, Arc::new(remote)),
+            None => {
+                let (engine, samples) = Engine::idle();
+                let cb_sink = siv.cb_sink().clone();
+                let output = args.output(&config, samples, move |e| {
+                    let _ = cb_sink.send(Box::new(move |siv| {
+                        if let Some(state) = siv.user_data::<SharedState>() {
+                            state.toasts.error(e);
+                        }
+                    }));
+                })?;
+                let core = Core {
+                    engine: Arc::new(engine),
+                    library: library.clone(),
+                    tracks: Arc::clone(&tracks),
+                };
+                let engine = core.engine.clone();
+                (Some(output), Some(core), engine)
+            }
+        };
+        let shared_state = SharedState::new(
+            engine,
+            output,
+            tracks,
+            library.clone(),
+            config.keys.clone(),
+            args.write_ratings,
+        );
 
-        {
-            let sink = shared_sink.clone();
-            let state = shared_state.clone();
-            siv.add_global_callback('n', move |_| {
-                sink.skip_one();
-                *state.queue_index.lock().unwrap() += 1;
-            });
-        }
+        siv.set_user_data(shared_state.clone());
+        config.keys.install(&mut siv);
 
-        let player_view = PlayerView::new(shared_state.clone());
+        let player_view = PlayerView::new(shared_state.clone(), &config);
         siv.add_fullscreen_layer(player_view.with_name("player").full_screen());
 
-        let library_root;
-        if let Some(ref dir) = args.dir {
-            library_root = PathBuf::from_str(dir).expect("Shouldn't fail");
-        } else {
-            library_root = dirs::audio_dir().ok_or(anyhow!("Couldn't find music folder"))?;
-        }
-
         let mut player = Player {
-            _stream: stream,
             args,
-            library_root,
+            library,
+            core,
+            mpd_address: config.mpd_address,
+            scrobble: config.scrobble,
+            notifications: config.notifications,
+            fps: config.fps,
             ui: Interface { siv },
         };
 
@@ -95,35 +312,13 @@ impl Player {
         Ok(player)
     }
 
-    fn get_tracks_from_disk(&self) -> Vec<Track> {
-        let files = WalkDir::new(&self.library_root)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|f| f.file_type().is_file());
-
-        files.flat_map(|f| Track::try_from(f.path())).collect()
-    }
-
     fn import_metadata(&mut self) -> Result<()> {
-        let mut path = dirs::cache_dir().expect("Missing cache dir?");
-        path.push("minim");
-        path.push("library.csv");
-
-        let tracks;
-        if !self.args.disable_cache {
-            if let Ok(t) = crate::cache::read_cache(&path) {
-                tracks = t;
-            } else {
-                tracks = self.get_tracks_from_disk();
-            }
-        } else {
-            tracks = self.get_tracks_from_disk();
-        }
+        let tracks = self.args.load_tracks(&self.library)?;
 
         let siv = &mut self.ui.siv;
 
         siv.call_on(&TRACKS_TABLE_VIEW_SELECTOR, |s: &mut TrackTable| {
-            s.set_items(tracks.clone());
+            s.set_items(tracks.iter().filter(|t| !t.hidden).cloned().collect());
         })
         .ok_or(anyhow!("Couldn't find tracks view while importing files?"))?;
 
@@ -163,16 +358,79 @@ impl Player {
     }
 
     pub fn run(&mut self) -> Result<()> {
-        let mut path = dirs::cache_dir().expect("Missing cache dir?");
-        path.push("minim");
-        if !fs::exists(&path)? {
-            fs::create_dir_all(&path)?;
-        }
-        path.push("library.csv");
+        let path = crate::cache::cache_path()?;
 
         self.import_metadata()?;
 
-        self.ui.siv.run();
+        let state = self
+            .ui
+            .siv
+            .user_data::<SharedState>()
+            .expect("Missing state?")
+            .clone();
+        let overlay = state.art_overlay.clone();
+
+        // Running out of inotify watches on a huge library shouldn't stop the player, it just won't notice changes
+        let _watcher = watcher::watch(self.library.clone(), state.bus.clone(), |e| {
+            state.toasts.error(e)
+        })
+        .map_err(|e| state.toasts.error(format!("{e:#}")))
+        .ok();
+
+        // Attached to another minim, its servers are the ones clients talk to
+        let _servers = match &self.core {
+            Some(core) => {
+                let toasts = state.toasts.clone();
+                if let Err(e) = self.scrobble.start(&*core.engine, move |e| toasts.error(e)) {
+                    state.toasts.error(format!("{e:#}"));
+                }
+                if self.notifications {
+                    if let Err(e) = notify_track_changes(core) {
+                        state.toasts.error(format!("{e:#}"));
+                    }
+                }
+                Some(Servers::start(core, self.mpd_address, |e| {
+                    state.toasts.error(e)
+                }))
+            }
+            None => {
+                state
+                    .toasts
+                    .info("Attached to the running minim. Quitting leaves it playing.");
+                None
+            }
+        };
+
+        // Nothing is redrawn on a timer, only when something changes
+        handle_messages(&mut self.ui.siv, Vec::new());
+        bus::pump(
+            &state.bus,
+            state.engine.clone(),
+            Duration::from_secs(1) / self.fps,
+            self.ui.siv.cb_sink().clone(),
+            handle_messages,
+        );
+
+        // Step through the event loop ourselves, so images can be drawn once cursive is done with each frame
+        let backend = cursive::backends::try_default()
+            .map_err(|e| anyhow!("Couldn't start the terminal interface: {e}"))?;
+        let backend = Repainting::new(backend);
+        let repaint = backend.full.clone();
+        let mut runner = Cursive::runner(&mut self.ui.siv, Box::new(backend));
+        runner.refresh();
+        while runner.is_running() {
+            runner.step();
+            let covered = runner.screen().len() > 1;
+            // Failing to draw cover art shouldn't take down the player
+            if overlay.lock().unwrap().flush(covered).unwrap_or(false) {
+                repaint.store(true, Ordering::Relaxed);
+                runner.clear();
+                runner.refresh();
+                repaint.store(false, Ordering::Relaxed);
+                let _ = overlay.lock().unwrap().flush(covered);
+            }
+        }
+        drop(runner);
 
         // Write state out
         let state = self.ui.siv.user_data::<SharedState>().unwrap();
@@ -0,0 +1,40 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+
+use crate::files::{CachedField, Track};
+
+/// Where playlists are saved, creating it if it doesn't exist yet
+fn playlist_dir() -> Result<PathBuf> {
+    let mut path = dirs::data_dir().ok_or(anyhow!("Couldn't find data dir"))?;
+    path.push("minim");
+    path.push("playlists");
+    fs::create_dir_all(&path)?;
+    Ok(path)
+}
+
+/// Saves `tracks` as an extended M3U playlist called `name`, returning where it was written
+pub(crate) fn save(name: &str, tracks: &[Track]) -> Result<PathBuf> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(anyhow!("\"{name}\" isn't a valid playlist name"));
+    }
+
+    let mut m3u = String::from("#EXTM3U\n");
+    for track in tracks {
+        let artist = track.cached_field_string(CachedField::Artist);
+        let title = track.cached_field_string(CachedField::Title);
+        let name = if artist.is_empty() {
+            title
+        } else {
+            format!("{artist} - {title}")
+        };
+        let _ = writeln!(m3u, "#EXTINF:{},{name}", track.duration());
+        let _ = writeln!(m3u, "{}", track.path.display());
+    }
+
+    let path = playlist_dir()?.join(format!("{name}.m3u8"));
+    fs::write(&path, m3u)?;
+    Ok(path)
+}
@@ -0,0 +1,230 @@
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+
+use crate::engine::{Event, Listeners, Playback, QueueError};
+use crate::files::Track;
+use crate::ipc::{self, Reply, Request, Status};
+
+/// What the attached player last said it was doing
+#[derive(Default)]
+struct Mirror {
+    queue: Vec<Track>,
+    /// With when it arrived, to tell how far playback has got since. It's gone once the player hangs up.
+    status: Option<(Status, Instant)>,
+}
+
+/// The engine of another minim, e.g. a daemon, driven over its control socket. Reads come from a mirror that's
+/// kept up to date by subscribing to the player's changes, and events are worked out from how the mirror changes.
+/// Position updates and playback errors aren't passed on, so followers should ask for the position as they need it.
+pub(crate) struct Remote {
+    mirror: Arc<Mutex<Mirror>>,
+    listeners: Listeners<Event>,
+}
+
+impl Remote {
+    /// Attaches to the player listening on the control socket, if there is one. `on_hangup` runs if it goes away.
+    pub(crate) fn attach(on_hangup: impl FnOnce() + Send + 'static) -> Result<Option<Self>> {
+        let Ok(stream) = ipc::connect() else {
+            return Ok(None);
+        };
+        let mut changes = ipc::send_request(stream, &Request::Subscribe)?;
+        if !matches!(changes.next(), Some(Ok(Reply::Ok))) {
+            return Err(anyhow!("The running minim didn't accept the connection"));
+        }
+
+        let remote = Self {
+            mirror: Arc::default(),
+            listeners: Listeners::default(),
+        };
+        let Reply::Status(status) = ipc::request(&Request::Status)? else {
+            return Err(anyhow!("The running minim didn't send its status"));
+        };
+        refresh(&remote.mirror, status)?;
+
+        let mirror = remote.mirror.clone();
+        let listeners = remote.listeners.clone();
+        thread::spawn(move || {
+            for change in changes {
+                let Ok(Reply::Changed(status)) = change else {
+                    break;
+                };
+                let Ok(events) = refresh(&mirror, status) else {
+                    break;
+                };
+                for event in events {
+                    listeners.notify(event);
+                }
+            }
+
+            *mirror.lock().unwrap() = Mirror::default();
+            listeners.notify(Event::QueueChanged);
+            listeners.notify(Event::StateChanged);
+            on_hangup();
+        });
+
+        Ok(Some(remote))
+    }
+
+    fn send(&self, request: Request) -> Result<()> {
+        ipc::request(&request).map(|_| ())
+    }
+
+    fn status<T>(&self, read: impl FnOnce(&Status) -> T) -> Option<T> {
+        let mirror = self.mirror.lock().unwrap();
+        mirror.status.as_ref().map(|(status, _)| read(status))
+    }
+}
+
+/// Takes in a new status, along with the queue as it is now, and returns the events that add up to the change
+fn refresh(mirror: &Mutex<Mirror>, status: Status) -> Result<Vec<Event>> {
+    let Reply::Queue { tracks } = ipc::request(&Request::Queue)? else {
+        return Err(anyhow!("The running minim didn't send its queue"));
+    };
+
+    let mut mirror = mirror.lock().unwrap();
+    let mut events = Vec::new();
+    if mirror.queue != tracks {
+        events.push(Event::QueueChanged);
+    }
+    let old = mirror.status.as_ref().map(|(old, _)| old);
+    if old.map(|o| (o.queue_index, &o.track)) != Some((status.queue_index, &status.track)) {
+        if let Some(track) = &status.track {
+            events.push(Event::TrackStarted {
+                index: status.queue_index,
+                track: track.clone(),
+            });
+        }
+    }
+    if old.map(|o| (o.paused, o.volume, o.shuffled))
+        != Some((status.paused, status.volume, status.shuffled))
+    {
+        events.push(Event::StateChanged);
+    }
+
+    *mirror = Mirror {
+        queue: tracks,
+        status: Some((status, Instant::now())),
+    };
+    Ok(events)
+}
+
+impl Playback for Remote {
+    fn queue(&self) -> Vec<Track> {
+        self.mirror.lock().unwrap().queue.clone()
+    }
+
+    fn queue_index(&self) -> usize {
+        self.status(|s| s.queue_index).unwrap_or_default()
+    }
+
+    fn position(&self) -> Duration {
+        let mirror = self.mirror.lock().unwrap();
+        let Some((status, received)) = &mirror.status else {
+            return Duration::ZERO;
+        };
+        let Some(track) = &status.track else {
+            return Duration::ZERO;
+        };
+
+        let position = Duration::from_secs_f64(status.position);
+        if status.paused {
+            position
+        } else {
+            (position + received.elapsed()).min(Duration::from_secs(track.duration()))
+        }
+    }
+
+    fn is_paused(&self) -> bool {
+        self.status(|s| s.paused).unwrap_or(true)
+    }
+
+    fn volume(&self) -> f32 {
+        self.status(|s| s.volume as f32 / 100.0).unwrap_or_default()
+    }
+
+    fn is_shuffled(&self) -> bool {
+        self.status(|s| s.shuffled).unwrap_or_default()
+    }
+
+    fn current(&self) -> Option<Track> {
+        self.status(|s| s.track.clone()).flatten()
+    }
+
+    fn play(&self) {
+        let _ = self.send(Request::Play);
+    }
+
+    fn pause(&self) {
+        let _ = self.send(Request::Pause);
+    }
+
+    fn next(&self) {
+        let _ = self.send(Request::Next);
+    }
+
+    fn previous(&self) -> Result<()> {
+        self.send(Request::Previous)
+    }
+
+    fn seek(&self, position: Duration) -> Result<()> {
+        self.send(Request::Seek {
+            position: position.as_secs_f64(),
+        })
+    }
+
+    fn set_volume(&self, volume: f32) {
+        let percent = (volume.clamp(0.0, 1.0) * 100.0).round() as u8;
+        let _ = self.send(Request::Volume { percent });
+    }
+
+    fn toggle_shuffle(&self) -> bool {
+        let _ = self.send(Request::Shuffle);
+        match ipc::request(&Request::Status) {
+            Ok(Reply::Status(status)) => status.shuffled,
+            _ => self.is_shuffled(),
+        }
+    }
+
+    fn stop(&self) {
+        let _ = self.send(Request::Stop);
+    }
+
+    fn play_from(&self, index: usize) -> Result<()> {
+        self.send(Request::PlayFrom { index })
+    }
+
+    fn enqueue(&self, track: &Track) -> Result<(), QueueError> {
+        self.send(Request::Enqueue {
+            path: track.path.clone(),
+        })
+        .map_err(|e| QueueError::Refused(format!("{e:#}")))
+    }
+
+    // Edits only reach the mirror. The attached player keeps its own library.
+
+    fn update(&self, track: &Track) {
+        for t in self.mirror.lock().unwrap().queue.iter_mut() {
+            if t == track {
+                *t = track.clone();
+            }
+        }
+        self.listeners.notify(Event::QueueChanged);
+    }
+
+    fn relocate(&self, from: &Path, to: &Path) {
+        for t in self.mirror.lock().unwrap().queue.iter_mut() {
+            if t.path == from {
+                t.path = to.to_path_buf();
+            }
+        }
+        self.listeners.notify(Event::QueueChanged);
+    }
+
+    fn subscribe(&self) -> mpsc::Receiver<Event> {
+        self.listeners.subscribe()
+    }
+}
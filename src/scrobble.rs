@@ -0,0 +1,584 @@
+//! Submits what's been listened to to ListenBrainz or Last.fm. Listens are kept on disk until a service takes
+//! them, so nothing is lost while offline.
+
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::engine::{Event, Playback};
+use crate::files::Track;
+
+/// Tracks this short are never scrobbled
+const MIN_DURATION: Duration = Duration::from_secs(30);
+
+/// Hearing this much of a track is enough, however long it is
+const ENOUGH_HEARD: Duration = Duration::from_secs(4 * 60);
+
+/// How often listens that couldn't be submitted are tried again
+const RETRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// How long to wait for a service before trying again later
+const TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Whether a track lasting `duration` counts as listened to after being heard for `heard`. It has to be longer
+/// than 30 seconds, and heard for half of it or four minutes, whichever comes first.
+pub fn should_scrobble(duration: Duration, heard: Duration) -> bool {
+    duration > MIN_DURATION && (heard >= duration / 2 || heard >= ENOUGH_HEARD)
+}
+
+/// A track that was listened to
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Listen {
+    pub artist: String,
+    pub title: String,
+    pub album: Option<String>,
+    /// In seconds
+    pub duration: u64,
+    /// When it started playing, in seconds since the Unix epoch
+    pub listened_at: u64,
+}
+
+impl Listen {
+    /// The listen for `track`, which started playing at `started`. Tracks without an artist or a title can't be
+    /// scrobbled.
+    pub fn new(track: &Track, started: SystemTime) -> Option<Self> {
+        let started = started.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Some(Self {
+            artist: track.artist()?.to_owned(),
+            title: track.title()?.to_owned(),
+            album: track.album().map(str::to_owned),
+            duration: track.duration(),
+            listened_at: started.as_secs(),
+        })
+    }
+}
+
+/// Why a service didn't take some listens
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SubmitError {
+    /// It couldn't be reached or can't take them right now, so they should be sent again later
+    Unavailable(String),
+    /// It turned them down, so sending them again won't help
+    Rejected(String),
+}
+
+impl fmt::Display for SubmitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubmitError::Unavailable(message) | SubmitError::Rejected(message) => {
+                write!(f, "{message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SubmitError {}
+
+/// A service that listens are submitted to
+pub trait Backend: Send {
+    /// Names the service in messages, and its queue on disk
+    fn name(&self) -> &str;
+    /// How many listens fit in one submission
+    fn batch_size(&self) -> usize;
+    fn submit(&self, listens: &[Listen]) -> Result<(), SubmitError>;
+}
+
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout(TIMEOUT)
+        .user_agent(concat!("minim/", env!("CARGO_PKG_VERSION")))
+        .build()
+}
+
+/// Submits to ListenBrainz, or another server with the same API
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ListenBrainz {
+    /// From <https://listenbrainz.org/settings/>
+    token: String,
+    #[serde(default = "ListenBrainz::default_url")]
+    url: String,
+}
+
+impl ListenBrainz {
+    pub fn new(token: impl Into<String>) -> Self {
+        Self {
+            token: token.into(),
+            url: Self::default_url(),
+        }
+    }
+
+    /// Submits to the server at `url` instead, e.g. one hosted at home
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    fn default_url() -> String {
+        "https://api.listenbrainz.org".to_owned()
+    }
+}
+
+// By hand, so the token doesn't end up in logs
+impl fmt::Debug for ListenBrainz {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ListenBrainz")
+            .field("token", &"<redacted>")
+            .field("url", &self.url)
+            .finish()
+    }
+}
+
+impl Backend for ListenBrainz {
+    fn name(&self) -> &str {
+        "ListenBrainz"
+    }
+
+    fn batch_size(&self) -> usize {
+        100
+    }
+
+    fn submit(&self, listens: &[Listen]) -> Result<(), SubmitError> {
+        let payload: Vec<Value> = listens
+            .iter()
+            .map(|listen| {
+                let mut metadata = json!({
+                    "artist_name": listen.artist,
+                    "track_name": listen.title,
+                    "additional_info": {
+                        "duration": listen.duration,
+                        "media_player": "minim",
+                        "submission_client": "minim",
+                        "submission_client_version": env!("CARGO_PKG_VERSION"),
+                    },
+                });
+                if let Some(album) = &listen.album {
+                    metadata["release_name"] = json!(album);
+                }
+                json!({ "listened_at": listen.listened_at, "track_metadata": metadata })
+            })
+            .collect();
+        let body = json!({
+            "listen_type": if listens.len() == 1 { "single" } else { "import" },
+            "payload": payload,
+        });
+
+        let url = format!("{}/1/submit-listens", self.url.trim_end_matches('/'));
+        let sent = agent()
+            .post(&url)
+            .set("Authorization", &format!("Token {}", self.token))
+            .set("Content-Type", "application/json")
+            .send_string(&body.to_string());
+        match sent {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(code, response)) => {
+                // Errors come back as {"code": 400, "error": "..."}
+                let message = response
+                    .into_string()
+                    .ok()
+                    .and_then(|body| serde_json::from_str::<Value>(&body).ok())
+                    .and_then(|body| body["error"].as_str().map(str::to_owned))
+                    .unwrap_or_else(|| format!("the server answered {code}"));
+                if code == 400 {
+                    Err(SubmitError::Rejected(message))
+                } else {
+                    Err(SubmitError::Unavailable(message))
+                }
+            }
+            Err(e) => Err(SubmitError::Unavailable(e.to_string())),
+        }
+    }
+}
+
+/// Submits to Last.fm, or another server with the same API
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LastFm {
+    /// From <https://www.last.fm/api/account/create>
+    api_key: String,
+    api_secret: String,
+    /// Gets listens into a particular account. `minim lastfm-login` asks Last.fm for one.
+    session_key: Option<String>,
+    #[serde(default = "LastFm::default_url")]
+    url: String,
+}
+
+impl LastFm {
+    pub fn new(
+        api_key: impl Into<String>,
+        api_secret: impl Into<String>,
+        session_key: impl Into<String>,
+    ) -> Self {
+        Self {
+            api_key: api_key.into(),
+            api_secret: api_secret.into(),
+            session_key: Some(session_key.into()),
+            url: Self::default_url(),
+        }
+    }
+
+    /// Submits to the server at `url` instead
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    fn default_url() -> String {
+        "https://ws.audioscrobbler.com/2.0/".to_owned()
+    }
+
+    /// Calls `method` with `params`, signed the way Last.fm wants
+    fn call(&self, method: &str, mut params: Vec<(String, String)>) -> Result<Value, SubmitError> {
+        params.push(("method".to_owned(), method.to_owned()));
+        params.push(("api_key".to_owned(), self.api_key.clone()));
+        // The signature is the MD5 of every parameter and value in order, then the secret
+        params.sort();
+        let mut signed: String = params.iter().map(|(k, v)| format!("{k}{v}")).collect();
+        signed.push_str(&self.api_secret);
+        params.push(("api_sig".to_owned(), format!("{:x}", md5::compute(signed))));
+        params.push(("format".to_owned(), "json".to_owned()));
+
+        let form: Vec<(&str, &str)> = params
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let response = match agent().post(&self.url).send_form(&form) {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+            Err(e) => return Err(SubmitError::Unavailable(e.to_string())),
+        };
+        let status = response.status();
+        let body: Value = response
+            .into_string()
+            .ok()
+            .and_then(|body| serde_json::from_str(&body).ok())
+            .unwrap_or_default();
+
+        // Errors come back as {"error": 9, "message": "..."}. Only bad parameters are the listens' fault; the rest
+        // is the service or the config, and the listens can wait until that's sorted out.
+        if let Some(code) = body["error"].as_u64() {
+            let message = body["message"]
+                .as_str()
+                .unwrap_or("unknown error")
+                .to_owned();
+            return Err(match code {
+                6 | 7 => SubmitError::Rejected(message),
+                _ => SubmitError::Unavailable(message),
+            });
+        }
+        if status >= 400 {
+            return Err(SubmitError::Unavailable(format!(
+                "the server answered {status}"
+            )));
+        }
+        Ok(body)
+    }
+}
+
+// By hand, so the secret and the session key don't end up in logs
+impl fmt::Debug for LastFm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LastFm")
+            .field("api_key", &self.api_key)
+            .field("api_secret", &"<redacted>")
+            .field(
+                "session_key",
+                &self.session_key.as_ref().map(|_| "<redacted>"),
+            )
+            .field("url", &self.url)
+            .finish()
+    }
+}
+
+impl Backend for LastFm {
+    fn name(&self) -> &str {
+        "Last.fm"
+    }
+
+    fn batch_size(&self) -> usize {
+        50
+    }
+
+    fn submit(&self, listens: &[Listen]) -> Result<(), SubmitError> {
+        let Some(session_key) = &self.session_key else {
+            return Err(SubmitError::Unavailable(
+                "there's no session_key yet, run `minim lastfm-login` to get one".to_owned(),
+            ));
+        };
+
+        let mut params = vec![("sk".to_owned(), session_key.clone())];
+        for (i, listen) in listens.iter().enumerate() {
+            params.push((format!("artist[{i}]"), listen.artist.clone()));
+            params.push((format!("track[{i}]"), listen.title.clone()));
+            params.push((format!("timestamp[{i}]"), listen.listened_at.to_string()));
+            params.push((format!("duration[{i}]"), listen.duration.to_string()));
+            if let Some(album) = &listen.album {
+                params.push((format!("album[{i}]"), album.clone()));
+            }
+        }
+        self.call("track.scrobble", params).map(|_| ())
+    }
+}
+
+/// Walks through letting minim scrobble to a Last.fm account, and prints the session key for the config file
+pub(crate) fn lastfm_login(lastfm: &LastFm) -> Result<()> {
+    let reply = lastfm.call("auth.getToken", Vec::new())?;
+    let token = reply["token"]
+        .as_str()
+        .ok_or(anyhow!("Last.fm didn't send a token"))?;
+
+    println!(
+        "Allow minim to scrobble at https://www.last.fm/api/auth/?api_key={}&token={token}, then press Enter",
+        lastfm.api_key
+    );
+    io::stdin().read_line(&mut String::new())?;
+
+    let reply = lastfm.call(
+        "auth.getSession",
+        vec![("token".to_owned(), token.to_owned())],
+    )?;
+    let key = reply["session"]["key"]
+        .as_str()
+        .ok_or(anyhow!("Last.fm didn't send a session key"))?;
+    println!(
+        "Add this to the [scrobble.lastfm] section of the config file:\n\nsession_key = \"{key}\""
+    );
+    Ok(())
+}
+
+/// Listens waiting to go to one service, one JSON object per line
+struct Queue {
+    path: PathBuf,
+}
+
+impl Queue {
+    fn push(&self, listen: &Listen) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(listen)?)?;
+        Ok(())
+    }
+
+    fn load(&self) -> Result<Vec<Listen>> {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        // A line cut short by a crash shouldn't hold up the rest
+        Ok(text
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    fn store(&self, listens: &[Listen]) -> Result<()> {
+        if listens.is_empty() {
+            return match fs::remove_file(&self.path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            };
+        }
+
+        let text: String = listens
+            .iter()
+            .map(|listen| Ok(serde_json::to_string(listen)? + "\n"))
+            .collect::<Result<_>>()?;
+        fs::write(&self.path, text)?;
+        Ok(())
+    }
+}
+
+/// Submits listens to each of its services, keeping the ones a service can't take yet on disk
+pub struct Scrobbler {
+    dir: PathBuf,
+    backends: Vec<(Box<dyn Backend>, Queue)>,
+}
+
+impl Scrobbler {
+    /// Keeps listens waiting to be submitted in `dir`
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            backends: Vec::new(),
+        }
+    }
+
+    pub fn with_backend(mut self, backend: impl Backend + 'static) -> Self {
+        let file = format!("{}.jsonl", backend.name().to_lowercase());
+        let queue = Queue {
+            path: self.dir.join(file),
+        };
+        self.backends.push((Box::new(backend), queue));
+        self
+    }
+
+    /// Queues `listen` for every service, then submits everything that's queued
+    pub fn scrobble(&self, listen: &Listen) -> Result<()> {
+        for (backend, queue) in &self.backends {
+            queue
+                .push(listen)
+                .with_context(|| format!("Couldn't keep a listen for {}", backend.name()))?;
+        }
+        self.flush()
+    }
+
+    /// Submits the listens that are queued. Ones a service can't take right now stay queued, and ones it turned
+    /// down are dropped.
+    pub fn flush(&self) -> Result<()> {
+        let problems: Vec<String> = self
+            .backends
+            .iter()
+            .filter_map(|(backend, queue)| flush(backend.as_ref(), queue).err())
+            .map(|e| format!("{e:#}"))
+            .collect();
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(problems.join("; ")))
+        }
+    }
+
+    fn pending(&self) -> bool {
+        self.backends.iter().any(|(_, queue)| queue.path.exists())
+    }
+
+    /// Scrobbles the tracks in `events` that were heard for long enough, on a thread of its own. Listens that
+    /// couldn't be submitted are tried again every few minutes. Problems are passed to `report`, once until
+    /// they're sorted out.
+    pub fn follow(
+        self,
+        events: Receiver<Event>,
+        report: impl Fn(String) + Send + 'static,
+    ) -> JoinHandle<()> {
+        thread::spawn(move || {
+            let mut reported = None;
+            let mut check = |result: Result<()>| match result {
+                Ok(()) => reported = None,
+                Err(e) => {
+                    let message = format!("{e:#}");
+                    if reported.as_ref() != Some(&message) {
+                        report(message.clone());
+                        reported = Some(message);
+                    }
+                }
+            };
+
+            // Whatever was left over from last time
+            if self.pending() {
+                check(self.flush());
+            }
+
+            // When the track at the index started, since pausing and seeking make working it out from the time heard
+            // wrong
+            let mut started = None;
+            loop {
+                match events.recv_timeout(RETRY_INTERVAL) {
+                    Ok(Event::TrackStarted { index, .. }) => {
+                        started = Some((index, SystemTime::now()))
+                    }
+                    Ok(Event::TrackEnded {
+                        index,
+                        track,
+                        heard,
+                    }) => {
+                        let started = match started.take() {
+                            Some((i, at)) if i == index => at,
+                            // It started before the scrobbler was following
+                            _ => SystemTime::now() - heard,
+                        };
+                        if !should_scrobble(Duration::from_secs(track.duration()), heard) {
+                            continue;
+                        }
+                        if let Some(listen) = Listen::new(&track, started) {
+                            check(self.scrobble(&listen));
+                        }
+                    }
+                    Ok(_) => (),
+                    Err(RecvTimeoutError::Timeout) if self.pending() => check(self.flush()),
+                    Err(RecvTimeoutError::Timeout) => (),
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+        })
+    }
+}
+
+/// Submits what's queued for `backend` in batches, until it runs out or the service stops taking them
+fn flush(backend: &dyn Backend, queue: &Queue) -> Result<()> {
+    let mut listens = queue.load()?;
+    let mut problem = None;
+    while !listens.is_empty() {
+        let batch = backend.batch_size().clamp(1, listens.len());
+        match backend.submit(&listens[..batch]) {
+            Ok(()) => {
+                listens.drain(..batch);
+            }
+            Err(SubmitError::Rejected(e)) => {
+                listens.drain(..batch);
+                problem = Some(anyhow!(
+                    "{} turned down {batch} listens: {e}",
+                    backend.name()
+                ));
+            }
+            Err(e) => {
+                problem = Some(anyhow!(
+                    "Couldn't submit to {}, trying again later: {e}",
+                    backend.name()
+                ));
+                break;
+            }
+        }
+    }
+
+    queue.store(&listens)?;
+    problem.map_or(Ok(()), Err)
+}
+
+/// The `[scrobble]` section of the config file
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Scrobbling {
+    pub(crate) listenbrainz: Option<ListenBrainz>,
+    pub(crate) lastfm: Option<LastFm>,
+}
+
+impl Scrobbling {
+    /// Scrobbles what `engine` plays to the services in the config file, if there are any
+    pub(crate) fn start(
+        &self,
+        engine: &dyn Playback,
+        report: impl Fn(String) + Send + 'static,
+    ) -> Result<()> {
+        if self.listenbrainz.is_none() && self.lastfm.is_none() {
+            return Ok(());
+        }
+
+        let mut dir = dirs::data_dir().ok_or(anyhow!("Couldn't find data dir"))?;
+        dir.push("minim");
+        dir.push("scrobbles");
+        let mut scrobbler = Scrobbler::new(dir);
+        if let Some(listenbrainz) = &self.listenbrainz {
+            scrobbler = scrobbler.with_backend(listenbrainz.clone());
+        }
+        if let Some(lastfm) = &self.lastfm {
+            scrobbler = scrobbler.with_backend(lastfm.clone());
+        }
+
+        scrobbler.follow(engine.subscribe(), report);
+        Ok(())
+    }
+}
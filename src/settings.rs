@@ -0,0 +1,71 @@
+use cursive::{
+    event::Key,
+    view::{Resizable, Scrollable},
+    views::{Dialog, LinearLayout, OnEventView, SelectView, TextView},
+    Cursive,
+};
+
+use crate::output;
+use crate::views::SharedState;
+
+/// Lets the output device be changed while playing. The queue carries on from where it was on the new device.
+pub(crate) fn open(siv: &mut Cursive, state: &SharedState) {
+    let Some(output) = state.output.clone() else {
+        state
+            .toasts
+            .error("The output device can only be changed in the minim that's playing");
+        return;
+    };
+    let Some(device) = output.device() else {
+        state.toasts.error(format!(
+            "Playing to {}, there's no device to change",
+            output.describe()
+        ));
+        return;
+    };
+
+    let devices = match output::devices() {
+        Ok(devices) => devices,
+        Err(e) => {
+            state
+                .toasts
+                .error(format!("Couldn't list output devices: {e}"));
+            return;
+        }
+    };
+    // Some backends complain on stderr while listing devices, which would be left on the screen
+    siv.clear();
+
+    let default = output::default_device().unwrap_or_else(|| "none".to_owned());
+    let mut choices = SelectView::new().item(format!("Default ({default})"), None);
+    for device in devices.into_iter().filter(|d| *d != default) {
+        choices.add_item(device.clone(), Some(device));
+    }
+    let chosen = device.chosen();
+    let selected = choices.iter().position(|(_, d)| *d == chosen);
+    if let Some(i) = selected {
+        choices.set_selection(i);
+    }
+
+    let choices = choices.on_submit(move |siv, device: &Option<String>| {
+        siv.pop_layer();
+        let state = siv.user_data::<SharedState>().expect("Missing state?");
+        let Some(output) = output.device() else {
+            return;
+        };
+        match output.switch(device.clone()) {
+            Ok(name) => state.toasts.info(format!("Playing on {name}")),
+            Err(e) => state.toasts.error(format!("{e:#}")),
+        }
+    });
+
+    let layout = LinearLayout::vertical()
+        .child(TextView::new("Output device"))
+        .child(choices.scrollable().max_height(12));
+    let dialog = Dialog::around(layout)
+        .title("Settings")
+        .dismiss_button("Close");
+    siv.add_layer(OnEventView::new(dialog).on_event(Key::Esc, |siv| {
+        siv.pop_layer();
+    }));
+}
@@ -0,0 +1,111 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cursive::theme::{BaseColor, Color, ColorStyle, Effect, Style};
+use cursive::utils::markup::StyledString;
+use cursive::Printer;
+
+use crate::bus::{Bus, Message, UiCommand};
+
+/// How long a toast stays on screen
+const TOAST_DURATION: Duration = Duration::from_secs(5);
+
+/// How many toasts are shown at once. Older ones are dropped early when more arrive.
+const MAX_TOASTS: usize = 4;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Level {
+    Info,
+    Error,
+}
+
+#[derive(Clone, Debug)]
+struct Toast {
+    level: Level,
+    message: String,
+    expires: Instant,
+}
+
+/// Short-lived messages shown in the corner of the player, for things that went wrong in the background or don't
+/// deserve a dialog
+#[derive(Clone)]
+pub(crate) struct Toasts {
+    toasts: Arc<Mutex<VecDeque<Toast>>>,
+    /// Asked to redraw the screen when a toast expires
+    bus: Bus,
+}
+
+impl Toasts {
+    pub(crate) fn new(bus: Bus) -> Self {
+        Self {
+            toasts: Arc::default(),
+            bus,
+        }
+    }
+
+    pub(crate) fn push(&self, level: Level, message: impl Into<String>) {
+        let mut toasts = self.toasts.lock().unwrap();
+        toasts.push_back(Toast {
+            level,
+            message: message.into(),
+            expires: Instant::now() + TOAST_DURATION,
+        });
+        while toasts.len() > MAX_TOASTS {
+            toasts.pop_front();
+        }
+
+        let bus = self.bus.clone();
+        thread::spawn(move || {
+            thread::sleep(TOAST_DURATION);
+            bus.notify(Message::Ui(UiCommand::Redraw));
+        });
+    }
+
+    pub(crate) fn info(&self, message: impl Into<String>) {
+        self.push(Level::Info, message);
+    }
+
+    pub(crate) fn error(&self, message: impl Into<String>) {
+        self.push(Level::Error, message);
+    }
+
+    /// Draws the toasts that haven't expired yet in the bottom right corner, newest at the bottom
+    pub(crate) fn draw(&self, printer: &Printer) {
+        let mut toasts = self.toasts.lock().unwrap();
+        let now = Instant::now();
+        toasts.retain(|t| t.expires > now);
+
+        let width = printer.size.x;
+        // Leave the bottom border of the panels alone
+        let mut y = printer.size.y.saturating_sub(2);
+        for toast in toasts.iter().rev() {
+            if y == 0 {
+                break;
+            }
+
+            let max = width.saturating_sub(4);
+            let mut message: String = toast.message.chars().take(max).collect();
+            if message.len() < toast.message.len() {
+                message.pop();
+                message.push('…');
+            }
+
+            let color = match toast.level {
+                Level::Info => ColorStyle::highlight(),
+                Level::Error => {
+                    ColorStyle::new(Color::Light(BaseColor::White), Color::Dark(BaseColor::Red))
+                }
+            };
+            let text = StyledString::styled(
+                format!(" {message} "),
+                Style::from(color).combine(Effect::Bold),
+            );
+
+            let x = width.saturating_sub(text.width() + 1);
+            printer.print_styled((x, y), &text);
+            y -= 1;
+        }
+    }
+}
@@ -1,119 +1,645 @@
 use std::{
+    cmp,
+    collections::HashSet,
     fs,
-    io::BufReader,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use cursive::{
     align::HAlign,
-    view::{Nameable, Resizable, Scrollable, Selector, ViewWrapper},
-    views::{LinearLayout, NamedView, Panel, ScrollView, TextContent, TextView},
-    View,
+    event::{Event, EventResult, Key},
+    theme::{Color, ColorStyle, Effect, Style},
+    utils::{lines::simple::LinesIterator, markup::StyledString},
+    view::{Nameable, Resizable, Scrollable, Selector, SizeConstraint, ViewWrapper},
+    views::{
+        Dialog, EditView, HideableView, LinearLayout, NamedView, OnEventView, Panel, ResizedView,
+        ScrollView, TextContent, TextView,
+    },
+    Cursive, View,
 };
 use cursive_table_view::{TableView, TableViewItem};
 use cursive_tabs::TabPanel;
-use lofty::tag::ItemKey;
-use rodio::Sink;
+use image::RgbImage;
 
-use crate::files::{CachedField, Track, WrappedSource};
+use crate::actions::{self, Action};
+use crate::art::{self, Overlay, Placement, Protocol};
+use crate::bus::{self, Bus, LibraryEvent, Message, UiCommand};
+use crate::config::{Config, Width};
+use crate::duplicates;
+use crate::editor::{open_batch_menu, open_tag_editor};
+use crate::engine::{self, Playback, QueueError};
+use crate::files::{CachedField, Track};
+use crate::filter::Filter;
+use crate::help::{self, KeyScope, ViewAction};
+use crate::keymap::Keymap;
+use crate::library::Library;
+use crate::lyrics::{Lyrics, LyricsCache};
+use crate::output::Backend;
+use crate::toast::Toasts;
+use crate::watcher::LibraryChange;
 
 pub(crate) const TRACKS_TABLE_VIEW_SELECTOR: Selector = Selector::Name("tracks");
 pub(crate) const QUEUE_VIEW_SELECTOR: Selector = Selector::Name("queue_list");
 
+/// Names of the tabs in [`PlayerView`], in order
+pub(crate) const TABS: [&str; 4] = ["Library", "Now Playing", "Lyrics", "Duplicates"];
+
+/// How many seconds apart the lengths of two copies of a track may be
+const DUPLICATE_TOLERANCE: u64 = 2;
+
 pub(crate) type TrackTable = TableView<Track, CachedField>;
 
 type ScrollNamedText = ScrollView<NamedView<TextView>>;
 type NamedPanel<T> = Panel<NamedView<T>>;
+type TrackPanel<T> = OnEventView<NamedPanel<T>>;
 type QueueTable = TableView<QueueEntry, QueueField>;
 
 #[derive(Clone)]
 pub(crate) struct SharedState {
-    pub(crate) sink: Arc<Sink>,
+    /// Plays the queue, here or in the minim this one is attached to
+    pub(crate) engine: Arc<dyn Playback>,
+    /// Where the engine's samples go, if it's playing here
+    pub(crate) output: Option<Arc<dyn Backend>>,
     pub(crate) tracks: Arc<Mutex<Vec<Track>>>,
-    pub(crate) queue: Arc<Mutex<Vec<Track>>>,
-    pub(crate) queue_index: Arc<Mutex<usize>>,
+    /// The global key bindings, for the help overlay
+    pub(crate) keymap: Arc<Keymap>,
+    pub(crate) library: Arc<Library>,
+    pub(crate) art_overlay: Arc<Mutex<Overlay>>,
+    pub(crate) lyrics: LyricsCache,
+    pub(crate) toasts: Toasts,
+    /// What the library view is narrowed down to
+    pub(crate) filter: Arc<Mutex<Filter>>,
+    /// Whether rating changes should also be saved into the files' tags
+    pub(crate) write_ratings: bool,
+    /// Carries what happens in the background to the views
+    pub(crate) bus: Bus,
 }
 
 impl SharedState {
-    pub(crate) fn new(sink: Arc<Sink>) -> Self {
+    pub(crate) fn new(
+        engine: Arc<dyn Playback>,
+        output: Option<Arc<dyn Backend>>,
+        tracks: Arc<Mutex<Vec<Track>>>,
+        library: Arc<Library>,
+        keymap: Keymap,
+        write_ratings: bool,
+    ) -> Self {
+        let bus = Bus::default();
+        bus::forward(engine.subscribe(), bus.clone());
         Self {
-            sink,
-            tracks: Arc::new(Mutex::new(Vec::new())),
-            queue: Arc::new(Mutex::new(Vec::new())),
-            queue_index: Arc::new(Mutex::new(0)),
+            engine,
+            output,
+            tracks,
+            keymap: Arc::new(keymap),
+            library,
+            art_overlay: Arc::new(Mutex::new(Overlay::new(Protocol::detect()))),
+            lyrics: LyricsCache::new(bus.clone()),
+            toasts: Toasts::new(bus.clone()),
+            filter: Arc::default(),
+            write_ratings,
+            bus,
         }
     }
+
+    /// Whether `track` belongs in the library view
+    pub(crate) fn shows(&self, track: &Track) -> bool {
+        !track.hidden && self.filter.lock().unwrap().matches(track)
+    }
 }
 
-struct LibraryTracksView {
-    inner: NamedPanel<TrackTable>,
+/// Replaces every copy of `track` (matched by path) in the library, the queue, and their table views
+pub(crate) fn update_track(siv: &mut Cursive, state: &SharedState, track: &Track) {
+    for t in state.tracks.lock().unwrap().iter_mut() {
+        if t == track {
+            *t = track.clone();
+        }
+    }
+
+    state.engine.update(track);
+
+    siv.call_on(&TRACKS_TABLE_VIEW_SELECTOR, |v: &mut TrackTable| {
+        for t in v.borrow_items_mut() {
+            if t == track {
+                *t = track.clone();
+            }
+        }
+    });
+
+    siv.call_on(&QUEUE_VIEW_SELECTOR, |v: &mut QueueTable| {
+        for entry in v.borrow_items_mut() {
+            if entry.track == *track {
+                entry.track = track.clone();
+            }
+        }
+    });
 }
 
-impl LibraryTracksView {
-    fn new(state: SharedState) -> Self {
-        let mut table = TrackTable::new()
-            .column(CachedField::Artist, "Artist", |c| c)
-            .column(CachedField::Title, "Title", |c| c)
-            .column(CachedField::Duration, "Length", |c| c.width(10));
+/// Points every copy of the track at `from` to its new location at `to`
+pub(crate) fn relocate_track(siv: &mut Cursive, state: &SharedState, from: &Path, to: &Path) {
+    let relocate = |t: &mut Track| {
+        if t.path == from {
+            t.path = to.to_path_buf();
+        }
+    };
 
-        table.set_on_submit(move |siv, _row, index| {
-            let mut title = String::new();
-            let mut valid_file = false;
-            let queue_index = state.queue_index.clone();
-            let queue = state.queue.clone();
-
-            // Play song
-            siv.call_on(&TRACKS_TABLE_VIEW_SELECTOR, |v: &mut TrackTable| {
-                let track = v
-                    .borrow_item(index)
-                    .expect("Index given by submit event should always be valid");
-
-                title = track.cached_field_string(CachedField::Title);
-
-                // TODO: handle case where file is removed while player is running, e.g., by prompting user to remove
-                // from library view. This could be useful if we ever switch to persisting the library in a database
-                let file = fs::File::open(&track.path)
-                    .expect("Path should be valid, since we imported these files at startup");
-
-                // Add song to queue. TODO: display error message when attempting to open an unsupported file
-                if let Ok(decoder) = rodio::Decoder::new(BufReader::new(file)) {
-                    {
-                        queue.lock().unwrap().push(track.clone());
-                    }
+    state.tracks.lock().unwrap().iter_mut().for_each(relocate);
+    state.engine.relocate(from, to);
+    state.lyrics.invalidate(from);
+
+    siv.call_on(&TRACKS_TABLE_VIEW_SELECTOR, |v: &mut TrackTable| {
+        v.borrow_items_mut().iter_mut().for_each(relocate);
+    });
+
+    siv.call_on(&QUEUE_VIEW_SELECTOR, |v: &mut QueueTable| {
+        v.borrow_items_mut()
+            .iter_mut()
+            .for_each(|entry| relocate(&mut entry.track));
+    });
+}
+
+/// Hides or unhides a track in the library view. The track stays in the library itself, so the choice is
+/// remembered in the cache.
+pub(crate) fn set_hidden(siv: &mut Cursive, state: &SharedState, track: &Track, hidden: bool) {
+    let mut track = track.clone();
+    track.hidden = hidden;
+    update_track(siv, state, &track);
+
+    siv.call_on(&TRACKS_TABLE_VIEW_SELECTOR, |v: &mut TrackTable| {
+        let index = v.borrow_items().iter().position(|t| *t == track);
+        match (index, hidden) {
+            (Some(i), true) => {
+                v.remove_item(i);
+            }
+            (None, false) if state.shows(&track) => v.insert_item(track.clone()),
+            _ => (),
+        }
+    });
+}
+
+/// Drops a track from the library entirely. Copies already in the queue are left alone.
+pub(crate) fn remove_track(siv: &mut Cursive, state: &SharedState, track: &Track) {
+    state.tracks.lock().unwrap().retain(|t| t != track);
+
+    siv.call_on(&TRACKS_TABLE_VIEW_SELECTOR, |v: &mut TrackTable| {
+        if let Some(i) = v.borrow_items().iter().position(|t| t == track) {
+            v.remove_item(i);
+        }
+    });
+}
+
+/// Refills the library view from the library, keeping marked tracks marked
+fn refresh_library_table(siv: &mut Cursive, state: &SharedState) {
+    let tracks = state.tracks.lock().unwrap().clone();
+    siv.call_on(&TRACKS_TABLE_VIEW_SELECTOR, |v: &mut TrackTable| {
+        let marked: HashSet<PathBuf> = v
+            .borrow_items()
+            .iter()
+            .filter(|t| t.marked)
+            .map(|t| t.path.clone())
+            .collect();
+
+        v.set_items(
+            tracks
+                .into_iter()
+                .filter(|t| state.shows(t))
+                .map(|mut t| {
+                    t.marked = marked.contains(&t.path);
+                    t
+                })
+                .collect(),
+        );
+    });
+}
+
+/// Narrows the library view down to tracks matching `filter`
+pub(crate) fn set_filter(siv: &mut Cursive, state: &SharedState, filter: Filter) {
+    let empty = filter.is_empty();
+    *state.filter.lock().unwrap() = filter;
+    refresh_library_table(siv, state);
+
+    if empty {
+        state.toasts.info("Showing every track");
+    } else {
+        let count = siv
+            .call_on(&TRACKS_TABLE_VIEW_SELECTOR, |v: &mut TrackTable| v.len())
+            .unwrap_or(0);
+        state.toasts.info(format!("{count} tracks match"));
+    }
+}
+
+/// Scans the library roots again in the background, keeping what the user set for tracks that are still there
+pub(crate) fn rescan(state: &SharedState) {
+    state.toasts.info("Rescanning library...");
 
-                    let source = WrappedSource::new(decoder, move || {
-                        *queue_index.lock().unwrap() += 1;
+    let library = state.library.clone();
+    let bus = state.bus.clone();
+    std::thread::spawn(move || {
+        let tracks = library.scan();
+        bus.notify(Message::Library(LibraryEvent::Scanned(tracks)));
+    });
+}
+
+/// Replaces the library with what a rescan found
+fn finish_rescan(siv: &mut Cursive, state: &SharedState, mut tracks: Vec<Track>) {
+    {
+        let mut library = state.tracks.lock().unwrap();
+        for track in &mut tracks {
+            if let Some(old) = library.iter().find(|t| *t == track) {
+                track.keep_user_state(old);
+            }
+        }
+        *library = tracks;
+    }
+
+    refresh_library_table(siv, state);
+    let count = state.tracks.lock().unwrap().len();
+    state.toasts.info(format!("Found {count} tracks"));
+}
+
+/// Brings the library up to date with a change the filesystem watcher saw
+fn apply_library_change(siv: &mut Cursive, state: &SharedState, change: LibraryChange) {
+    match change {
+        LibraryChange::Updated(mut track) => {
+            state.lyrics.invalidate(&track.path);
+
+            let existing = {
+                let tracks = state.tracks.lock().unwrap();
+                tracks.iter().find(|t| **t == track).cloned()
+            };
+
+            if let Some(existing) = existing {
+                track.keep_user_state(&existing);
+                update_track(siv, state, &track);
+            } else {
+                state.tracks.lock().unwrap().push(track.clone());
+                if state.shows(&track) {
+                    siv.call_on(&TRACKS_TABLE_VIEW_SELECTOR, |v: &mut TrackTable| {
+                        v.insert_item(track)
                     });
-                    state.sink.append(source);
+                }
+            }
+        }
+        LibraryChange::Removed(path) => {
+            // A removed directory takes every track under it along
+            let gone: Vec<Track> = state
+                .tracks
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|t| t.path.starts_with(&path) && !t.unavailable)
+                .cloned()
+                .collect();
+
+            for mut track in gone {
+                state.lyrics.invalidate(&track.path);
+                track.unavailable = true;
+                update_track(siv, state, &track);
+            }
+        }
+        LibraryChange::Sidecar(path) => {
+            let tracks = state.tracks.lock().unwrap();
+            for track in tracks.iter() {
+                if track.path.with_extension("lrc") == path {
+                    state.lyrics.invalidate(&track.path);
+                }
+            }
+        }
+    }
+}
+
+/// Applies `edit` to `track` and propagates the change everywhere it's displayed
+fn edit_rating<F>(siv: &mut Cursive, state: &SharedState, mut track: Track, edit: F)
+where
+    F: FnOnce(&mut Track),
+{
+    edit(&mut track);
+
+    if state.write_ratings {
+        // Ratings are still kept in the library cache, so a failed write only loses the copy in the file
+        if let Err(e) = track.write_rating_to_file() {
+            state.toasts.error(format!(
+                "Couldn't save rating to {}: {e}",
+                track.path.display()
+            ));
+        }
+    }
+
+    update_track(siv, state, &track);
+}
+
+/// Adds the rating and tag editor keybinds to a table. `selected` returns the tracks the keys should act
+/// on, i.e., the marked tracks or the one under the cursor.
+fn with_track_keys<V, F>(view: V, state: SharedState, selected: F) -> OnEventView<V>
+where
+    V: View,
+    F: Fn(&mut V) -> Vec<Track> + Send + Sync + Clone + 'static,
+{
+    let mut view = OnEventView::new(view);
+    for (event, action) in help::presses(KeyScope::Tracks) {
+        let state = state.clone();
+        let selected = selected.clone();
+        view.set_on_event_inner(event, move |v, _| {
+            let tracks = selected(v);
+            let opens_editor = matches!(action, ViewAction::EditTags | ViewAction::BatchEdit);
+            if opens_editor && tracks.is_empty() {
+                return None;
+            }
+            let state = state.clone();
+            Some(EventResult::with_cb_once(move |siv| match action {
+                ViewAction::Rate(stars) => {
+                    for track in tracks {
+                        edit_rating(siv, &state, track, |t| t.set_rating(stars))
+                    }
+                }
+                ViewAction::Favourite => {
+                    for track in tracks {
+                        edit_rating(siv, &state, track, |t| t.toggle_favourite())
+                    }
+                }
+                ViewAction::EditTags => open_tag_editor(siv, state, tracks),
+                ViewAction::BatchEdit => open_batch_menu(siv, state, tracks),
+                _ => (),
+            }))
+        });
+    }
+
+    view
+}
+
+/// Adds vim-style navigation to a table: `j`/`k`, `gg`/`G` and `Ctrl-d`/`Ctrl-u`
+struct VimKeys<V> {
+    inner: V,
+    /// Whether `g` was just pressed, so another `g` jumps to the top
+    pending_g: bool,
+}
 
-                    valid_file = true;
+impl<V> VimKeys<V> {
+    fn new(inner: V) -> Self {
+        Self {
+            inner,
+            pending_g: false,
+        }
+    }
+
+    cursive::inner_getters!(self.inner: V);
+}
+
+impl<V: View> ViewWrapper for VimKeys<V> {
+    cursive::wrap_impl!(self.inner: V);
+
+    fn wrap_on_event(&mut self, event: Event) -> EventResult {
+        // Refreshes arrive between key presses and shouldn't break off `gg`
+        if event == Event::Refresh {
+            return self.inner.on_event(event);
+        }
+
+        let pending_g = std::mem::take(&mut self.pending_g);
+        let event = match help::action(KeyScope::Table, &event) {
+            Some(ViewAction::Down) => Event::Key(Key::Down),
+            Some(ViewAction::Up) => Event::Key(Key::Up),
+            Some(ViewAction::Bottom) => Event::Key(Key::End),
+            Some(ViewAction::Top) if pending_g => Event::Key(Key::Home),
+            Some(ViewAction::Top) => {
+                self.pending_g = true;
+                return EventResult::Consumed(None);
+            }
+            Some(ViewAction::PageDown) => Event::Key(Key::PageDown),
+            Some(ViewAction::PageUp) => Event::Key(Key::PageUp),
+            _ => event,
+        };
+        self.inner.on_event(event)
+    }
+}
+
+/// The marked tracks in `table` in the order they're displayed, or the track under the cursor if none are marked
+fn selected_tracks(table: &mut TrackTable) -> Vec<Track> {
+    let mut marked: Vec<Track> = table
+        .borrow_items()
+        .iter()
+        .filter(|t| t.marked)
+        .cloned()
+        .collect();
+    if !marked.is_empty() {
+        // Items are stored unsorted, so put them in the order they're displayed
+        if let Some((column, order)) = table.order() {
+            marked.sort_by(|a, b| match order {
+                cmp::Ordering::Greater => b.cmp(a, column),
+                _ => a.cmp(b, column),
+            });
+        }
+        return marked;
+    }
+
+    table
+        .item()
+        .and_then(|index| table.borrow_item(index))
+        .cloned()
+        .into_iter()
+        .collect()
+}
+
+/// The tracks that library actions should act on. See [`selected_tracks`].
+pub(crate) fn library_selection(siv: &mut Cursive) -> Vec<Track> {
+    siv.call_on(&TRACKS_TABLE_VIEW_SELECTOR, |v: &mut TrackTable| {
+        selected_tracks(v)
+    })
+    .unwrap_or_default()
+}
+
+/// Adds `track` to the queue, asking first if it couldn't be played before
+pub(crate) fn enqueue(siv: &mut Cursive, state: &SharedState, track: Track) {
+    if !track.undecodable {
+        LibraryTracksView::queue_track(siv, state, track);
+        return;
+    }
+
+    let state = state.clone();
+    let title = track.cached_field_string(CachedField::Title);
+    siv.add_layer(
+        Dialog::text(format!("{title} couldn't be played last time. Try again?"))
+            .title("Unplayable track")
+            .button("Try again", move |siv| {
+                siv.pop_layer();
+                LibraryTracksView::queue_track(siv, &state, track.clone());
+            })
+            .dismiss_button("Cancel"),
+    );
+}
+
+/// Brings the views up to date with what arrived on the bus. Views keep what they show to themselves, so this is
+/// the only time they ask the engine.
+pub(crate) fn handle_messages(siv: &mut Cursive, messages: Vec<Message>) {
+    let state = siv
+        .user_data::<SharedState>()
+        .expect("Missing state?")
+        .clone();
+
+    let mut queue_changed = false;
+    for message in messages {
+        match message {
+            Message::Player(engine::Event::Error(message)) => state.toasts.error(message),
+            Message::Player(engine::Event::Quit) => siv.quit(),
+            Message::Player(engine::Event::PositionChanged(_)) => (),
+            Message::Player(_) => queue_changed = true,
+            Message::Library(LibraryEvent::Changed(changes)) => {
+                for change in changes {
+                    apply_library_change(siv, &state, change);
                 }
+            }
+            Message::Library(LibraryEvent::Scanned(tracks)) => finish_rescan(siv, &state, tracks),
+            // The views below pick the lyrics up
+            Message::Library(LibraryEvent::LyricsLoaded) => (),
+            Message::Ui(UiCommand::Redraw | UiCommand::Tick) => (),
+        }
+    }
+
+    if queue_changed {
+        sync_queue(siv);
+    }
+
+    let track = state.engine.current();
+    let position = state.engine.position();
+    siv.call_on_name(TABS[1], |v: &mut NowPlayingView| v.show(track.clone()));
+    siv.call_on_name(TABS[2], |v: &mut LyricsView| v.show(track, position));
+}
+
+/// Brings the queue table up to date with the engine's queue. New tracks are added to the end, anything else
+/// refills the table.
+fn sync_queue(siv: &mut Cursive) {
+    let state = siv
+        .user_data::<SharedState>()
+        .expect("Missing state?")
+        .clone();
+    let queue = state.engine.queue();
+
+    siv.call_on(&QUEUE_VIEW_SELECTOR, |v: &mut QueueTable| {
+        let shown = v.len();
+        let grown = shown <= queue.len()
+            && v.borrow_items()
+                .iter()
+                .all(|entry| queue.get(entry.index - 1) == Some(&entry.track));
+        if !grown {
+            v.clear();
+        }
+
+        for (i, track) in queue.into_iter().enumerate().skip(v.len()) {
+            state.lyrics.prefetch(&track);
+            v.insert_item(QueueEntry {
+                index: i + 1,
+                track,
+            });
+        }
+    });
+}
+
+struct LibraryTracksView {
+    inner: VimKeys<TrackPanel<TrackTable>>,
+}
+
+impl LibraryTracksView {
+    /// Adds `track` to the end of the queue, reporting files that have gone missing or can't be decoded
+    fn queue_track(siv: &mut Cursive, state: &SharedState, mut track: Track) {
+        let title = track.cached_field_string(CachedField::Title);
+
+        match state.engine.enqueue(&track) {
+            Ok(()) if track.unavailable || track.undecodable => {
+                // It works now, e.g., a drive was remounted or the file was replaced
+                track.unavailable = false;
+                track.undecodable = false;
+                update_track(siv, state, &track);
+            }
+            Ok(()) => (),
+            Err(QueueError::Missing) => {
+                track.unavailable = true;
+                update_track(siv, state, &track);
+                Self::offer_removal(siv, state, track);
+            }
+            Err(QueueError::Undecodable(e)) => {
+                state.toasts.error(format!("Couldn't play {title}: {e}"));
+                track.undecodable = true;
+                update_track(siv, state, &track);
+            }
+            Err(e) => state.toasts.error(format!("Couldn't open {title}: {e}")),
+        }
+    }
+
+    fn offer_removal(siv: &mut Cursive, state: &SharedState, track: Track) {
+        let state = state.clone();
+        let path = track.path.display().to_string();
+        siv.add_layer(
+            Dialog::text(format!(
+                "{path} no longer exists. Remove it from the library?"
+            ))
+            .title("Missing file")
+            .button("Remove", move |siv| {
+                siv.pop_layer();
+                remove_track(siv, &state, &track);
+                state
+                    .toasts
+                    .info(format!("Removed {path} from the library"));
             })
-            .expect("tracks view must exist");
-
-            if valid_file {
-                // Add to queue list view
-                siv.call_on(&QUEUE_VIEW_SELECTOR, |v: &mut QueueTable| {
-                    let queue = queue.lock().unwrap();
-                    v.insert_item(QueueEntry {
-                        index: v.len() + 1,
-                        track: queue.last().unwrap().clone(),
-                    })
+            .dismiss_button("Keep"),
+        );
+    }
+
+    fn new(state: SharedState, config: &Config) -> Self {
+        let mut table = TrackTable::new();
+        for column in &config.columns {
+            table.add_column(column.field, &column.title, |c| match column.width {
+                Some(Width::Cells(w)) => c.width(w),
+                Some(Width::Percent(p)) => c.width_percent(p),
+                None => c,
+            });
+        }
+        if let Some(sort) = config.sort {
+            table.sort_by(sort.field, sort.order);
+        }
+
+        let key_state = state.clone();
+        table.set_on_submit(move |siv, _row, index| {
+            let Some(track) = siv
+                .call_on(&TRACKS_TABLE_VIEW_SELECTOR, |v: &mut TrackTable| {
+                    v.borrow_item(index).cloned()
                 })
-                .expect("queue list view must exist");
-            }
+                .flatten()
+            else {
+                return;
+            };
+
+            enqueue(siv, &state, track);
         });
 
         let panel = Panel::new(table.with_name("tracks"));
+        let mut panel = with_track_keys(panel, key_state, |p: &mut NamedPanel<TrackTable>| {
+            selected_tracks(&mut p.get_inner_mut().get_mut())
+        });
 
-        Self { inner: panel }
+        // Toggle the selected track in or out of the multi-selection
+        for (event, action) in help::presses(KeyScope::Library) {
+            if action != ViewAction::Mark {
+                continue;
+            }
+            panel.set_on_event_inner(event, |p, _| {
+                let mut table = p.get_inner_mut().get_mut();
+                let index = table.item()?;
+                let track = table.borrow_item_mut(index)?;
+                track.marked = !track.marked;
+                Some(EventResult::Consumed(None))
+            });
+        }
+
+        Self {
+            inner: VimKeys::new(panel),
+        }
     }
 
-    cursive::inner_getters!(self.inner: NamedPanel<TrackTable>);
+    cursive::inner_getters!(self.inner: VimKeys<TrackPanel<TrackTable>>);
 }
 
 impl ViewWrapper for LibraryTracksView {
-    cursive::wrap_impl!(self.inner: NamedPanel<TrackTable>);
+    cursive::wrap_impl!(self.inner: VimKeys<TrackPanel<TrackTable>>);
 }
 
 #[derive(Clone, PartialEq, Eq, Hash)]
@@ -151,8 +677,7 @@ impl TableViewItem<QueueField> for QueueEntry {
 }
 
 struct LibrarySidebarView {
-    inner: NamedPanel<QueueTable>,
-    state: SharedState,
+    inner: VimKeys<TrackPanel<QueueTable>>,
 }
 
 impl LibrarySidebarView {
@@ -162,18 +687,26 @@ impl LibrarySidebarView {
             .column(QueueField::Track, "Track", |c| c);
 
         let panel = Panel::new(table.with_name("queue_list"));
+        let panel = with_track_keys(panel, state, |p: &mut NamedPanel<QueueTable>| {
+            let table = p.get_inner_mut().get_mut();
+            table
+                .item()
+                .and_then(|index| table.borrow_item(index))
+                .map(|entry| entry.track.clone())
+                .into_iter()
+                .collect()
+        });
 
         Self {
-            inner: panel,
-            state,
+            inner: VimKeys::new(panel),
         }
     }
 
-    cursive::inner_getters!(self.inner: NamedPanel<QueueTable>);
+    cursive::inner_getters!(self.inner: VimKeys<TrackPanel<QueueTable>>);
 }
 
 impl ViewWrapper for LibrarySidebarView {
-    cursive::wrap_impl!(self.inner: NamedPanel<QueueTable>);
+    cursive::wrap_impl!(self.inner: VimKeys<TrackPanel<QueueTable>>);
 }
 
 struct LibraryView {
@@ -181,9 +714,9 @@ struct LibraryView {
 }
 
 impl LibraryView {
-    fn new(state: SharedState) -> Self {
+    fn new(state: SharedState, config: &Config) -> Self {
         let linear_layout = LinearLayout::horizontal()
-            .child(LibraryTracksView::new(state.clone()).full_screen())
+            .child(LibraryTracksView::new(state.clone(), config).full_screen())
             .child(LibrarySidebarView::new(state.clone()).min_width(40));
 
         Self {
@@ -198,13 +731,330 @@ impl ViewWrapper for LibraryView {
     cursive::wrap_impl!(self.inner: LinearLayout);
 }
 
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct DuplicateEntry {
+    group: usize,
+    track: Track,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum DuplicateField {
+    Group,
+    Artist,
+    Title,
+    Duration,
+    Path,
+}
+
+impl TableViewItem<DuplicateField> for DuplicateEntry {
+    fn to_column(&self, column: DuplicateField) -> String {
+        match column {
+            DuplicateField::Group => format!("{}", self.group),
+            DuplicateField::Artist => self.track.cached_field_string(CachedField::Artist),
+            DuplicateField::Title => self.track.cached_field_string(CachedField::Title),
+            DuplicateField::Duration => self.track.cached_field_string(CachedField::Duration),
+            DuplicateField::Path if self.track.hidden => {
+                format!("(hidden) {}", self.track.path.display())
+            }
+            DuplicateField::Path => self.track.path.display().to_string(),
+        }
+    }
+
+    fn cmp(&self, other: &Self, column: DuplicateField) -> std::cmp::Ordering
+    where
+        Self: Sized,
+    {
+        match column {
+            DuplicateField::Group => self.group.cmp(&other.group),
+            DuplicateField::Duration => self.track.duration().cmp(&other.track.duration()),
+            DuplicateField::Path => self.track.path.cmp(&other.track.path),
+            _ => self
+                .to_column(column)
+                .to_lowercase()
+                .cmp(&other.to_column(column).to_lowercase()),
+        }
+    }
+}
+
+type DuplicateTable = TableView<DuplicateEntry, DuplicateField>;
+
+/// Lists groups of tracks that look like copies of each other, so extra copies can be hidden or deleted
+type DuplicatePanel = OnEventView<Panel<NamedView<DuplicateTable>>>;
+
+struct DuplicatesView {
+    inner: VimKeys<DuplicatePanel>,
+}
+
+impl DuplicatesView {
+    fn new(state: SharedState) -> Self {
+        let table = DuplicateTable::new()
+            .column(DuplicateField::Group, "#", |c| {
+                c.width(4).align(HAlign::Right)
+            })
+            .column(DuplicateField::Artist, "Artist", |c| c)
+            .column(DuplicateField::Title, "Title", |c| c)
+            .column(DuplicateField::Duration, "Length", |c| c.width(8))
+            .column(DuplicateField::Path, "Path", |c| c);
+
+        let panel = Panel::new(table.with_name("duplicates"))
+            .title("Duplicates: r to search, F to compare audio, h to hide, D to delete");
+        let mut view = OnEventView::new(panel);
+
+        for (event, action) in help::presses(KeyScope::Duplicates) {
+            let state = state.clone();
+            view.set_on_event(event, move |siv| match action {
+                ViewAction::SearchTags => Self::search(siv, &state, false),
+                ViewAction::SearchAudio => Self::search(siv, &state, true),
+                ViewAction::Hide => Self::toggle_hidden(siv, &state),
+                ViewAction::Delete => Self::delete(siv, &state),
+                _ => (),
+            });
+        }
+
+        Self {
+            inner: VimKeys::new(view),
+        }
+    }
+
+    fn toggle_hidden(siv: &mut Cursive, state: &SharedState) {
+        if let Some(entry) = Self::selected(siv) {
+            set_hidden(siv, state, &entry.track, !entry.track.hidden);
+            Self::update_entry(siv, &entry.track, |t| t.hidden = !t.hidden);
+        }
+    }
+
+    /// Deletes the selected file from disk once it's confirmed
+    fn delete(siv: &mut Cursive, state: &SharedState) {
+        let Some(entry) = Self::selected(siv) else {
+            return;
+        };
+        let state = state.clone();
+        let path = entry.track.path.display().to_string();
+        siv.add_layer(
+            Dialog::text(format!("Delete {path} from disk?"))
+                .title("Delete file")
+                .button("Delete", move |siv| {
+                    siv.pop_layer();
+                    if let Err(e) = fs::remove_file(&entry.track.path) {
+                        siv.add_layer(Dialog::info(format!("Couldn't delete {path}: {e}")));
+                        return;
+                    }
+
+                    remove_track(siv, &state, &entry.track);
+                    siv.call_on_name("duplicates", |v: &mut DuplicateTable| {
+                        if let Some(i) = v.borrow_items().iter().position(|e| *e == entry) {
+                            v.remove_item(i);
+                        }
+                    });
+                })
+                .dismiss_button("Cancel"),
+        );
+    }
+
+    fn selected(siv: &mut Cursive) -> Option<DuplicateEntry> {
+        siv.call_on_name("duplicates", |v: &mut DuplicateTable| {
+            v.item().and_then(|i| v.borrow_item(i)).cloned()
+        })
+        .flatten()
+    }
+
+    fn update_entry<F>(siv: &mut Cursive, track: &Track, edit: F)
+    where
+        F: Fn(&mut Track),
+    {
+        siv.call_on_name("duplicates", |v: &mut DuplicateTable| {
+            for entry in v.borrow_items_mut() {
+                if entry.track == *track {
+                    edit(&mut entry.track);
+                }
+            }
+        });
+    }
+
+    /// Searches for duplicates in the background, since fingerprinting has to decode every candidate
+    fn search(siv: &mut Cursive, state: &SharedState, fingerprint: bool) {
+        let tracks = state.tracks.lock().unwrap().clone();
+        let cb_sink = siv.cb_sink().clone();
+
+        siv.call_on_name("duplicates", |v: &mut DuplicateTable| v.clear());
+
+        std::thread::spawn(move || {
+            let mut groups = duplicates::find_duplicates(&tracks, DUPLICATE_TOLERANCE);
+            if fingerprint {
+                groups = duplicates::confirm_with_fingerprints(groups);
+            }
+
+            let entries: Vec<DuplicateEntry> = groups
+                .into_iter()
+                .enumerate()
+                .flat_map(|(i, group)| {
+                    group.into_iter().map(move |track| DuplicateEntry {
+                        group: i + 1,
+                        track,
+                    })
+                })
+                .collect();
+
+            let _ = cb_sink.send(Box::new(move |siv| {
+                siv.call_on_name("duplicates", |v: &mut DuplicateTable| {
+                    v.set_items(entries);
+                    v.sort_by(DuplicateField::Group, cmp::Ordering::Less);
+                });
+            }));
+        });
+    }
+
+    cursive::inner_getters!(self.inner: VimKeys<DuplicatePanel>);
+}
+
+impl ViewWrapper for DuplicatesView {
+    cursive::wrap_impl!(self.inner: VimKeys<DuplicatePanel>);
+}
+
+#[derive(Default)]
+struct CoverArt {
+    path: Option<PathBuf>,
+    image: Option<Arc<RgbImage>>,
+}
+
+/// Shows the current track's details and cover art
+struct NowPlayingView {
+    state: SharedState,
+    /// The track playing or paused, as of the last time the engine said something
+    track: Option<Track>,
+    art: Arc<Mutex<CoverArt>>,
+}
+
+impl NowPlayingView {
+    /// Lines of text above the cover
+    const HEADER_HEIGHT: usize = 4;
+
+    fn new(state: SharedState) -> Self {
+        Self {
+            state,
+            track: None,
+            art: Arc::new(Mutex::new(CoverArt::default())),
+        }
+    }
+
+    fn show(&mut self, track: Option<Track>) {
+        self.track = track;
+    }
+
+    /// Returns the cover for `track` if it's loaded, and starts loading it in the background otherwise
+    fn cover(&self, track: &Track) -> Option<Arc<RgbImage>> {
+        let mut art = self.art.lock().unwrap();
+        if art.path.as_ref() == Some(&track.path) {
+            return art.image.clone();
+        }
+
+        art.path = Some(track.path.clone());
+        art.image = None;
+
+        let shared = self.art.clone();
+        let bus = self.state.bus.clone();
+        let track = track.clone();
+        std::thread::spawn(move || {
+            let image = art::thumbnail(&track);
+            let mut art = shared.lock().unwrap();
+            // The track may have changed while we were loading
+            if art.path.as_ref() == Some(&track.path) {
+                art.image = image;
+                bus.notify(Message::Ui(UiCommand::Redraw));
+            }
+        });
+
+        None
+    }
+
+    fn draw_half_blocks(printer: &cursive::Printer, image: &RgbImage, cols: usize, rows: usize) {
+        let image = art::fit(image, cols, rows, (1, 2));
+        let rgb = |x: u32, y: u32| {
+            let p = image.get_pixel(x, y);
+            Color::Rgb(p[0], p[1], p[2])
+        };
+
+        for y in 0..image.height().div_ceil(2) {
+            for x in 0..image.width() {
+                let top = rgb(x, y * 2);
+                let bottom = if y * 2 + 1 < image.height() {
+                    rgb(x, y * 2 + 1)
+                } else {
+                    Color::TerminalDefault
+                };
+                printer.with_color(ColorStyle::new(top, bottom), |p| {
+                    p.print((x as usize, y as usize), "▀")
+                });
+            }
+        }
+    }
+}
+
+impl View for NowPlayingView {
+    fn draw(&self, printer: &cursive::Printer) {
+        let Some(track) = &self.track else {
+            printer.print((0, 0), "Nothing playing");
+            return;
+        };
+
+        printer.with_effect(Effect::Bold, |p| {
+            p.print((0, 0), &track.cached_field_string(CachedField::Title))
+        });
+        printer.print((0, 1), &track.cached_field_string(CachedField::Artist));
+        printer.print((0, 2), &track.cached_field_string(CachedField::Album));
+
+        let cols = printer.size.x;
+        let rows = printer.size.y.saturating_sub(Self::HEADER_HEIGHT);
+        if cols == 0 || rows == 0 {
+            return;
+        }
+
+        let Some(image) = self.cover(track) else {
+            return;
+        };
+
+        let (cols, rows) = art::fitted_cells(&image, cols, rows);
+        let printer = printer.offset((0, Self::HEADER_HEIGHT));
+        let mut overlay = self.state.art_overlay.lock().unwrap();
+        match overlay.protocol {
+            Protocol::HalfBlocks => Self::draw_half_blocks(&printer, &image, cols, rows),
+            _ => overlay.request(Placement {
+                x: printer.offset.x,
+                y: printer.offset.y,
+                cols,
+                rows,
+                id: track.path.clone(),
+                image,
+            }),
+        }
+    }
+
+    fn required_size(&mut self, constraint: cursive::Vec2) -> cursive::Vec2 {
+        constraint
+    }
+}
+
 struct LyricsView {
     state: SharedState,
     content: TextContent,
     inner: ScrollNamedText,
+    /// The track playing or paused and how far into it, as of the last time the engine said something
+    track: Option<Track>,
+    position: Duration,
+    /// The line to scroll to at the next layout, counting from the start of the text
+    scroll_to: Option<usize>,
+    /// The lyrics shown, along with the track they belong to. `None` while they're loading.
+    lyrics: Option<(PathBuf, Option<Option<Arc<Lyrics>>>)>,
+    /// Manual adjustment for badly timed lyrics, in milliseconds. Positive values show lines later.
+    offset: i64,
+    /// The highlighted line and offset from the last layout, so we only rebuild the text when they change
+    shown: Option<(Option<usize>, i64)>,
 }
 
 impl LyricsView {
+    /// How far each press of `+` or `-` moves the lyrics
+    const OFFSET_STEP_MS: i64 = 250;
+
     fn new(state: SharedState) -> Self {
         let content = TextContent::new("");
         let view = TextView::new_with_content(content.clone())
@@ -214,59 +1064,284 @@ impl LyricsView {
             state,
             content,
             inner: view,
+            track: None,
+            position: Duration::ZERO,
+            scroll_to: None,
+            lyrics: None,
+            offset: 0,
+            shown: None,
+        }
+    }
+
+    fn show(&mut self, track: Option<Track>, position: Duration) {
+        self.track = track;
+        self.position = position;
+        self.refresh();
+    }
+
+    /// Rebuilds the text if needed, keeping the highlighted line in view
+    fn refresh(&mut self) {
+        if let Some(line) = self.update() {
+            self.scroll_to = Some(line);
+        }
+    }
+
+    fn position(&self) -> Duration {
+        let position = self.position;
+        if self.offset >= 0 {
+            position.saturating_sub(Duration::from_millis(self.offset as u64))
+        } else {
+            position + Duration::from_millis(self.offset.unsigned_abs())
         }
     }
 
-    cursive::inner_getters!(self.inner: ScrollNamedText);
+    /// Reloads the lyrics if the track changed, and rebuilds the text if the highlighted line moved.
+    /// Returns the line of the text the highlight is on, if it changed.
+    fn update(&mut self) -> Option<usize> {
+        let track = self.track.clone();
+        let path = track.as_ref().map(|t| t.path.clone());
+        if self.lyrics.as_ref().map(|(p, _)| p) != path.as_ref() {
+            self.offset = 0;
+        }
+
+        // This is only a map lookup, the cache does the actual loading in the background
+        let lyrics = track.map(|t| (t.path.clone(), self.state.lyrics.get(&t)));
+        let same_lyrics = match (&self.lyrics, &lyrics) {
+            (None, None) => true,
+            (Some((old_path, old)), Some((path, new))) => {
+                old_path == path
+                    && match (old, new) {
+                        (None, None) | (Some(None), Some(None)) => true,
+                        (Some(Some(old)), Some(Some(new))) => Arc::ptr_eq(old, new),
+                        _ => false,
+                    }
+            }
+            _ => false,
+        };
+        if !same_lyrics {
+            self.lyrics = lyrics;
+            self.shown = None;
+        }
+
+        let lyrics = match &self.lyrics {
+            Some((_, Some(Some(lyrics)))) => lyrics.clone(),
+            other => {
+                if self.shown.is_none() {
+                    let loading = matches!(other, Some((_, None)));
+                    self.content
+                        .set_content(if loading { "Loading lyrics..." } else { "" });
+                    self.shown = Some((None, self.offset));
+                }
+                return None;
+            }
+        };
+
+        let current = lyrics.current_line(self.position());
+        if self.shown == Some((current, self.offset)) {
+            return None;
+        }
+        self.shown = Some((current, self.offset));
+
+        let mut text = StyledString::new();
+        let mut header_rows = 0;
+        if self.offset != 0 {
+            text.append_styled(
+                format!("Offset: {:+.2}s\n\n", self.offset as f64 / 1000.0),
+                Effect::Italic,
+            );
+            header_rows = 2;
+        }
+
+        match lyrics.as_ref() {
+            Lyrics::Plain(plain) => text.append_plain(plain),
+            Lyrics::Synced(lines) => {
+                for (i, line) in lines.iter().enumerate() {
+                    if Some(i) == current {
+                        text.append_styled(
+                            &line.text,
+                            Style::from(Effect::Reverse).combine(Effect::Bold),
+                        );
+                    } else {
+                        text.append_plain(&line.text);
+                    }
+                    text.append_plain("\n");
+                }
+            }
+        }
+
+        self.content.set_content(text);
+        current.map(|line| line + header_rows)
+    }
 }
 
 impl ViewWrapper for LyricsView {
     cursive::wrap_impl!(self.inner: ScrollNamedText);
 
-    fn wrap_draw(&self, printer: &cursive::Printer) {
-        let mut content = String::new();
+    fn wrap_layout(&mut self, size: cursive::Vec2) {
+        self.inner.layout(size);
 
-        {
-            let queue = self.state.queue.lock().unwrap();
+        // Keep the highlighted line in the middle of the screen. Long lines wrap, so the row it's on depends on how
+        // many rows each line above it takes at this width.
+        if let Some(line) = self.scroll_to.take() {
+            let width = self.inner.content_viewport().width();
+            let content = self.content.get_content();
+            let row: usize = content
+                .source()
+                .split('\n')
+                .take(line)
+                .map(|l| LinesIterator::new(l, width).count().max(1))
+                .sum();
+            self.inner.set_offset((0, row.saturating_sub(size.y / 2)));
+        }
+    }
 
-            if let Some(track) = queue.get(*self.state.queue_index.lock().unwrap()) {
-                content = track
-                    .tag_string_from_track(ItemKey::Lyrics)
-                    .unwrap_or_default();
+    fn wrap_on_event(&mut self, event: Event) -> EventResult {
+        match help::action(KeyScope::Lyrics, &event) {
+            Some(ViewAction::Later) => {
+                self.offset += Self::OFFSET_STEP_MS;
+                self.refresh();
+                EventResult::Consumed(None)
+            }
+            Some(ViewAction::Earlier) => {
+                self.offset -= Self::OFFSET_STEP_MS;
+                self.refresh();
+                EventResult::Consumed(None)
             }
+            _ => self.inner.on_event(event),
         }
-
-        self.content.set_content(content);
-        self.with_view(|v| v.draw(printer));
     }
 }
 
 pub(crate) struct PlayerView {
-    inner: TabPanel,
+    inner: LinearLayout,
     state: SharedState,
+    /// The height last given to the tabs, to only resize them when it changes
+    tabs_height: usize,
 }
 
 impl PlayerView {
-    pub(crate) fn new(state: SharedState) -> Self {
+    pub(crate) fn new(state: SharedState, config: &Config) -> Self {
         let mut tab_view = TabPanel::new()
-            .with_tab(LibraryView::new(state.clone()).with_name("Library"))
-            .with_tab(LyricsView::new(state.clone()).with_name("Lyrics"));
+            .with_tab(LibraryView::new(state.clone(), config).with_name(TABS[0]))
+            .with_tab(NowPlayingView::new(state.clone()).with_name(TABS[1]))
+            .with_tab(LyricsView::new(state.clone()).with_name(TABS[2]))
+            .with_tab(DuplicatesView::new(state.clone()).with_name(TABS[3]));
 
         // We can't use .with_active_tab() when constructing because it uses Self as the Err type,
         // which doesn't implement Debug, meaning we can't call .expect() on it
         tab_view
-            .set_active_tab("Library")
-            .expect("Setting default tab shouldn't fail");
+            .set_active_tab(&config.startup_tab)
+            .expect("The startup tab is checked when loading the config");
+
+        let layout = LinearLayout::vertical()
+            .child(tab_view.full_screen())
+            .child(Self::command_line(state.clone()));
 
         Self {
-            inner: tab_view,
+            inner: layout,
             state,
+            tabs_height: 0,
+        }
+    }
+
+    /// The `:` prompt at the bottom of the screen, hidden until it's opened
+    fn command_line(state: SharedState) -> NamedView<CommandLineRow> {
+        let edit = EditView::new()
+            .on_submit(move |siv, line| {
+                close_command_line(siv);
+                if line.trim().is_empty() {
+                    return;
+                }
+                match line.parse::<Action>() {
+                    Ok(action) => action.run(siv),
+                    Err(e) => state.toasts.error(e.to_string()),
+                }
+            })
+            .with_name(COMMAND_LINE);
+
+        let mut edit = OnEventView::new(edit);
+        for (event, action) in help::presses(KeyScope::CommandLine) {
+            match action {
+                ViewAction::Complete => edit.set_on_pre_event_inner(event, complete_command_line),
+                ViewAction::Close => edit.set_on_event(event, close_command_line),
+                _ => (),
+            }
         }
+
+        let row = LinearLayout::horizontal()
+            .child(TextView::new(":"))
+            .child(edit.full_width());
+
+        HideableView::new(row.fixed_height(1))
+            .hidden()
+            .with_name(COMMAND_LINE_ROW)
     }
 
-    cursive::inner_getters!(self.inner: TabPanel);
+    cursive::inner_getters!(self.inner: LinearLayout);
+}
+
+type CommandLineRow = HideableView<ResizedView<LinearLayout>>;
+
+const COMMAND_LINE: &str = "command_line";
+const COMMAND_LINE_ROW: &str = "command_line_row";
+
+/// Shows the command line, starting with `line`, and moves the focus there
+pub(crate) fn open_command_line(siv: &mut Cursive, line: &str) {
+    siv.call_on_name(COMMAND_LINE_ROW, |v: &mut CommandLineRow| v.unhide());
+    siv.call_on_name(COMMAND_LINE, |v: &mut EditView| v.set_content(line));
+    let _ = siv.focus_name(COMMAND_LINE);
+}
+
+/// Completes what's typed so far, listing the candidates if there's more than one
+fn complete_command_line(v: &mut NamedView<EditView>, _: &Event) -> Option<EventResult> {
+    let mut edit = v.get_mut();
+    let (line, candidates) = actions::complete(&edit.get_content());
+    let cb = edit.set_content(line);
+    Some(
+        EventResult::Consumed(Some(cb)).and(EventResult::with_cb(move |siv| {
+            if candidates.len() > 1 {
+                let state = siv.user_data::<SharedState>().expect("Missing state?");
+                state.toasts.info(candidates.join("  "));
+            }
+        })),
+    )
+}
+
+fn close_command_line(siv: &mut Cursive) {
+    siv.call_on_name(COMMAND_LINE_ROW, |v: &mut CommandLineRow| v.hide());
+    siv.call_on_name("player", |v: &mut PlayerView| {
+        let _ = v.get_inner_mut().set_focus_index(0);
+    });
 }
 
 impl ViewWrapper for PlayerView {
-    cursive::wrap_impl!(self.inner: TabPanel);
+    cursive::wrap_impl!(self.inner: LinearLayout);
+
+    fn wrap_draw(&self, printer: &cursive::Printer) {
+        self.state.art_overlay.lock().unwrap().begin_frame();
+        self.inner.draw(printer);
+        self.state.toasts.draw(printer);
+    }
+
+    fn wrap_layout(&mut self, size: cursive::Vec2) {
+        // The tabs ask for more than the whole screen, which would squeeze the command line out of sight, so they
+        // get a fixed height of whatever it leaves
+        let line = self
+            .inner
+            .get_child_mut(1)
+            .map_or(0, |row| row.required_size(size).y);
+        let height = size.y.saturating_sub(line);
+        if height != self.tabs_height {
+            self.tabs_height = height;
+            if let Some(tabs) = self
+                .inner
+                .get_child_mut(0)
+                .and_then(|tabs| tabs.downcast_mut::<ResizedView<TabPanel>>())
+            {
+                tabs.set_height(SizeConstraint::Fixed(height));
+            }
+        }
+
+        self.inner.layout(size);
+    }
 }
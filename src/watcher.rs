@@ -0,0 +1,141 @@
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+
+use crate::bus::{Bus, LibraryEvent, Message};
+use crate::files::Track;
+use crate::library::Library;
+
+/// How long to wait for a burst of filesystem events to settle, e.g., while a tagger rewrites a file
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A change to the library found by the watcher
+#[derive(Clone, Debug)]
+pub(crate) enum LibraryChange {
+    /// A file was added or modified. Its tags have already been read.
+    Updated(Track),
+    /// A file or directory disappeared
+    Removed(PathBuf),
+    /// A file next to a track that affects it, e.g., a `.lrc` file, changed
+    Sidecar(PathBuf),
+}
+
+/// Works out what changed at `path`. Tags are read here, so this should run off the UI thread.
+fn changes_at(library: &Library, path: &Path) -> Vec<LibraryChange> {
+    if !path.exists() {
+        return vec![LibraryChange::Removed(path.to_path_buf())];
+    }
+
+    if path.is_dir() {
+        // Directories moved into the library only produce one event, so look inside them
+        return library
+            .scan_dir(path)
+            .into_iter()
+            .map(LibraryChange::Updated)
+            .collect();
+    }
+
+    match library.read_track(path) {
+        Some(track) => vec![LibraryChange::Updated(track)],
+        None => vec![LibraryChange::Sidecar(path.to_path_buf())],
+    }
+}
+
+/// Watches every library root for changes and posts them on `bus`. Watching stops when the returned debouncer is
+/// dropped. Roots that can't be watched are passed to `report` and the rest are still watched; it's only an error
+/// if none of them can be.
+pub(crate) fn watch(
+    library: Arc<Library>,
+    bus: Bus,
+    report: impl Fn(String),
+) -> Result<Debouncer<RecommendedWatcher>> {
+    let (tx, rx) = mpsc::channel::<DebounceEventResult>();
+    let mut debouncer = new_debouncer(DEBOUNCE_TIMEOUT, tx)?;
+    let failures: Vec<String> = library
+        .roots
+        .iter()
+        .filter_map(|root| {
+            let watched = debouncer
+                .watcher()
+                .watch(&root.path, RecursiveMode::Recursive);
+            watched
+                .err()
+                .map(|e| format!("{}: {e}", root.path.display()))
+        })
+        .collect();
+    if !library.roots.is_empty() && failures.len() == library.roots.len() {
+        bail!(
+            "Not watching the library for changes: {}",
+            failures.join(", ")
+        );
+    }
+    for failure in failures {
+        report(format!("Not watching {failure} for changes"));
+    }
+
+    std::thread::spawn(move || {
+        // Ends when the debouncer is dropped, since that closes the channel
+        for events in rx {
+            let Ok(events) = events else {
+                continue;
+            };
+
+            let changes: Vec<LibraryChange> = events
+                .iter()
+                .flat_map(|e| changes_at(&library, &e.path))
+                .collect();
+            if !changes.is_empty() {
+                bus.notify(Message::Library(LibraryEvent::Changed(changes)));
+            }
+        }
+    });
+
+    Ok(debouncer)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::fs;
+
+    use super::*;
+    use crate::library::LibraryRoot;
+
+    fn library(roots: &[&Path]) -> Arc<Library> {
+        let roots = roots
+            .iter()
+            .map(|p| LibraryRoot::new(p.to_path_buf()))
+            .collect();
+        Arc::new(Library::new(roots))
+    }
+
+    #[test]
+    fn one_missing_root_doesnt_stop_the_others_being_watched() {
+        let missing =
+            std::env::temp_dir().join(format!("minim-test-{}-missing", std::process::id()));
+        let present =
+            std::env::temp_dir().join(format!("minim-test-{}-present", std::process::id()));
+        fs::create_dir_all(&present).unwrap();
+        let reported = RefCell::new(Vec::new());
+        let watched = watch(library(&[&present, &missing]), Bus::default(), |e| {
+            reported.borrow_mut().push(e)
+        });
+
+        assert!(watched.is_ok());
+        let reported = reported.into_inner();
+        assert_eq!(reported.len(), 1);
+        assert!(reported[0].contains(&*missing.to_string_lossy()));
+    }
+
+    #[test]
+    fn no_roots_being_watched_is_an_error() {
+        let missing =
+            std::env::temp_dir().join(format!("minim-test-{}-missing", std::process::id()));
+        let watched = watch(library(&[&missing]), Bus::default(), |_| ());
+        assert!(watched.is_err());
+    }
+}
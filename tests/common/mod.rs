@@ -0,0 +1,122 @@
+//! Made up files, engines and buses shared by the tests
+
+// Each test file uses its own share of these
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{self, Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use minim::{Engine, Playback, Track};
+
+pub const SAMPLE_RATE: u32 = 8000;
+
+/// A directory of its own for each test, removed when it's dropped
+pub struct Scratch(pub PathBuf);
+
+impl Scratch {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("minim-test-{}-{name}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    /// A tenth of a second of silence, titled `title`
+    pub fn track(&self, title: &str) -> Track {
+        self.track_lasting(title, Duration::from_millis(100))
+    }
+
+    /// `length` of silence, titled `title`
+    pub fn track_lasting(&self, title: &str, length: Duration) -> Track {
+        let samples = SAMPLE_RATE as u128 * length.as_millis() / 1000;
+        self.track_of(title, &vec![0; samples as usize])
+    }
+
+    /// A track playing `samples` at [`SAMPLE_RATE`], titled `title`
+    pub fn track_of(&self, title: &str, samples: &[i16]) -> Track {
+        let path = self.0.join(format!("{title}.wav"));
+        fs::write(&path, wav(title, samples)).unwrap();
+        Track::try_from(path.as_path()).unwrap()
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn chunk(id: &[u8], mut body: Vec<u8>) -> Vec<u8> {
+    let mut chunk = id.to_vec();
+    chunk.extend((body.len() as u32).to_le_bytes());
+    if body.len() % 2 == 1 {
+        body.push(0);
+    }
+    chunk.extend(body);
+    chunk
+}
+
+/// A mono 16-bit WAV file with a RIFF INFO title, by "Someone"
+fn wav(title: &str, samples: &[i16]) -> Vec<u8> {
+    let mut format = Vec::new();
+    format.extend(1u16.to_le_bytes());
+    format.extend(1u16.to_le_bytes());
+    format.extend(SAMPLE_RATE.to_le_bytes());
+    format.extend((SAMPLE_RATE * 2).to_le_bytes());
+    format.extend(2u16.to_le_bytes());
+    format.extend(16u16.to_le_bytes());
+
+    let mut info = b"INFO".to_vec();
+    info.extend(chunk(b"INAM", format!("{title}\0").into_bytes()));
+    info.extend(chunk(b"IART", b"Someone\0".to_vec()));
+
+    let mut wave = b"WAVE".to_vec();
+    wave.extend(chunk(b"fmt ", format));
+    wave.extend(chunk(b"LIST", info));
+    let data = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+    wave.extend(chunk(b"data", data));
+    chunk(b"RIFF", wave)
+}
+
+/// An engine whose output is pulled as fast as it goes on another thread. It starts paused, since paused tracks
+/// don't move on, so tests decide when time passes.
+pub fn engine() -> Engine {
+    let (engine, output) = Engine::idle();
+    engine.pause();
+    thread::spawn(move || output.for_each(drop));
+    engine
+}
+
+/// A private session bus, stopped when it's dropped
+pub struct Bus {
+    daemon: Child,
+    pub address: String,
+}
+
+impl Bus {
+    pub fn start() -> Option<Self> {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .ok()?;
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take()?)
+            .read_line(&mut address)
+            .ok()?;
+        Some(Self {
+            daemon,
+            address: address.trim().to_owned(),
+        })
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
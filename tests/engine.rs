@@ -0,0 +1,195 @@
+//! Plays made up files through an engine with no sound card
+mod common;
+
+use std::fs;
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+
+use minim::{Event, Playback, QueueError};
+
+use common::{engine, Scratch};
+
+/// Waits for `count` events about tracks starting and ending
+fn wait_for_tracks(events: &Receiver<Event>, count: usize) -> Vec<(bool, usize, String)> {
+    let mut seen = Vec::new();
+    while seen.len() < count {
+        let event = events
+            .recv_timeout(Duration::from_secs(5))
+            .expect("The tracks should have played by now");
+        seen.extend(track_event(event));
+    }
+    seen
+}
+
+/// An event about a track starting or ending, as (started, index, title)
+fn track_event(event: Event) -> Option<(bool, usize, String)> {
+    let (started, index, track) = match event {
+        Event::TrackStarted { index, track } => (true, index, track),
+        Event::TrackEnded { index, track, .. } => (false, index, track),
+        _ => return None,
+    };
+    Some((started, index, track.title().unwrap().to_owned()))
+}
+
+fn started(index: usize, title: &str) -> (bool, usize, String) {
+    (true, index, title.to_owned())
+}
+
+fn ended(index: usize, title: &str) -> (bool, usize, String) {
+    (false, index, title.to_owned())
+}
+
+#[test]
+fn tracks_play_through_in_order() {
+    let scratch = Scratch::new("order");
+    let engine = engine();
+    let events = engine.subscribe();
+
+    engine.enqueue(&scratch.track("One")).unwrap();
+    engine.enqueue(&scratch.track("Two")).unwrap();
+    assert_eq!(engine.current().unwrap().title(), Some("One"));
+    engine.play();
+
+    assert_eq!(
+        wait_for_tracks(&events, 4),
+        [
+            started(0, "One"),
+            ended(0, "One"),
+            started(1, "Two"),
+            ended(1, "Two"),
+        ][..]
+    );
+    assert_eq!(engine.queue_index(), 2);
+    assert!(engine.current().is_none());
+}
+
+#[test]
+fn skipping_and_going_back() {
+    let scratch = Scratch::new("skipping");
+    let engine = engine();
+    engine.enqueue(&scratch.track("One")).unwrap();
+    engine.enqueue(&scratch.track("Two")).unwrap();
+    let events = engine.subscribe();
+
+    engine.next();
+    assert_eq!(engine.current().unwrap().title(), Some("Two"));
+    engine.previous().unwrap();
+    assert_eq!(engine.current().unwrap().title(), Some("One"));
+
+    assert_eq!(
+        events
+            .try_iter()
+            .filter_map(track_event)
+            .collect::<Vec<_>>(),
+        [
+            ended(0, "One"),
+            started(1, "Two"),
+            ended(1, "Two"),
+            started(0, "One"),
+        ]
+    );
+}
+
+#[test]
+fn files_that_went_missing() {
+    let scratch = Scratch::new("missing");
+    let engine = engine();
+    let gone = scratch.track("Gone");
+    fs::remove_file(gone.path()).unwrap();
+    assert!(matches!(engine.enqueue(&gone), Err(QueueError::Missing)));
+    assert!(engine.queue().is_empty());
+
+    // Already queued, then deleted before it's played again
+    let track = scratch.track("Deleted");
+    engine.enqueue(&track).unwrap();
+    fs::remove_file(track.path()).unwrap();
+    let events = engine.subscribe();
+    engine.play_from(0).unwrap();
+
+    let errors: Vec<String> = events
+        .try_iter()
+        .filter_map(|event| match event {
+            Event::Error(message) => Some(message),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(errors, ["Couldn't play Deleted: the file is missing"]);
+}
+
+#[test]
+fn seeking_reports_the_position() {
+    let scratch = Scratch::new("seeking");
+    let engine = engine();
+    assert!(engine.seek(Duration::ZERO).is_err());
+
+    engine.enqueue(&scratch.track("One")).unwrap();
+    let events = engine.subscribe();
+    engine.seek(Duration::from_millis(50)).unwrap();
+    assert_eq!(
+        events.try_recv().unwrap(),
+        Event::PositionChanged(Duration::from_millis(50))
+    );
+}
+
+#[test]
+fn stopping_until_played_again() {
+    let scratch = Scratch::new("stopping");
+    let engine = engine();
+    engine.enqueue(&scratch.track("One")).unwrap();
+    engine.enqueue(&scratch.track("Two")).unwrap();
+
+    engine.stop();
+    assert!(engine.is_stopped() && engine.is_paused());
+    engine.pause();
+    assert!(engine.is_stopped());
+    engine.play();
+    assert!(!engine.is_stopped());
+
+    engine.stop();
+    engine.play_from(1).unwrap();
+    assert!(!engine.is_stopped());
+}
+
+#[test]
+fn skipping_as_tracks_end_keeps_the_queue_in_step() {
+    let scratch = Scratch::new("racing");
+    let engine = engine();
+    let titles: Vec<String> = (0..30).map(|i| format!("T{i}")).collect();
+    for title in &titles {
+        engine.enqueue(&scratch.track(title)).unwrap();
+    }
+    let events = engine.subscribe();
+
+    // Tracks are short enough to keep ending by themselves while these land
+    engine.play();
+    for _ in 0..20 {
+        engine.next();
+    }
+
+    // Every track that ends was the one playing, and the queue only moves forwards. The first track started as it was queued.
+    let mut playing = Some(0);
+    let mut last = Some(0);
+    loop {
+        let event = events
+            .recv_timeout(Duration::from_secs(5))
+            .expect("The queue should have run out by now");
+        match track_event(event) {
+            Some((true, index, title)) => {
+                assert_eq!(playing, None, "{title} started over another track");
+                assert!(last < Some(index), "{title} started again");
+                assert_eq!(title, titles[index]);
+                playing = Some(index);
+                last = playing;
+            }
+            Some((false, index, title)) => {
+                assert_eq!(playing, Some(index), "{title} ended without playing");
+                playing = None;
+                if index == titles.len() - 1 {
+                    break;
+                }
+            }
+            None => (),
+        }
+    }
+    assert_eq!(engine.queue_index(), titles.len());
+}
@@ -0,0 +1,197 @@
+//! Talks to the control socket the way `minim ctl` and scripts do, one JSON object per line each way
+mod common;
+
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use minim::ipc::{self, Server};
+use minim::{Core, Library, LibraryRoot};
+use serde_json::{json, Value};
+
+use common::Scratch;
+
+/// The socket's path comes from the environment, so only one test can have a player listening at a time
+static LISTENING: Mutex<()> = Mutex::new(());
+
+/// A player on a library of two tracks in `scratch`, listening in a runtime directory inside it
+fn serve(scratch: &Scratch) -> (Server, MutexGuard<'static, ()>) {
+    let listening = LISTENING.lock().unwrap_or_else(|e| e.into_inner());
+    scratch.track("One");
+    scratch.track("Two");
+    let library = Library::new(vec![LibraryRoot::new(scratch.0.clone())]);
+    let tracks = library.scan();
+    let core = Core::new(common::engine(), Arc::new(library), tracks);
+
+    let runtime = scratch.0.join("runtime");
+    std::fs::create_dir_all(&runtime).unwrap();
+    env::set_var("XDG_RUNTIME_DIR", &runtime);
+    (ipc::serve(core).unwrap(), listening)
+}
+
+struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Client {
+    fn connect(scratch: &Scratch) -> Self {
+        let writer = UnixStream::connect(scratch.0.join("runtime/minim.sock")).unwrap();
+        writer
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        Self {
+            reader: BufReader::new(writer.try_clone().unwrap()),
+            writer,
+        }
+    }
+
+    fn send(&mut self, line: &str) {
+        writeln!(self.writer, "{line}").unwrap();
+    }
+
+    fn reply(&mut self) -> Value {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    /// Reads replies until one matches `done`, since one request can cause a few changes
+    fn reply_until(&mut self, done: impl Fn(&Value) -> bool) -> Value {
+        loop {
+            let reply = self.reply();
+            if done(&reply) {
+                return reply;
+            }
+        }
+    }
+
+    fn request(&mut self, request: Value) -> Value {
+        self.send(&request.to_string());
+        self.reply()
+    }
+}
+
+fn queued_paths(client: &mut Client) -> Vec<String> {
+    let reply = client.request(json!({"command": "queue"}));
+    assert_eq!(reply["reply"], "queue");
+    reply["tracks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t["path"].as_str().unwrap().to_owned())
+        .collect()
+}
+
+fn path_string(path: &Path) -> String {
+    path.to_str().unwrap().to_owned()
+}
+
+#[test]
+fn replies_are_tagged_with_what_they_are() {
+    let scratch = Scratch::new("ipc-replies");
+    let (_server, _listening) = serve(&scratch);
+    let mut client = Client::connect(&scratch);
+
+    let status = client.request(json!({"command": "status"}));
+    assert_eq!(status["reply"], "status");
+    assert_eq!(status["queue_length"], 0);
+    assert_eq!(status["track"], Value::Null);
+
+    let reply = client.request(json!({"command": "volume", "percent": 40}));
+    assert_eq!(reply, json!({"reply": "ok"}));
+    let status = client.request(json!({"command": "status"}));
+    assert_eq!(status["volume"], 40);
+
+    assert_eq!(queued_paths(&mut client), Vec::<String>::new());
+}
+
+#[test]
+fn bad_requests_get_errors_and_the_connection_stays_open() {
+    let scratch = Scratch::new("ipc-errors");
+    let (_server, _listening) = serve(&scratch);
+    let mut client = Client::connect(&scratch);
+
+    client.send("not json");
+    let reply = client.reply();
+    assert_eq!(reply["reply"], "error");
+    assert!(reply["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("Couldn't read request"));
+
+    let reply = client.request(json!({"command": "dance"}));
+    assert_eq!(reply["reply"], "error");
+
+    let reply = client.request(json!({"command": "play-from", "index": 3}));
+    assert_eq!(reply["reply"], "error");
+
+    let missing = scratch.0.join("Missing.wav");
+    let reply = client.request(json!({"command": "enqueue", "path": missing}));
+    assert_eq!(reply["reply"], "error");
+    assert!(reply["message"]
+        .as_str()
+        .unwrap()
+        .contains(&path_string(&missing)));
+
+    let status = client.request(json!({"command": "status"}));
+    assert_eq!(status["reply"], "status");
+}
+
+#[test]
+fn enqueued_paths_are_resolved_to_library_tracks() {
+    let scratch = Scratch::new("ipc-enqueue");
+    let (_server, _listening) = serve(&scratch);
+    let mut client = Client::connect(&scratch);
+    let root = scratch.0.canonicalize().unwrap();
+
+    // Paths are canonicalized, so a roundabout one still finds the library's copy
+    let roundabout = scratch.0.join("runtime/../One.wav");
+    let reply = client.request(json!({"command": "enqueue", "path": roundabout}));
+    assert_eq!(reply, json!({"reply": "ok"}));
+
+    // Files outside the library are read fresh, so they have no root
+    let outside = Scratch::new("ipc-enqueue-outside");
+    outside.track("Three");
+    let three = outside.0.join("Three.wav");
+    let reply = client.request(json!({"command": "enqueue", "path": three}));
+    assert_eq!(reply, json!({"reply": "ok"}));
+
+    let reply = client.request(json!({"command": "queue"}));
+    let tracks = reply["tracks"].as_array().unwrap();
+    assert_eq!(tracks.len(), 2);
+    assert_eq!(tracks[0]["path"], path_string(&root.join("One.wav")));
+    assert_eq!(tracks[0]["root"], path_string(&scratch.0));
+    assert_eq!(
+        tracks[1]["path"],
+        path_string(&three.canonicalize().unwrap())
+    );
+    assert_eq!(tracks[1]["root"], "");
+}
+
+#[test]
+fn subscribers_hear_every_change() {
+    let scratch = Scratch::new("ipc-subscribe");
+    let (_server, _listening) = serve(&scratch);
+    let mut subscriber = Client::connect(&scratch);
+    let mut client = Client::connect(&scratch);
+
+    let reply = subscriber.request(json!({"command": "subscribe"}));
+    assert_eq!(reply, json!({"reply": "ok"}));
+
+    let two = scratch.0.join("Two.wav");
+    client.request(json!({"command": "enqueue", "path": two}));
+    let changed = subscriber.reply_until(|r| r["queue_length"] == 1);
+    assert_eq!(changed["reply"], "changed");
+    assert_eq!(
+        changed["track"]["path"],
+        path_string(&two.canonicalize().unwrap())
+    );
+
+    client.request(json!({"command": "play"}));
+    let changed = subscriber.reply_until(|r| r["paused"] == false);
+    assert_eq!(changed["reply"], "changed");
+}
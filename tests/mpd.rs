@@ -0,0 +1,338 @@
+//! Drives the MPD server over localhost the way a client like mpc would, against a made up library
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+use minim::mpd::{Backend, Request, Server, Song, State, Status};
+
+fn song(file: &str, artist: &str, album: &str, title: &str) -> Song {
+    Song {
+        file: file.to_owned(),
+        title: title.to_owned(),
+        artist: artist.to_owned(),
+        album: album.to_owned(),
+        duration: Duration::from_secs(180),
+    }
+}
+
+/// Plays along with requests well enough for clients to see their effect
+#[derive(Clone, Default)]
+struct FakeBackend {
+    library: Vec<Song>,
+    queue: Arc<Mutex<Vec<Song>>>,
+    status: Arc<Mutex<Status>>,
+    requests: Arc<Mutex<Vec<Request>>>,
+    subscribers: Arc<Mutex<Vec<mpsc::Sender<()>>>>,
+}
+
+impl Backend for FakeBackend {
+    fn status(&self) -> Status {
+        self.status.lock().unwrap().clone()
+    }
+
+    fn queue(&self) -> Vec<Song> {
+        self.queue.lock().unwrap().clone()
+    }
+
+    fn library(&self) -> Vec<Song> {
+        self.library.clone()
+    }
+
+    fn request(&self, request: Request) -> Result<(), String> {
+        {
+            let mut status = self.status.lock().unwrap();
+            let mut queue = self.queue.lock().unwrap();
+            match &request {
+                Request::Add(files) => {
+                    for file in files {
+                        queue.push(
+                            self.library
+                                .iter()
+                                .find(|s| s.file == *file)
+                                .unwrap()
+                                .clone(),
+                        );
+                    }
+                    if status.song.is_none() {
+                        status.song = Some(0);
+                        status.state = State::Play;
+                    }
+                }
+                Request::Play(position) => {
+                    status.song = position.or(status.song);
+                    status.state = State::Play;
+                }
+                Request::Pause(pause) => {
+                    let pause = pause.unwrap_or(status.state == State::Play);
+                    status.state = if pause { State::Pause } else { State::Play };
+                }
+                Request::Next => status.song = status.song.map(|i| i + 1),
+                Request::SetVolume(volume) => status.volume = *volume,
+                _ => {}
+            }
+        }
+        self.requests.lock().unwrap().push(request);
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.send(()).is_ok());
+        Ok(())
+    }
+
+    fn subscribe(&self) -> mpsc::Receiver<()> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+}
+
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Client {
+    fn connect(server: &Server) -> Self {
+        let writer = TcpStream::connect(server.address()).unwrap();
+        writer
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        writer.set_nodelay(true).unwrap();
+        let mut client = Self {
+            reader: BufReader::new(writer.try_clone().unwrap()),
+            writer,
+        };
+        assert_eq!(client.line(), "OK MPD 0.19.0");
+        client
+    }
+
+    fn line(&mut self) -> String {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        line.trim_end().to_owned()
+    }
+
+    fn send(&mut self, command: &str) {
+        writeln!(self.writer, "{command}").unwrap();
+    }
+
+    /// The reply up to and including the final `OK` or `ACK` line
+    fn read_reply(&mut self) -> Vec<String> {
+        let mut reply = Vec::new();
+        loop {
+            let line = self.line();
+            let done = line == "OK" || line.starts_with("ACK") || line.is_empty();
+            reply.push(line);
+            if done {
+                return reply;
+            }
+        }
+    }
+
+    fn command(&mut self, command: &str) -> Vec<String> {
+        self.send(command);
+        self.read_reply()
+    }
+
+    /// The value of every `key: value` line with this key
+    fn values(&mut self, command: &str, key: &str) -> Vec<String> {
+        let prefix = format!("{key}: ");
+        self.command(command)
+            .iter()
+            .filter_map(|l| l.strip_prefix(&prefix).map(str::to_owned))
+            .collect()
+    }
+}
+
+fn start() -> (Server, FakeBackend) {
+    let backend = FakeBackend {
+        library: vec![
+            song(
+                "Artist A/First Album/one.flac",
+                "Artist A",
+                "First Album",
+                "One",
+            ),
+            song(
+                "Artist A/First Album/two.flac",
+                "Artist A",
+                "First Album",
+                "Two",
+            ),
+            song("Artist B/three.flac", "Artist B", "", "Three"),
+            song("loose.mp3", "Artist B", "", "Loose"),
+        ],
+        status: Arc::new(Mutex::new(Status {
+            volume: 80,
+            ..Status::default()
+        })),
+        ..FakeBackend::default()
+    };
+    let server = Server::bind("127.0.0.1:0", backend.clone()).unwrap();
+    (server, backend)
+}
+
+#[test]
+fn status_of_an_empty_queue() {
+    let (server, _) = start();
+    let mut client = Client::connect(&server);
+
+    assert_eq!(client.values("status", "state"), ["stop"]);
+    assert_eq!(client.values("status", "volume"), ["80"]);
+    assert_eq!(client.values("status", "playlistlength"), ["0"]);
+    assert!(client.values("status", "song").is_empty());
+    assert_eq!(client.command("currentsong"), ["OK"]);
+    assert_eq!(client.command("ping"), ["OK"]);
+}
+
+#[test]
+fn browsing_and_searching_the_library() {
+    let (server, _) = start();
+    let mut client = Client::connect(&server);
+
+    assert_eq!(
+        client.values("lsinfo", "directory"),
+        ["Artist A", "Artist B"]
+    );
+    assert_eq!(client.values("lsinfo", "file"), ["loose.mp3"]);
+    assert_eq!(
+        client.values("lsinfo \"Artist A\"", "directory"),
+        ["Artist A/First Album"]
+    );
+    assert_eq!(
+        client.values("lsinfo \"Artist A/First Album\"", "file"),
+        [
+            "Artist A/First Album/one.flac",
+            "Artist A/First Album/two.flac"
+        ]
+    );
+    assert_eq!(
+        client.command("lsinfo nowhere"),
+        ["ACK [50@0] {lsinfo} No such directory"]
+    );
+
+    assert_eq!(
+        client.values("search title o", "Title"),
+        ["One", "Two", "Loose"]
+    );
+    assert_eq!(
+        client.values("search any \"artist b\"", "file"),
+        ["Artist B/three.flac", "loose.mp3"]
+    );
+    assert_eq!(
+        client.values("find title one", "file"),
+        Vec::<String>::new()
+    );
+    assert_eq!(client.values("find title One", "file").len(), 1);
+    assert_eq!(
+        client.values("list album artist \"Artist A\"", "Album"),
+        ["First Album"]
+    );
+    assert_eq!(
+        client.command("search mood happy"),
+        ["ACK [2@0] {search} Unknown tag type \"mood\""]
+    );
+}
+
+#[test]
+fn controlling_playback() {
+    let (server, backend) = start();
+    let mut client = Client::connect(&server);
+
+    assert_eq!(client.command("add \"Artist A\""), ["OK"]);
+    assert_eq!(
+        client.values("playlistinfo", "file"),
+        [
+            "Artist A/First Album/one.flac",
+            "Artist A/First Album/two.flac"
+        ]
+    );
+    assert_eq!(client.values("playlistinfo 1", "Pos"), ["1"]);
+    assert_eq!(client.values("currentsong", "Title"), ["One"]);
+
+    for command in [
+        "pause 1",
+        "pause",
+        "next",
+        "setvol 50",
+        "play 0",
+        "seekcur 30",
+    ] {
+        assert_eq!(client.command(command), ["OK"], "{command}");
+    }
+    assert_eq!(client.values("status", "volume"), ["50"]);
+    assert_eq!(
+        client.command("play 5"),
+        ["ACK [2@0] {play} Bad song index"]
+    );
+    assert_eq!(
+        client.command(&format!("playlistinfo {}", usize::MAX)),
+        ["ACK [2@0] {playlistinfo} Bad song index"]
+    );
+    assert_eq!(
+        client.command("add missing.flac"),
+        ["ACK [50@0] {add} No such song"]
+    );
+    assert_eq!(
+        client.command("bogus"),
+        ["ACK [5@0] {bogus} unknown command \"bogus\""]
+    );
+
+    assert_eq!(
+        *backend.requests.lock().unwrap(),
+        [
+            Request::Add(vec![
+                "Artist A/First Album/one.flac".to_owned(),
+                "Artist A/First Album/two.flac".to_owned(),
+            ]),
+            Request::Pause(Some(true)),
+            Request::Pause(None),
+            Request::Next,
+            Request::SetVolume(50),
+            Request::Play(Some(0)),
+            Request::Seek(Duration::from_secs(30)),
+        ]
+    );
+}
+
+#[test]
+fn command_lists() {
+    let (server, _) = start();
+    let mut client = Client::connect(&server);
+
+    client.send("command_list_ok_begin\nping\nstatus\ncommand_list_end");
+    let reply = client.read_reply();
+    assert_eq!(reply.iter().filter(|l| *l == "list_OK").count(), 2);
+    assert_eq!(reply.last().unwrap(), "OK");
+
+    client.send("command_list_begin\nping\nbogus\nping\ncommand_list_end");
+    assert_eq!(
+        client.read_reply(),
+        ["ACK [5@1] {bogus} unknown command \"bogus\""]
+    );
+}
+
+#[test]
+fn idle_waits_for_changes() {
+    let (server, _) = start();
+    let mut idler = Client::connect(&server);
+    let mut other = Client::connect(&server);
+
+    idler.send("idle playlist");
+    assert_eq!(other.command("setvol 10"), ["OK"]);
+    assert_eq!(other.command("add loose.mp3"), ["OK"]);
+    // The volume changed first, but only the playlist was asked about
+    assert_eq!(idler.read_reply(), ["changed: playlist", "OK"]);
+
+    // Changes that happen between idles are reported straight away
+    assert_eq!(
+        idler.command("idle"),
+        ["changed: mixer", "changed: player", "OK"]
+    );
+
+    idler.send("idle");
+    idler.send("noidle");
+    assert_eq!(idler.read_reply(), ["OK"]);
+}
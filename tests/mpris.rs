@@ -0,0 +1,188 @@
+//! Runs the MPRIS server on a private bus and drives it like a desktop client would. Needs `dbus-daemon`, and is
+//! skipped without it.
+#![cfg(feature = "mpris")]
+mod common;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use minim::mpris::{Controls, Request, Server, Status, TrackInfo};
+use zbus::blocking::{Connection, MessageIterator, Proxy};
+use zbus::zvariant::{ObjectPath, OwnedValue};
+use zbus::MatchRule;
+
+use common::Bus;
+
+const NAME: &str = "org.mpris.MediaPlayer2.minim";
+const PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER: &str = "org.mpris.MediaPlayer2.Player";
+
+/// Plays a made up track and records what it's asked to do
+#[derive(Clone, Default)]
+struct FakeControls {
+    requests: Arc<Mutex<Vec<Request>>>,
+    status: Arc<Mutex<Status>>,
+    track: Arc<Mutex<Option<TrackInfo>>>,
+}
+
+impl Controls for FakeControls {
+    fn status(&self) -> Status {
+        self.status.lock().unwrap().clone()
+    }
+
+    fn track(&self) -> Option<TrackInfo> {
+        self.track.lock().unwrap().clone()
+    }
+
+    fn request(&self, request: Request) {
+        self.requests.lock().unwrap().push(request);
+    }
+}
+
+fn track(index: usize, title: &str) -> TrackInfo {
+    TrackInfo {
+        index,
+        title: title.to_owned(),
+        artist: "Artist".to_owned(),
+        album: "Album".to_owned(),
+        length: Duration::from_secs(200),
+        art: None,
+    }
+}
+
+fn start() -> Option<(Bus, FakeControls, Server, Connection)> {
+    let Some(bus) = Bus::start() else {
+        eprintln!("Skipping, dbus-daemon isn't available");
+        return None;
+    };
+    let controls = FakeControls::default();
+    *controls.track.lock().unwrap() = Some(track(2, "Song"));
+    *controls.status.lock().unwrap() = Status {
+        loaded: true,
+        paused: false,
+        position: Duration::from_secs(10),
+        volume: 0.5,
+    };
+    let server = Server::start_at(&bus.address, controls.clone()).unwrap();
+    let client = zbus::blocking::connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .build()
+        .unwrap();
+    Some((bus, controls, server, client))
+}
+
+fn player(client: &Connection) -> Proxy<'_> {
+    Proxy::new(client, NAME, PATH, PLAYER).unwrap()
+}
+
+#[test]
+fn method_calls_reach_the_player() {
+    let Some((_bus, controls, _server, client)) = start() else {
+        return;
+    };
+    let player = player(&client);
+
+    player.call_method("PlayPause", &()).unwrap();
+    player.call_method("Next", &()).unwrap();
+    player.call_method("Previous", &()).unwrap();
+    player.call_method("Seek", &(-5_000_000i64)).unwrap();
+    let track_id = ObjectPath::try_from("/org/minim/queue/2").unwrap();
+    player
+        .call_method("SetPosition", &(&track_id, 30_000_000i64))
+        .unwrap();
+    // A stale track id is ignored
+    let stale = ObjectPath::try_from("/org/minim/queue/1").unwrap();
+    player
+        .call_method("SetPosition", &(&stale, 30_000_000i64))
+        .unwrap();
+    player.set_property("Volume", 0.25).unwrap();
+
+    assert_eq!(
+        *controls.requests.lock().unwrap(),
+        [
+            Request::PlayPause,
+            Request::Next,
+            Request::Previous,
+            Request::Seek(-5_000_000),
+            Request::SetPosition(Duration::from_secs(30)),
+            Request::SetVolume(0.25),
+        ]
+    );
+}
+
+#[test]
+fn properties_describe_the_current_track() {
+    let Some((_bus, _controls, _server, client)) = start() else {
+        return;
+    };
+    let player = player(&client);
+
+    let status: String = player.get_property("PlaybackStatus").unwrap();
+    assert_eq!(status, "Playing");
+    let position: i64 = player.get_property("Position").unwrap();
+    assert_eq!(position, 10_000_000);
+
+    let metadata: HashMap<String, OwnedValue> = player.get_property("Metadata").unwrap();
+    let title: String = metadata["xesam:title"]
+        .try_clone()
+        .unwrap()
+        .try_into()
+        .unwrap();
+    assert_eq!(title, "Song");
+    let artists: Vec<String> = metadata["xesam:artist"]
+        .try_clone()
+        .unwrap()
+        .try_into()
+        .unwrap();
+    assert_eq!(artists, ["Artist"]);
+    let length: i64 = metadata["mpris:length"]
+        .try_clone()
+        .unwrap()
+        .try_into()
+        .unwrap();
+    assert_eq!(length, 200_000_000);
+    let id: ObjectPath = metadata["mpris:trackid"]
+        .try_clone()
+        .unwrap()
+        .try_into()
+        .unwrap();
+    assert_eq!(id.as_str(), "/org/minim/queue/2");
+
+    let root = Proxy::new(&client, NAME, PATH, "org.mpris.MediaPlayer2").unwrap();
+    let identity: String = root.get_property("Identity").unwrap();
+    assert_eq!(identity, "minim");
+}
+
+#[test]
+fn track_changes_are_signalled() {
+    let Some((_bus, controls, server, client)) = start() else {
+        return;
+    };
+    let rule = MatchRule::builder()
+        .msg_type(zbus::message::Type::Signal)
+        .interface("org.freedesktop.DBus.Properties")
+        .unwrap()
+        .member("PropertiesChanged")
+        .unwrap()
+        .path(PATH)
+        .unwrap()
+        .build();
+    let mut signals = MessageIterator::for_match_rule(rule, &client, None).unwrap();
+
+    *controls.track.lock().unwrap() = Some(track(3, "Next song"));
+    server.changed().unwrap();
+
+    let message = signals.next().unwrap().unwrap();
+    let (interface, changed, _): (String, HashMap<String, OwnedValue>, Vec<String>) =
+        message.body().deserialize().unwrap();
+    assert_eq!(interface, PLAYER);
+    let metadata: HashMap<String, OwnedValue> =
+        changed["Metadata"].try_clone().unwrap().try_into().unwrap();
+    let title: String = metadata["xesam:title"]
+        .try_clone()
+        .unwrap()
+        .try_into()
+        .unwrap();
+    assert_eq!(title, "Next song");
+}
@@ -0,0 +1,147 @@
+//! Shows notifications through a stub daemon on a private bus. Needs `dbus-daemon`, and is skipped without it.
+#![cfg(feature = "notifications")]
+mod common;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
+
+use minim::notifications::{Notification, Notifier};
+use minim::Playback;
+use zbus::blocking::{connection, Connection};
+use zbus::zvariant::OwnedValue;
+
+use common::{engine, Bus, Scratch};
+
+/// A notification as the daemon got it
+#[derive(Debug)]
+struct Shown {
+    replaces_id: u32,
+    summary: String,
+    body: String,
+    image: Option<String>,
+}
+
+/// Hands out ids the way a real notification daemon does, and passes on what it's asked to show
+struct StubDaemon {
+    markup: bool,
+    last_id: u32,
+    shown: Sender<Shown>,
+}
+
+#[zbus::interface(name = "org.freedesktop.Notifications")]
+impl StubDaemon {
+    fn get_capabilities(&self) -> Vec<String> {
+        let mut capabilities = vec!["body".to_owned()];
+        if self.markup {
+            capabilities.push("body-markup".to_owned());
+        }
+        capabilities
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &mut self,
+        _app_name: String,
+        replaces_id: u32,
+        _app_icon: String,
+        summary: String,
+        body: String,
+        _actions: Vec<String>,
+        hints: HashMap<String, OwnedValue>,
+        _expire_timeout: i32,
+    ) -> u32 {
+        let id = if replaces_id == 0 {
+            self.last_id += 1;
+            self.last_id
+        } else {
+            replaces_id
+        };
+        let image = hints
+            .get("image-path")
+            .map(|path| String::try_from(path.clone()).unwrap());
+        let _ = self.shown.send(Shown {
+            replaces_id,
+            summary,
+            body,
+            image,
+        });
+        id
+    }
+}
+
+/// A private bus with a stub daemon on it, and what it's been asked to show
+fn start(markup: bool) -> Option<(Bus, Connection, Receiver<Shown>)> {
+    let Some(bus) = Bus::start() else {
+        eprintln!("Skipping, dbus-daemon isn't available");
+        return None;
+    };
+    let (sender, shown) = mpsc::channel();
+    let daemon = StubDaemon {
+        markup,
+        last_id: 0,
+        shown: sender,
+    };
+    let connection = connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .serve_at("/org/freedesktop/Notifications", daemon)
+        .unwrap()
+        .name("org.freedesktop.Notifications")
+        .unwrap()
+        .build()
+        .unwrap();
+    Some((bus, connection, shown))
+}
+
+fn next(shown: &Receiver<Shown>) -> Shown {
+    shown
+        .recv_timeout(Duration::from_secs(5))
+        .expect("A notification should have been shown by now")
+}
+
+#[test]
+fn each_track_replaces_the_last_notification() {
+    let Some((bus, _daemon, shown)) = start(false) else {
+        return;
+    };
+    let scratch = Scratch::new("notifications");
+    let engine = engine();
+    Notifier::connect_at(&bus.address)
+        .unwrap()
+        .follow(engine.subscribe());
+
+    engine.enqueue(&scratch.track("One")).unwrap();
+    engine.enqueue(&scratch.track("Two")).unwrap();
+    let first = next(&shown);
+    assert_eq!(first.replaces_id, 0);
+    assert_eq!(first.summary, "One");
+    assert_eq!(first.body, "Someone");
+
+    engine.next();
+    let second = next(&shown);
+    assert_eq!(second.replaces_id, 1);
+    assert_eq!(second.summary, "Two");
+    assert!(shown.recv_timeout(Duration::from_millis(200)).is_err());
+}
+
+#[test]
+fn markup_is_escaped_for_daemons_that_read_it() {
+    let Some((bus, _daemon, shown)) = start(true) else {
+        return;
+    };
+    let mut notifier = Notifier::connect_at(&bus.address).unwrap();
+
+    notifier
+        .show(&Notification {
+            title: "Rock & Roll".to_owned(),
+            artist: "Them & Us".to_owned(),
+            album: "<Live>".to_owned(),
+            art: Some(PathBuf::from("/covers/live.png")),
+        })
+        .unwrap();
+    let shown = next(&shown);
+    assert_eq!(shown.summary, "Rock & Roll");
+    assert_eq!(shown.body, "Them &amp; Us — &lt;Live&gt;");
+    assert_eq!(shown.image.as_deref(), Some("/covers/live.png"));
+}
@@ -0,0 +1,145 @@
+//! Plays made up files into a WAV file and nowhere
+mod common;
+
+use std::fs;
+use std::sync::mpsc::Receiver;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use minim::output::{Null, Pace, WavFile};
+use minim::{Engine, Event, Playback};
+
+use common::{Scratch, SAMPLE_RATE};
+
+/// A tenth of a second of a ramp that never touches zero, so where it starts and ends can be told from silence
+fn ramp(from: i16) -> Vec<i16> {
+    (0..SAMPLE_RATE as i16 / 10).map(|i| from + i).collect()
+}
+
+/// Waits for `count` tracks to end, returning (started, index) for the tracks starting and ending on the way,
+/// along with how much of each ended track was heard
+fn wait_for_tracks(events: &Receiver<Event>, count: usize) -> (Vec<(bool, usize)>, Vec<Duration>) {
+    let mut seen = Vec::new();
+    let mut heard = Vec::new();
+    while heard.len() < count {
+        let event = events
+            .recv_timeout(Duration::from_secs(5))
+            .expect("The tracks should have played by now");
+        match event {
+            Event::TrackStarted { index, .. } => seen.push((true, index)),
+            Event::TrackEnded {
+                index, heard: h, ..
+            } => {
+                seen.push((false, index));
+                heard.push(h);
+            }
+            _ => {}
+        }
+    }
+    (seen, heard)
+}
+
+/// The 16-bit samples after a WAV file's 44 byte header
+fn pcm(bytes: &[u8]) -> Vec<i16> {
+    assert_eq!(&bytes[..4], b"RIFF");
+    let length = u32::from_le_bytes(bytes[40..44].try_into().unwrap()) as usize;
+    assert_eq!(length, bytes.len() - 44);
+    bytes[44..]
+        .chunks_exact(2)
+        .map(|s| i16::from_le_bytes([s[0], s[1]]))
+        .collect()
+}
+
+#[test]
+fn queued_tracks_are_written_one_after_another() {
+    let scratch = Scratch::new("wav");
+    let ramps = [ramp(1000), ramp(5000), ramp(-9000)];
+    let (engine, samples) = Engine::idle();
+    engine.pause();
+    let events = engine.subscribe();
+    for (title, ramp) in ["One", "Two", "Three"].iter().zip(&ramps) {
+        engine.enqueue(&scratch.track_of(title, ramp)).unwrap();
+    }
+
+    let path = scratch.0.join("out.wav");
+    let output = WavFile::create(
+        samples,
+        &path,
+        1,
+        SAMPLE_RATE,
+        Pace::Unlimited(engine.busy()),
+    )
+    .unwrap();
+    engine.play();
+    let (seen, heard) = wait_for_tracks(&events, 3);
+    // The writer reads a little ahead of what the engine says has played
+    thread::sleep(Duration::from_millis(100));
+    output.finish().unwrap();
+
+    assert_eq!(
+        seen,
+        [
+            (true, 0),
+            (false, 0),
+            (true, 1),
+            (false, 1),
+            (true, 2),
+            (false, 2)
+        ]
+    );
+    assert_eq!(heard, [Duration::from_millis(100); 3]);
+
+    // Silence while paused before, and after the queue ran out
+    let written = pcm(&fs::read(&path).unwrap());
+    let start = written.iter().position(|s| *s != 0).unwrap();
+    let end = written.iter().rposition(|s| *s != 0).unwrap() + 1;
+    assert!(written[..start]
+        .iter()
+        .chain(&written[end..])
+        .all(|s| *s == 0));
+    assert_eq!(written[start..end], ramps.concat());
+    // Once the queue ran out, the writer waited rather than filling the file with silence. It only got as far as
+    // reading ahead a little.
+    assert!(written.len() - end <= SAMPLE_RATE as usize / 100);
+}
+
+#[test]
+fn nowhere_takes_as_long_as_the_tracks_last() {
+    let scratch = Scratch::new("null");
+    let (engine, samples) = Engine::idle();
+    engine.pause();
+    let events = engine.subscribe();
+    for title in ["One", "Two", "Three"] {
+        engine.enqueue(&scratch.track(title)).unwrap();
+    }
+
+    let _output = Null::start(samples, Pace::RealTime);
+    let started = Instant::now();
+    engine.play();
+    wait_for_tracks(&events, 3);
+    // Three tenths of a second, give or take a chunk
+    assert!(started.elapsed() >= Duration::from_millis(280));
+}
+
+#[test]
+fn fast_output_picks_up_tracks_queued_while_it_waits() {
+    let scratch = Scratch::new("idle");
+    let (engine, samples) = Engine::idle();
+    let events = engine.subscribe();
+    let _output = Null::start(samples, Pace::Unlimited(engine.busy()));
+
+    // Nothing to play yet, then a track, then nothing again, then another
+    thread::sleep(Duration::from_millis(50));
+    engine
+        .enqueue(&scratch.track_lasting("One", Duration::from_secs(10)))
+        .unwrap();
+    wait_for_tracks(&events, 1);
+    thread::sleep(Duration::from_millis(50));
+    assert!(!engine.busy().is_busy());
+    engine
+        .enqueue(&scratch.track_lasting("Two", Duration::from_secs(10)))
+        .unwrap();
+    let (seen, heard) = wait_for_tracks(&events, 1);
+    assert_eq!(seen, [(true, 1), (false, 1)]);
+    assert_eq!(heard, [Duration::from_secs(10)]);
+}
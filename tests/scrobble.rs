@@ -0,0 +1,301 @@
+//! Submits listens to a made up service over localhost
+mod common;
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use minim::scrobble::{should_scrobble, LastFm, Listen, ListenBrainz, Scrobbler};
+use minim::Playback;
+
+use common::{engine, Scratch};
+
+/// A request as the server saw it
+struct Request {
+    path: String,
+    headers: HashMap<String, String>,
+    body: String,
+}
+
+impl Request {
+    fn json(&self) -> serde_json::Value {
+        serde_json::from_str(&self.body).unwrap()
+    }
+
+    fn form(&self) -> HashMap<String, String> {
+        form_urlencoded(&self.body)
+    }
+}
+
+/// An HTTP server that answers each request with the next of its replies, or 200 once it runs out
+struct Server {
+    url: String,
+    replies: Arc<Mutex<VecDeque<(u16, String)>>>,
+    requests: Receiver<Request>,
+}
+
+impl Server {
+    fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let replies = Arc::new(Mutex::new(VecDeque::new()));
+        let (sender, requests) = mpsc::channel();
+
+        let queued = replies.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let path = line.split(' ').nth(1).unwrap().to_owned();
+                let mut headers = HashMap::new();
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    let Some((name, value)) = line.trim_end().split_once(": ") else {
+                        break;
+                    };
+                    headers.insert(name.to_lowercase(), value.to_owned());
+                }
+                let length = headers
+                    .get("content-length")
+                    .map_or(0, |l| l.parse().unwrap());
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let (status, reply) = queued
+                    .lock()
+                    .unwrap()
+                    .pop_front()
+                    .unwrap_or((200, "{}".to_owned()));
+                write!(
+                    stream,
+                    "HTTP/1.1 {status} Whatever\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{reply}",
+                    reply.len()
+                )
+                .unwrap();
+
+                let request = Request {
+                    path,
+                    headers,
+                    body: String::from_utf8(body).unwrap(),
+                };
+                if sender.send(request).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Self {
+            url,
+            replies,
+            requests,
+        }
+    }
+
+    fn reply(&self, status: u16, body: &str) {
+        self.replies
+            .lock()
+            .unwrap()
+            .push_back((status, body.to_owned()));
+    }
+
+    fn request(&self) -> Request {
+        self.requests
+            .recv_timeout(Duration::from_secs(5))
+            .expect("A request should have arrived by now")
+    }
+
+    fn nothing_more(&self) {
+        assert!(self
+            .requests
+            .recv_timeout(Duration::from_millis(200))
+            .is_err());
+    }
+}
+
+fn form_urlencoded(body: &str) -> HashMap<String, String> {
+    fn decode(s: &str) -> String {
+        let bytes = s.replace('+', " ").into_bytes();
+        let mut decoded = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b'%' {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap();
+                decoded.push(u8::from_str_radix(hex, 16).unwrap());
+                i += 3;
+            } else {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+        String::from_utf8(decoded).unwrap()
+    }
+
+    body.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (decode(k), decode(v)))
+        .collect()
+}
+
+fn listen(title: &str, listened_at: u64) -> Listen {
+    Listen {
+        artist: "Someone".to_owned(),
+        title: title.to_owned(),
+        album: Some("Something".to_owned()),
+        duration: 200,
+        listened_at,
+    }
+}
+
+#[test]
+fn only_tracks_mostly_heard_count() {
+    let secs = Duration::from_secs;
+    assert!(should_scrobble(secs(200), secs(100)));
+    assert!(!should_scrobble(secs(200), secs(99)));
+    assert!(should_scrobble(secs(3600), secs(240)));
+    assert!(!should_scrobble(secs(3600), secs(239)));
+    assert!(!should_scrobble(secs(30), secs(30)));
+}
+
+#[test]
+fn listenbrainz_gets_listens_with_the_token() {
+    let scratch = Scratch::new("listenbrainz");
+    let server = Server::start();
+    let scrobbler =
+        Scrobbler::new(&scratch.0).with_backend(ListenBrainz::new("secret").with_url(&server.url));
+
+    scrobbler.scrobble(&listen("One", 1000)).unwrap();
+    let request = server.request();
+    assert_eq!(request.path, "/1/submit-listens");
+    assert_eq!(request.headers["authorization"], "Token secret");
+    let body = request.json();
+    assert_eq!(body["listen_type"], "single");
+    let listen = &body["payload"][0];
+    assert_eq!(listen["listened_at"], 1000);
+    assert_eq!(listen["track_metadata"]["artist_name"], "Someone");
+    assert_eq!(listen["track_metadata"]["track_name"], "One");
+    assert_eq!(listen["track_metadata"]["release_name"], "Something");
+    assert_eq!(listen["track_metadata"]["additional_info"]["duration"], 200);
+}
+
+#[test]
+fn lastfm_gets_signed_scrobbles() {
+    let scratch = Scratch::new("lastfm");
+    let server = Server::start();
+    let scrobbler = Scrobbler::new(&scratch.0)
+        .with_backend(LastFm::new("key", "shh", "session").with_url(&server.url));
+
+    scrobbler.scrobble(&listen("One", 1000)).unwrap();
+    let mut form = server.request().form();
+    assert_eq!(form["method"], "track.scrobble");
+    assert_eq!(form["artist[0]"], "Someone");
+    assert_eq!(form["track[0]"], "One");
+    assert_eq!(form["album[0]"], "Something");
+    assert_eq!(form["timestamp[0]"], "1000");
+    assert_eq!(form["sk"], "session");
+    assert_eq!(form["format"], "json");
+
+    // Every parameter but the format, sorted, then the secret
+    let signature = form.remove("api_sig").unwrap();
+    form.remove("format");
+    let mut params: Vec<_> = form.into_iter().collect();
+    params.sort();
+    let mut signed: String = params.into_iter().map(|(k, v)| k + &v).collect();
+    signed.push_str("shh");
+    assert_eq!(signature, format!("{:x}", md5::compute(signed)));
+}
+
+#[test]
+fn listens_wait_on_disk_while_the_service_is_down() {
+    let scratch = Scratch::new("offline");
+    let server = Server::start();
+    let queue = scratch.0.join("listenbrainz.jsonl");
+    let scrobbler = || {
+        Scrobbler::new(&scratch.0).with_backend(ListenBrainz::new("secret").with_url(&server.url))
+    };
+
+    server.reply(503, "{}");
+    let e = scrobbler().scrobble(&listen("One", 1000)).unwrap_err();
+    assert!(e.to_string().contains("trying again later"));
+    server.request();
+    assert!(queue.exists());
+
+    // It's still there for the next run, and goes in with the next listen
+    server.reply(503, "{}");
+    scrobbler().flush().unwrap_err();
+    server.request();
+    scrobbler().scrobble(&listen("Two", 2000)).unwrap();
+    let body = server.request().json();
+    assert_eq!(body["listen_type"], "import");
+    assert_eq!(body["payload"][0]["track_metadata"]["track_name"], "One");
+    assert_eq!(body["payload"][1]["track_metadata"]["track_name"], "Two");
+    assert!(!queue.exists());
+}
+
+#[test]
+fn listens_turned_down_are_dropped() {
+    let scratch = Scratch::new("rejected");
+    let server = Server::start();
+    let scrobbler =
+        Scrobbler::new(&scratch.0).with_backend(ListenBrainz::new("secret").with_url(&server.url));
+
+    server.reply(400, r#"{"code": 400, "error": "That's no listen"}"#);
+    let e = scrobbler.scrobble(&listen("One", 1000)).unwrap_err();
+    assert!(e.to_string().contains("That's no listen"));
+    server.request();
+
+    scrobbler.flush().unwrap();
+    server.nothing_more();
+    assert!(!scratch.0.join("listenbrainz.jsonl").exists());
+}
+
+#[test]
+fn tracks_played_through_are_scrobbled() {
+    let scratch = Scratch::new("following");
+    let server = Server::start();
+    let engine = engine();
+    let (errors, reported) = mpsc::channel();
+    Scrobbler::new(scratch.0.join("queue"))
+        .with_backend(ListenBrainz::new("secret").with_url(&server.url))
+        .follow(engine.subscribe(), move |e| {
+            let _ = errors.send(e);
+        });
+
+    // Too short to count, then long enough
+    let queued_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    engine.enqueue(&scratch.track("Short")).unwrap();
+    engine
+        .enqueue(&scratch.track_lasting("Long", Duration::from_secs(40)))
+        .unwrap();
+    engine.play();
+
+    let body = server.request().json();
+    let listen = &body["payload"][0]["track_metadata"];
+    assert_eq!(listen["track_name"], "Long");
+    assert_eq!(listen["artist_name"], "Someone");
+    assert_eq!(listen["additional_info"]["duration"], 40);
+    // It plays faster than real time here, so working it out from the time heard would put it before it was queued
+    assert!(body["payload"][0]["listened_at"].as_u64().unwrap() >= queued_at);
+    server.nothing_more();
+    assert!(reported.try_recv().is_err());
+}
+
+#[test]
+fn credentials_stay_out_of_debug_output() {
+    let listenbrainz = format!("{:?}", ListenBrainz::new("the-token"));
+    assert!(!listenbrainz.contains("the-token"), "{listenbrainz}");
+
+    let lastfm = format!("{:?}", LastFm::new("key", "the-secret", "the-session"));
+    assert!(!lastfm.contains("the-secret"), "{lastfm}");
+    assert!(!lastfm.contains("the-session"), "{lastfm}");
+}